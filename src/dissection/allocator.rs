@@ -0,0 +1,313 @@
+//! Allocation-detecting allocator.
+//!
+//! # Overview
+//!
+//! The render thread must never allocate memory: an allocation could take a lock inside the system
+//! allocator and stall the output stream. [`CountingAllocator`] wraps the system allocator and counts
+//! allocations made by the current thread inside [`count_allocations`] scopes, so it's possible to check
+//! that a piece of code is allocation-free. The allocator does nothing useful unless it is installed as the
+//! global allocator, the crate does this for its own tests.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use audio::dissection::allocator::{assert_no_allocations, CountingAllocator};
+//! use audio::dissection::engine::SoundContext;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator;
+//!
+//! let mut context = SoundContext::new();
+//! let mut block = vec![(0.0, 0.0); 1024];
+//! // Warm up internal buffers first.
+//! context.render(&mut block);
+//! assert_no_allocations(|| context.render(&mut block));
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    // Amount of allocations made by the thread, `None` if allocations aren't counted right now.
+    static ALLOCATIONS: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn record_allocation() {
+    // The thread local could be already destroyed if the thread is exiting.
+    let _ = ALLOCATIONS.try_with(|allocations| {
+        if let Some(count) = allocations.get() {
+            allocations.set(Some(count + 1));
+        }
+    });
+}
+
+/// Global allocator that counts allocations made inside [`count_allocations`] scopes. See module docs.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record_allocation();
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Calls the given closure and returns its result with the amount of allocations (and deallocations) made
+/// by the current thread while it was running. The amount is always zero if [`CountingAllocator`] isn't the
+/// global allocator.
+pub fn count_allocations<F, R>(func: F) -> (R, usize)
+where
+    F: FnOnce() -> R,
+{
+    let previous = ALLOCATIONS.with(|allocations| allocations.replace(Some(0)));
+    let result = func();
+    let count = ALLOCATIONS.with(|allocations| {
+        let count = allocations.get().unwrap_or_default();
+        // Nested scopes are counted by the outer scope too.
+        allocations.set(previous.map(|previous| previous + count));
+        count
+    });
+    (result, count)
+}
+
+/// Calls the given closure and panics if it allocated or freed memory. See [`count_allocations`].
+pub fn assert_no_allocations<F, R>(func: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let (result, count) = count_allocations(func);
+    assert_eq!(
+        count, 0,
+        "{count} allocations were made in a real-time scope!"
+    );
+    result
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        allocator::{assert_no_allocations, count_allocations},
+        ambisonics::{AmbisonicDecoder, SpeakerLayout},
+        buffer::{Buffer, SharedBuffer},
+        bus::{AudioBus, AudioBusGraphMode, AuxSend, SendTap},
+        effects::{Attenuate, Effect},
+        engine::{SharedSoundContext, SoundContext},
+        group::SoundGroup,
+        source::{SoundSource, SourceParameter, Status},
+    };
+    use glam::Vec3;
+
+    #[test]
+    fn test_count_allocations() {
+        let (_, count) = count_allocations(|| vec![1.0f32; 16]);
+        assert!(count > 0);
+        let (_, count) = count_allocations(|| 2 + 2);
+        assert_eq!(count, 0);
+    }
+
+    fn make_context() -> SoundContext {
+        let mut context = SoundContext::new();
+        let graph = context.bus_graph_mut();
+        let mut bus = AudioBus::new("Effects".to_string());
+        bus.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
+        let effects = graph.add_bus(bus, graph.primary_bus_handle()).unwrap();
+        let mut group = SoundGroup::new("Group");
+        group.set_gain(0.8).set_pitch(1.1).set_max_instances(1);
+        let group = context.add_group(group);
+
+        let mut mono = SoundSource::default();
+        mono.set_buffer(Some(Buffer::new(vec![0.25; 1000], true).into()));
+        mono.looping = true;
+        mono.status = Status::Playing;
+        mono.set_bus("Effects");
+        mono.set_pitch(0.7);
+        mono.spatial_blend = 1.0;
+        mono.set_position(Vec3::new(3.0, 0.0, 1.0));
+        mono.set_air_absorption(1.0);
+        mono.set_group(group);
+        context.add_source(mono);
+
+        let mut stereo = SoundSource::default();
+        stereo.set_buffer(Some(Buffer::new(vec![0.5; 3000], false).into()));
+        stereo.looping = true;
+        stereo.status = Status::Playing;
+        stereo.add_send(AuxSend::new(effects, 0.5, SendTap::PostFader));
+        stereo
+            .automation_mut(SourceParameter::Gain)
+            .linear_ramp_to(0.1, 0, 100_000);
+        stereo
+            .automation_mut(SourceParameter::Pitch)
+            .exponential_ramp_to(2.0, 0, 100_000);
+        context.add_source(stereo);
+
+        let mut stretched = SoundSource::default();
+        stretched.set_buffer(Some(Buffer::new(vec![0.25; 3000], true).into()));
+        stretched.looping = true;
+        stretched.status = Status::Playing;
+        stretched
+            .set_time_stretch(true)
+            .set_tempo(1.5)
+            .set_pitch(0.8);
+        context.add_source(stretched);
+
+        context
+    }
+
+    #[test]
+    fn test_steady_state_render_does_not_allocate() {
+        let mut context = make_context();
+        let mut block = vec![(0.0, 0.0); 512];
+        context.render(&mut block);
+        for _ in 0..10 {
+            assert_no_allocations(|| context.render(&mut block));
+        }
+
+        // Smaller blocks reuse already allocated buffers.
+        assert_no_allocations(|| context.render(&mut block[..100]));
+    }
+
+    #[test]
+    fn test_virtual_voices_do_not_allocate() {
+        let mut context = make_context();
+        context.set_max_voices(1);
+        let mut block = vec![(0.0, 0.0); 512];
+        context.render(&mut block);
+        for _ in 0..10 {
+            assert_no_allocations(|| context.render(&mut block));
+        }
+    }
+
+    #[test]
+    fn test_queued_buffers_do_not_allocate() {
+        let context = SharedSoundContext::new();
+        let mut source = SoundSource::default();
+        source.set_queue_crossfade(100);
+        for _ in 0..8 {
+            source.enqueue(SharedBuffer::new(Buffer::new(vec![0.5; 700], true)));
+        }
+        source.status = Status::Playing;
+        let source = context.add_source(source);
+
+        // Pretend to be the render thread, finished buffers are freed by the control thread.
+        let mut render_context = context.take_detached().unwrap();
+        let mut block = vec![(0.0, 0.0); 512];
+        render_context.render(&mut block);
+        for _ in 0..10 {
+            assert_no_allocations(|| render_context.render(&mut block));
+        }
+        assert_eq!(render_context.source(source).status, Status::Stopped);
+        render_context.return_home();
+    }
+
+    #[test]
+    fn test_uncollected_buffers_do_not_allocate() {
+        let context = SharedSoundContext::new();
+        let mut template = SoundSource::default();
+        template.set_queue_crossfade(10);
+        // More buffers than the garbage queue and its overflow could take.
+        for _ in 0..3000 {
+            template.enqueue(SharedBuffer::new(Buffer::new(vec![0.5; 64], true)));
+        }
+        template.status = Status::Playing;
+        // Copies of a source don't keep the room reserved for finished buffers.
+        let source = context.add_source(template.clone());
+
+        // No one collects garbage, so finished buffers pile up in the source.
+        let mut render_context = context.take_detached().unwrap();
+        let mut block = vec![(0.0, 0.0); 512];
+        render_context.render(&mut block);
+        for _ in 0..400 {
+            assert_no_allocations(|| render_context.render(&mut block));
+        }
+        assert_eq!(render_context.source(source).status, Status::Stopped);
+        render_context.return_home();
+    }
+
+    #[test]
+    fn test_ambisonic_render_does_not_allocate() {
+        let mut context = make_context();
+        context
+            .bus_graph_mut()
+            .set_mode(AudioBusGraphMode::Ambisonic(AmbisonicDecoder::new(
+                SpeakerLayout::Quad,
+            )));
+        let mut block = vec![(0.0, 0.0); 512];
+        context.render(&mut block);
+        for _ in 0..10 {
+            assert_no_allocations(|| context.render(&mut block));
+        }
+    }
+
+    #[test]
+    fn test_routing_changes_do_not_allocate() {
+        let mut context = SoundContext::new();
+        let graph = context.bus_graph_mut();
+        let root = graph.primary_bus_handle();
+        let a = graph.add_bus(AudioBus::new("A".to_string()), root).unwrap();
+        let b = graph.add_bus(AudioBus::new("B".to_string()), root).unwrap();
+        let mut reroute = || {
+            graph.link_buses(b, a).unwrap();
+            graph
+                .add_send(a, AuxSend::new(root, 0.5, SendTap::PreFader))
+                .unwrap();
+            graph.link_buses(b, root).unwrap();
+            graph.remove_send(a, 0).unwrap();
+        };
+        // Warm up.
+        reroute();
+        assert_no_allocations(reroute);
+    }
+
+    #[test]
+    fn test_commands_do_not_allocate_on_render_thread() {
+        let context = SharedSoundContext::new();
+        let source = context.add_source(SoundSource::default());
+        let mut one_shot = SoundSource::default();
+        one_shot.set_buffer(Some(Buffer::new(vec![0.5; 100], true).into()));
+        one_shot.play_once = true;
+        one_shot.status = Status::Playing;
+        let one_shot = context.add_source(one_shot);
+
+        // Pretend to be the render thread.
+        let mut render_context = context.take_detached().unwrap();
+        let mut block = vec![(0.0, 0.0); 512];
+        render_context.render(&mut block);
+
+        context.edit_source(source, |source| {
+            source.set_pitch(2.0);
+        });
+        context.remove_source(source);
+        // The source pool gets bigger storage from the control thread instead of growing by itself.
+        let added = (0..40)
+            .map(|_| context.add_source(SoundSource::default()))
+            .collect::<Vec<_>>();
+        // Removed and finished sources are freed by the control thread.
+        assert_no_allocations(|| render_context.render(&mut block));
+        assert!(!render_context.is_valid_handle(source));
+        assert!(!render_context.is_valid_handle(one_shot));
+        assert!(added
+            .iter()
+            .all(|&handle| render_context.is_valid_handle(handle)));
+
+        render_context.return_home();
+        assert!(!context.is_attached());
+    }
+}
@@ -0,0 +1,268 @@
+//! First-order Ambisonics.
+//!
+//! # Overview
+//!
+//! Ambisonics represents a whole sound field around a point (B-format) instead of a set of speaker
+//! feeds. First-order B-format has four channels: `W` - omnidirectional pressure, and `X`, `Y`, `Z` -
+//! figure-of-eight components along three axes. Spatial sources are encoded into B-format by their
+//! direction, the field is then rotated with the listener and decoded to a speaker layout (stereo,
+//! quad or 5.1).
+//!
+//! Encoding uses SN3D normalization and the channel order is `W, X, Y, Z`. The field is stored in
+//! world space, where `X`, `Y` and `Z` are world axes. [`world_to_listener`] creates a rotation that
+//! maps the field into listener space (`X` - front, `Y` - left, `Z` - up) which is expected by
+//! [`AmbisonicDecoder`].
+
+use crate::dissection::listener::Listener;
+use glam::{Mat3, Vec3};
+use std::ops::{Add, AddAssign, Mul};
+
+/// A single frame of first-order B-format signal.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct BFormatSample {
+    /// Omnidirectional component.
+    pub w: f32,
+    /// Figure-of-eight component along X axis.
+    pub x: f32,
+    /// Figure-of-eight component along Y axis.
+    pub y: f32,
+    /// Figure-of-eight component along Z axis.
+    pub z: f32,
+}
+
+impl BFormatSample {
+    /// Encodes a mono sample coming from the given direction. Direction does not need to be normalized,
+    /// zero direction produces omnidirectional signal.
+    pub fn encode(direction: Vec3, sample: f32) -> Self {
+        Self::encoding_gains(direction) * sample
+    }
+
+    /// Returns gains of each B-format channel for a sound coming from the given direction. These are
+    /// the same as encoding of unit sample.
+    pub fn encoding_gains(direction: Vec3) -> Self {
+        let direction = direction.normalize_or_zero();
+        Self {
+            w: 1.0,
+            x: direction.x,
+            y: direction.y,
+            z: direction.z,
+        }
+    }
+
+    /// Rotates the sound field. First order rotation affects only directional components.
+    pub fn rotate(&self, rotation: &Mat3) -> Self {
+        let directional = *rotation * Vec3::new(self.x, self.y, self.z);
+        Self {
+            w: self.w,
+            x: directional.x,
+            y: directional.y,
+            z: directional.z,
+        }
+    }
+
+    /// Linearly interpolates between two B-format samples.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        *self + (*other + *self * -1.0) * t
+    }
+}
+
+impl Add for BFormatSample {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            w: self.w + rhs.w,
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        }
+    }
+}
+
+impl AddAssign for BFormatSample {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Mul<f32> for BFormatSample {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self {
+            w: self.w * rhs,
+            x: self.x * rhs,
+            y: self.y * rhs,
+            z: self.z * rhs,
+        }
+    }
+}
+
+/// Creates a rotation that maps world-space sound field into listener space (`X` - front, `Y` - left,
+/// `Z` - up). Use it with [`BFormatSample::rotate`] before decoding, so the field turns with the listener.
+pub fn world_to_listener(listener: &Listener) -> Mat3 {
+    Mat3::from_cols(
+        listener.look_axis(),
+        -listener.ear_axis(),
+        listener.up_axis(),
+    )
+    .transpose()
+}
+
+/// A set of speakers to decode B-format to.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SpeakerLayout {
+    /// Two speakers at ±30 degrees. Channel order: L, R.
+    #[default]
+    Stereo,
+    /// Four speakers at ±45 and ±135 degrees. Channel order: FL, FR, RL, RR.
+    Quad,
+    /// ITU 5.1 layout: speakers at ±30, 0, ±110 degrees and LFE. Channel order: L, R, C, LFE, Ls, Rs.
+    Surround51,
+}
+
+impl SpeakerLayout {
+    /// Returns amount of output channels of the layout.
+    pub fn channel_count(&self) -> usize {
+        self.speakers().len()
+    }
+
+    // Azimuth of each speaker in degrees (counter-clockwise, zero is front), `None` is LFE.
+    fn speakers(&self) -> &'static [Option<f32>] {
+        match self {
+            SpeakerLayout::Stereo => &[Some(30.0), Some(-30.0)],
+            SpeakerLayout::Quad => &[Some(45.0), Some(-45.0), Some(135.0), Some(-135.0)],
+            SpeakerLayout::Surround51 => &[
+                Some(30.0),
+                Some(-30.0),
+                Some(0.0),
+                None,
+                Some(110.0),
+                Some(-110.0),
+            ],
+        }
+    }
+
+    /// Folds down a frame of this layout to stereo. Could be used to monitor multichannel output on a
+    /// stereo device.
+    pub fn downmix_to_stereo(&self, frame: &[f32]) -> (f32, f32) {
+        const HALF_POWER: f32 = std::f32::consts::FRAC_1_SQRT_2;
+        match self {
+            SpeakerLayout::Stereo => (frame[0], frame[1]),
+            SpeakerLayout::Quad => (frame[0] + frame[2], frame[1] + frame[3]),
+            SpeakerLayout::Surround51 => (
+                frame[0] + HALF_POWER * (frame[2] + frame[4]),
+                frame[1] + HALF_POWER * (frame[2] + frame[5]),
+            ),
+        }
+    }
+}
+
+/// Basic first-order decoder. Each speaker is fed by a virtual cardioid microphone pointing at the
+/// speaker. The LFE channel of 5.1 layout is left silent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmbisonicDecoder {
+    layout: SpeakerLayout,
+    // Decoding gains (w, x, y, z) of each speaker.
+    matrix: Vec<BFormatSample>,
+}
+
+impl Default for AmbisonicDecoder {
+    fn default() -> Self {
+        Self::new(SpeakerLayout::default())
+    }
+}
+
+impl AmbisonicDecoder {
+    /// Creates new decoder for the given speaker layout.
+    pub fn new(layout: SpeakerLayout) -> Self {
+        let speakers = layout.speakers();
+        // Keep total power of the decoded field roughly independent of speaker count.
+        let normalization = (2.0 / speakers.iter().flatten().count() as f32).sqrt();
+        let matrix = speakers
+            .iter()
+            .map(|azimuth| match azimuth {
+                Some(azimuth) => {
+                    let azimuth = azimuth.to_radians();
+                    BFormatSample {
+                        w: 0.5,
+                        x: 0.5 * azimuth.cos(),
+                        y: 0.5 * azimuth.sin(),
+                        z: 0.0,
+                    } * normalization
+                }
+                None => BFormatSample::default(),
+            })
+            .collect();
+        Self { layout, matrix }
+    }
+
+    /// Returns speaker layout of the decoder.
+    pub fn layout(&self) -> SpeakerLayout {
+        self.layout
+    }
+
+    /// Decodes a single B-format frame (in listener space) into speaker feeds. `output` must have at least
+    /// [`SpeakerLayout::channel_count`] elements.
+    pub fn decode(&self, sample: &BFormatSample, output: &mut [f32]) {
+        for (gains, speaker) in self.matrix.iter().zip(output.iter_mut()) {
+            *speaker =
+                gains.w * sample.w + gains.x * sample.x + gains.y * sample.y + gains.z * sample.z;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        ambisonics::{world_to_listener, AmbisonicDecoder, BFormatSample, SpeakerLayout},
+        listener::Listener,
+    };
+    use glam::Vec3;
+
+    #[test]
+    fn test_encode_decode_stereo() {
+        let decoder = AmbisonicDecoder::new(SpeakerLayout::Stereo);
+        let mut listener = Listener::new();
+        let mut output = [0.0; 2];
+
+        // Default listener looks along +Z and its right ear points to +X.
+        let right = BFormatSample::encode(Vec3::new(1.0, 0.0, 0.0), 1.0);
+        decoder.decode(&right.rotate(&world_to_listener(&listener)), &mut output);
+        assert!(output[1] > output[0]);
+
+        let front = BFormatSample::encode(Vec3::new(0.0, 0.0, 1.0), 1.0);
+        decoder.decode(&front.rotate(&world_to_listener(&listener)), &mut output);
+        assert!((output[0] - output[1]).abs() < 1.0e-6);
+
+        // Turn the listener to the right, the sound that was on the right is now in front.
+        listener.set_orientation_lh(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        decoder.decode(&right.rotate(&world_to_listener(&listener)), &mut output);
+        assert!((output[0] - output[1]).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_decode_layouts() {
+        for layout in [
+            SpeakerLayout::Stereo,
+            SpeakerLayout::Quad,
+            SpeakerLayout::Surround51,
+        ] {
+            let decoder = AmbisonicDecoder::new(layout);
+            let mut output = vec![0.0; layout.channel_count()];
+            // Sound from behind the listener, in listener space.
+            decoder.decode(
+                &BFormatSample::encode(Vec3::new(-1.0, 0.0, 0.0), 1.0),
+                &mut output,
+            );
+            match layout {
+                SpeakerLayout::Stereo => assert!(output[0] < 0.1),
+                SpeakerLayout::Quad => assert!(output[2] > output[0]),
+                SpeakerLayout::Surround51 => {
+                    assert!(output[4] > output[2]);
+                    assert_eq!(output[3], 0.0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Sample-accurate parameter automation.
+//!
+//! # Overview
+//!
+//! Setting a parameter directly (for example [`crate::dissection::source::SoundSource::set_gain`]) takes
+//! effect at the next rendered block. [`Automation`] is a timeline of scheduled changes of one parameter:
+//! jumps to a value at a given time and linear or exponential ramps. The timeline is evaluated for every
+//! frame inside [`crate::dissection::engine::SoundContext::render`], so changes happen exactly at the
+//! scheduled frame.
+//!
+//! Time is measured in frames of the context clock, see
+//! [`crate::dissection::engine::SoundContext::current_frame`]. Use [`duration_to_frames`] to convert
+//! durations.
+//!
+//! Automatable parameters are:
+//!
+//! - gain and pitch of a sound source, see [`crate::dissection::source::SoundSource::automation_mut`];
+//! - gain of an audio bus, see [`crate::dissection::bus::AudioBus::gain_automation_mut`];
+//! - parameters of bus effects, see [`crate::dissection::bus::AudioBus::effect_automation_mut`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use audio::dissection::automation::duration_to_frames;
+//! use audio::dissection::engine::SoundContext;
+//! use audio::dissection::source::{SoundSource, SourceParameter};
+//! use std::time::Duration;
+//!
+//! let mut context = SoundContext::new();
+//! let now = context.current_frame();
+//! let mut source = SoundSource::default();
+//! // Fade in over one second, then drop to half of the volume two seconds later.
+//! source
+//!     .automation_mut(SourceParameter::Gain)
+//!     .set_value_at(now, 0.0)
+//!     .linear_ramp_to(1.0, now, duration_to_frames(Duration::from_secs(1)))
+//!     .set_value_at(now + duration_to_frames(Duration::from_secs(3)), 0.5);
+//! context.add_source(source);
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::SAMPLE_RATE;
+
+/// Converts a duration into the amount of frames at the output sample rate.
+pub fn duration_to_frames(duration: Duration) -> u64 {
+    (duration.as_secs_f64() * SAMPLE_RATE as f64).round() as u64
+}
+
+/// Shape of a ramp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RampShape {
+    /// The value changes by the same amount every frame.
+    Linear,
+    /// The value changes by the same ratio every frame, which sounds even for gains and pitches. Both the
+    /// start and the target values must be positive, otherwise the ramp is linear.
+    Exponential,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum AutomationEvent {
+    Set {
+        time: u64,
+        value: f32,
+    },
+    Ramp {
+        start: u64,
+        end: u64,
+        target: f32,
+        shape: RampShape,
+    },
+}
+
+impl AutomationEvent {
+    fn time(&self) -> u64 {
+        match self {
+            Self::Set { time, .. } => *time,
+            Self::Ramp { start, .. } => *start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ActiveRamp {
+    from: f32,
+    to: f32,
+    start: u64,
+    end: u64,
+    shape: RampShape,
+}
+
+impl ActiveRamp {
+    fn value_at(&self, time: u64) -> f32 {
+        let t = (time - self.start) as f32 / (self.end - self.start) as f32;
+        match self.shape {
+            RampShape::Exponential if self.from > 0.0 && self.to > 0.0 => {
+                self.from * (self.to / self.from).powf(t)
+            }
+            _ => self.from + (self.to - self.from) * t,
+        }
+    }
+}
+
+/// Timeline of scheduled changes of one parameter. See module docs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Automation {
+    // Events sorted by time, the first one is the next to happen.
+    events: VecDeque<AutomationEvent>,
+    ramp: Option<ActiveRamp>,
+}
+
+impl Automation {
+    fn schedule(&mut self, event: AutomationEvent) {
+        // Events scheduled at the same time happen in the order they were added.
+        let position = self
+            .events
+            .iter()
+            .position(|other| other.time() > event.time())
+            .unwrap_or(self.events.len());
+        self.events.insert(position, event);
+    }
+
+    /// Sets the parameter to the given value at the given frame. Stops a ramp that is in progress at that
+    /// moment.
+    pub fn set_value_at(&mut self, time: u64, value: f32) -> &mut Self {
+        self.schedule(AutomationEvent::Set { time, value });
+        self
+    }
+
+    /// Linearly changes the parameter from the value it has at the `start` frame to `value` over the given
+    /// amount of frames.
+    pub fn linear_ramp_to(&mut self, value: f32, start: u64, duration: u64) -> &mut Self {
+        self.ramp_to(value, start, duration, RampShape::Linear)
+    }
+
+    /// Exponentially changes the parameter from the value it has at the `start` frame to `value` over the
+    /// given amount of frames. See [`RampShape::Exponential`].
+    pub fn exponential_ramp_to(&mut self, value: f32, start: u64, duration: u64) -> &mut Self {
+        self.ramp_to(value, start, duration, RampShape::Exponential)
+    }
+
+    /// Changes the parameter from the value it has at the `start` frame to `value` over the given amount of
+    /// frames. A new event that happens while the ramp is in progress stops it.
+    pub fn ramp_to(
+        &mut self,
+        value: f32,
+        start: u64,
+        duration: u64,
+        shape: RampShape,
+    ) -> &mut Self {
+        self.schedule(AutomationEvent::Ramp {
+            start,
+            end: start.saturating_add(duration),
+            target: value,
+            shape,
+        });
+        self
+    }
+
+    /// Removes every scheduled event and stops a ramp in progress. The parameter keeps its current value.
+    pub fn cancel(&mut self) {
+        self.events.clear();
+        self.ramp = None;
+    }
+
+    /// Removes events scheduled at the given frame or later. A ramp in progress is not affected.
+    pub fn cancel_from(&mut self, time: u64) {
+        self.events.retain(|event| event.time() < time);
+    }
+
+    /// Returns `true` if there are scheduled events or a ramp in progress.
+    pub fn is_active(&self) -> bool {
+        self.ramp.is_some() || !self.events.is_empty()
+    }
+
+    /// Returns the frame of the next scheduled event, if any.
+    pub fn next_event_time(&self) -> Option<u64> {
+        self.events.front().map(|event| event.time())
+    }
+
+    // Returns `true` if the parameter doesn't change during `amount` frames starting from `time`.
+    pub(crate) fn is_idle(&self, time: u64, amount: usize) -> bool {
+        self.ramp.is_none()
+            && self
+                .next_event_time()
+                .is_none_or(|next| next >= time + amount as u64)
+    }
+
+    // Writes values of the parameter for each frame starting from `time` into `curve`. `value` is the
+    // current value of the parameter, it is updated to the value at the last frame.
+    pub(crate) fn render(&mut self, time: u64, value: &mut f32, curve: &mut [f32]) {
+        for (frame, output) in (time..).zip(curve.iter_mut()) {
+            while let Some(event) = self.events.front() {
+                if event.time() > frame {
+                    break;
+                }
+                match *event {
+                    AutomationEvent::Set { value: new, .. } => {
+                        *value = new;
+                        self.ramp = None;
+                    }
+                    AutomationEvent::Ramp {
+                        end, target, shape, ..
+                    } => {
+                        // A late ramp starts right now, but still ends at the scheduled time.
+                        self.ramp = Some(ActiveRamp {
+                            from: *value,
+                            to: target,
+                            start: frame,
+                            end,
+                            shape,
+                        });
+                    }
+                }
+                self.events.pop_front();
+            }
+
+            if let Some(ramp) = self.ramp.as_ref() {
+                if frame >= ramp.end {
+                    *value = ramp.to;
+                    self.ramp = None;
+                } else {
+                    *value = ramp.value_at(frame);
+                }
+            }
+            *output = *value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::automation::Automation;
+
+    fn render(automation: &mut Automation, time: u64, value: &mut f32) -> [f32; 8] {
+        let mut curve = [0.0; 8];
+        automation.render(time, value, &mut curve);
+        curve
+    }
+
+    #[test]
+    fn test_set_value_is_sample_accurate() {
+        let mut automation = Automation::default();
+        automation.set_value_at(13, 2.0).set_value_at(11, 1.0);
+        let mut value = 0.0;
+        assert!(automation.is_idle(0, 8));
+        assert!(!automation.is_idle(8, 8));
+        assert_eq!(
+            render(&mut automation, 8, &mut value),
+            [0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 2.0]
+        );
+        assert_eq!(value, 2.0);
+        assert!(!automation.is_active());
+    }
+
+    #[test]
+    fn test_ramps() {
+        let mut automation = Automation::default();
+        automation.linear_ramp_to(1.0, 2, 4);
+        let mut value = 0.0;
+        assert_eq!(
+            render(&mut automation, 0, &mut value),
+            [0.0, 0.0, 0.0, 0.25, 0.5, 0.75, 1.0, 1.0]
+        );
+
+        automation.exponential_ramp_to(4.0, 8, 2);
+        assert_eq!(
+            render(&mut automation, 8, &mut value),
+            [1.0, 2.0, 4.0, 4.0, 4.0, 4.0, 4.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut automation = Automation::default();
+        automation
+            .linear_ramp_to(1.0, 0, 16)
+            .set_value_at(20, 5.0)
+            .set_value_at(30, 6.0);
+        automation.cancel_from(25);
+        assert_eq!(automation.next_event_time(), Some(0));
+
+        let mut value = 0.0;
+        render(&mut automation, 0, &mut value);
+        assert_eq!(value, 0.4375);
+        automation.cancel();
+        assert_eq!(render(&mut automation, 8, &mut value), [0.4375; 8]);
+        assert!(!automation.is_active());
+    }
+}
@@ -0,0 +1,426 @@
+//! Output backends.
+//!
+//! # Overview
+//!
+//! Output backend is a thing that periodically asks the engine for new samples and sends them somewhere:
+//! to a sound card ([`TinyaudioBackend`]), nowhere ([`NullBackend`]), into a file ([`FileBackend`]) or
+//! to a user-supplied closure ([`CallbackBackend`]). Backends are used by
+//! [`crate::dissection::engine::SharedSoundEngine::with_backend`], any type that implements
+//! [`OutputBackend`] trait could be used there.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::mess::fileio::{make_wav_header, max_wav_frames};
+use crate::{SAMPLES_PER_CHANNEL, SAMPLE_RATE};
+
+/// Parameters of an output stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OutputParameters {
+    /// Sample rate of the stream in Hz.
+    pub sample_rate: usize,
+    /// Amount of interleaved channels.
+    pub channels_count: usize,
+    /// Amount of samples per channel in each block passed to the render callback.
+    pub channel_sample_count: usize,
+}
+
+impl Default for OutputParameters {
+    fn default() -> Self {
+        Self {
+            sample_rate: SAMPLE_RATE as usize,
+            channels_count: 2,
+            channel_sample_count: SAMPLES_PER_CHANNEL,
+        }
+    }
+}
+
+impl OutputParameters {
+    /// Returns amount of interleaved samples in each block.
+    pub fn block_len(&self) -> usize {
+        self.channels_count * self.channel_sample_count
+    }
+
+    /// Returns real-time duration of each block.
+    pub fn block_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.channel_sample_count as f64 / self.sample_rate as f64)
+    }
+}
+
+/// Render callback fills the given buffer with interleaved samples.
+pub type RenderCallback = Box<dyn FnMut(&mut [f32]) + Send + 'static>;
+
+/// Sink receives rendered blocks of interleaved samples.
+pub type OutputSink = Box<dyn FnMut(&[f32]) + Send + 'static>;
+
+/// Output backend periodically calls the render callback and sends rendered samples somewhere. The stream
+/// must run until the backend is dropped.
+pub trait OutputBackend: Send {
+    /// Starts the output stream with the given parameters.
+    fn start(
+        &mut self,
+        parameters: OutputParameters,
+        callback: RenderCallback,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Stops the output stream and returns the first error that happened while it was running. It is
+    /// called when the engine is stopped, the default implementation does nothing.
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Default backend that outputs samples to the default sound card using `tinyaudio`.
+#[derive(Default)]
+pub struct TinyaudioBackend {
+    device: Option<tinyaudio::OutputDevice>,
+}
+
+impl OutputBackend for TinyaudioBackend {
+    fn start(
+        &mut self,
+        parameters: OutputParameters,
+        callback: RenderCallback,
+    ) -> Result<(), Box<dyn Error>> {
+        self.device = Some(tinyaudio::run_output_device(
+            tinyaudio::OutputDeviceParameters {
+                sample_rate: parameters.sample_rate,
+                channels_count: parameters.channels_count,
+                channel_sample_count: parameters.channel_sample_count,
+            },
+            callback,
+        )?);
+        Ok(())
+    }
+}
+
+// A thread that calls the render callback at real-time pace and passes rendered samples to a sink.
+struct PacedThread {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PacedThread {
+    fn spawn<S>(
+        parameters: OutputParameters,
+        mut callback: RenderCallback,
+        mut sink: S,
+    ) -> std::io::Result<Self>
+    where
+        S: FnMut(&[f32]) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::Builder::new()
+            .name("AudioOutput".to_string())
+            .spawn(move || {
+                let mut buffer = vec![0.0; parameters.block_len()];
+                let block_duration = parameters.block_duration();
+                let mut deadline = Instant::now();
+                while !thread_stop.load(Ordering::Acquire) {
+                    callback(&mut buffer);
+                    sink(&buffer);
+
+                    // Use absolute deadlines, so the stream won't drift because of render time.
+                    deadline += block_duration;
+                    let now = Instant::now();
+                    if deadline > now {
+                        std::thread::sleep(deadline - now);
+                    } else {
+                        deadline = now;
+                    }
+                }
+            })?;
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for PacedThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            // The backend could be dropped by its own render callback, joining in this case would hang forever.
+            if thread.thread().id() != std::thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Backend that renders samples at real-time pace and passes them to a user-supplied closure. Could be
+/// used to stream the output over network, analyze it, etc.
+pub struct CallbackBackend {
+    sink: Option<OutputSink>,
+    thread: Option<PacedThread>,
+}
+
+impl CallbackBackend {
+    /// Creates new backend that passes every rendered block of interleaved samples to the given closure.
+    pub fn new<F>(sink: F) -> Self
+    where
+        F: FnMut(&[f32]) + Send + 'static,
+    {
+        Self {
+            sink: Some(Box::new(sink)),
+            thread: None,
+        }
+    }
+}
+
+impl OutputBackend for CallbackBackend {
+    fn start(
+        &mut self,
+        parameters: OutputParameters,
+        callback: RenderCallback,
+    ) -> Result<(), Box<dyn Error>> {
+        let sink = self.sink.take().ok_or("The backend is already started!")?;
+        self.thread = Some(PacedThread::spawn(parameters, callback, sink)?);
+        Ok(())
+    }
+}
+
+/// Backend that renders samples at real-time pace and throws them away. Useful for tests and servers that
+/// have no sound card, but still need the engine to run in real time.
+#[derive(Default)]
+pub struct NullBackend {
+    thread: Option<PacedThread>,
+}
+
+impl OutputBackend for NullBackend {
+    fn start(
+        &mut self,
+        parameters: OutputParameters,
+        callback: RenderCallback,
+    ) -> Result<(), Box<dyn Error>> {
+        self.thread = Some(PacedThread::spawn(parameters, callback, |_| {})?);
+        Ok(())
+    }
+}
+
+// WAV file that is written block by block, its header is updated when the file is closed.
+struct WavFileWriter {
+    writer: BufWriter<File>,
+    channels_count: u16,
+    sample_rate: u32,
+    samples_written: u64,
+    // The first write error, nothing is written after it.
+    error: Arc<Mutex<Option<std::io::Error>>>,
+    failed: bool,
+}
+
+impl WavFileWriter {
+    fn write_block(&mut self, block: &[f32]) -> std::io::Result<()> {
+        for sample in block {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples_written += block.len() as u64;
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> std::io::Result<()> {
+        let frames = header_frames(self.samples_written, self.channels_count);
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&make_wav_header(
+            self.channels_count,
+            self.sample_rate,
+            frames,
+        ))?;
+        self.writer.flush()
+    }
+}
+
+// Amount of frames written to the header of a WAV file. Sizes in the header are 32-bit, so a file that is too
+// long to describe gets the biggest size that fits, the rest of the data is still written.
+fn header_frames(samples_written: u64, channels_count: u16) -> u32 {
+    let frames = samples_written / channels_count as u64;
+    frames.min(max_wav_frames(channels_count) as u64) as u32
+}
+
+impl WavFileWriter {
+    fn report(&mut self, result: std::io::Result<()>) {
+        if let Err(error) = result {
+            self.failed = true;
+            self.error.lock().unwrap().get_or_insert(error);
+        }
+    }
+}
+
+impl Drop for WavFileWriter {
+    fn drop(&mut self) {
+        if !self.failed {
+            let result = self.finalize();
+            self.report(result);
+        }
+    }
+}
+
+/// Backend that records the output into a 32-bit float WAV file at real-time pace. The file header is
+/// finalized when the backend is stopped or dropped. Writing stops at the first I/O error, the error is
+/// returned by [`OutputBackend::finish`] (and so by
+/// [`crate::dissection::engine::SharedSoundEngine::stop`]).
+pub struct FileBackend {
+    file: Option<File>,
+    thread: Option<PacedThread>,
+    error: Arc<Mutex<Option<std::io::Error>>>,
+}
+
+impl FileBackend {
+    /// Creates (or truncates) a WAV file at the given path.
+    pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Self {
+            file: Some(File::create(path)?),
+            thread: None,
+            error: Default::default(),
+        })
+    }
+}
+
+impl OutputBackend for FileBackend {
+    fn start(
+        &mut self,
+        parameters: OutputParameters,
+        callback: RenderCallback,
+    ) -> Result<(), Box<dyn Error>> {
+        let file = self.file.take().ok_or("The backend is already started!")?;
+        let mut wav = WavFileWriter {
+            writer: BufWriter::new(file),
+            channels_count: parameters.channels_count as u16,
+            sample_rate: parameters.sample_rate as u32,
+            samples_written: 0,
+            error: self.error.clone(),
+            failed: false,
+        };
+        // Reserve space for the header, it will be rewritten when the file is closed.
+        if let Err(error) = wav.finalize() {
+            wav.failed = true;
+            return Err(error.into());
+        }
+
+        self.thread = Some(PacedThread::spawn(parameters, callback, move |block| {
+            if !wav.failed {
+                let result = wav.write_block(block);
+                wav.report(result);
+            }
+        })?);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        // Joining the thread drops the writer, which finalizes the file.
+        self.thread = None;
+        match self.error.lock().unwrap().take() {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        backend::{header_frames, CallbackBackend, FileBackend, OutputParameters},
+        buffer::Buffer,
+        engine::{SharedSoundContext, SharedSoundEngine},
+        source::{SoundSource, Status},
+    };
+    use crate::mess::fileio::{make_wav_header, max_wav_frames};
+    use crate::SAMPLE_RATE;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn play_constant(context: &SharedSoundContext) {
+        let mut source = SoundSource::default();
+        source.set_buffer(Some(Buffer::new(vec![0.25; 64], true).into()));
+        source.looping = true;
+        source.status = Status::Playing;
+        context.add_source(source);
+    }
+
+    #[test]
+    fn test_callback_backend() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let parameters = OutputParameters {
+            channels_count: 4,
+            channel_sample_count: 256,
+            ..Default::default()
+        };
+        let backend = CallbackBackend::new(move |block| {
+            assert_eq!(block.len(), 4 * 256);
+            sink.lock().unwrap().extend_from_slice(block)
+        });
+        let engine = SharedSoundEngine::with_backend(backend, parameters).unwrap();
+
+        let context = SharedSoundContext::new();
+        play_constant(&context);
+        engine.set_context(context).unwrap();
+
+        std::thread::sleep(Duration::from_millis(200));
+        engine.stop().unwrap();
+
+        // Stereo signal goes to the first two channels, the rest are silent.
+        let received = received.lock().unwrap();
+        assert!(received.chunks(4).any(|frame| frame[..2] == [0.25, 0.25]));
+        assert!(received.chunks(4).all(|frame| frame[2..] == [0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_unsupported_parameters() {
+        let parameters = OutputParameters {
+            sample_rate: 12345,
+            ..Default::default()
+        };
+        let backend = CallbackBackend::new(|_| ());
+        assert!(SharedSoundEngine::with_backend(backend, parameters).is_err());
+    }
+
+    #[test]
+    fn test_file_backend() {
+        let path = std::env::temp_dir().join("audio_test_file_backend.wav");
+        let backend = FileBackend::new(&path).unwrap();
+        let engine = SharedSoundEngine::with_backend(backend, Default::default()).unwrap();
+        let context = SharedSoundContext::new();
+        play_constant(&context);
+        engine.set_context(context).unwrap();
+
+        std::thread::sleep(Duration::from_millis(200));
+        engine.stop().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let data_size = u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as usize;
+        assert!(data_size > 0);
+        assert_eq!(data_size, bytes.len() - 44);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_header_frames_saturate() {
+        let max = max_wav_frames(2);
+        assert_eq!(header_frames(max as u64 * 2, 2), max);
+        assert_eq!(header_frames(max as u64 * 2 + 2, 2), max);
+        assert_eq!(header_frames(u64::MAX, 2), max);
+
+        let header = make_wav_header(2, SAMPLE_RATE, header_frames(u64::MAX, 2));
+        let riff_size = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let data_size = u32::from_le_bytes(header[40..44].try_into().unwrap());
+        assert_eq!(riff_size, data_size + 36);
+        assert_eq!(data_size, max * 8);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_file_backend_error() {
+        // Every write to this device fails with "no space left".
+        let backend = FileBackend::new("/dev/full").unwrap();
+        assert!(SharedSoundEngine::with_backend(backend, Default::default()).is_err());
+    }
+}
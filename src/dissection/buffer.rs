@@ -1,20 +1,79 @@
 use std::ops::{Deref, DerefMut};
 use std::fmt::Debug;
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::SAMPLE_RATE;
 
-#[derive(Default, Clone)]
+/// Loop region of a buffer in frames, the end is exclusive. See [`crate::dissection::source::SoundSource::set_loop_region`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopRegion {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LoopRegion {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Returns the length of the region in frames.
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Returns `true` if the region has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A named position in a buffer. Sources report markers they pass during playback, see
+/// [`crate::dissection::source::SoundEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CueMarker {
+    /// Identifier of the marker, it is reported in events.
+    pub id: u32,
+    /// Position of the marker in frames.
+    pub position: usize,
+}
+
+impl CueMarker {
+    pub fn new(id: u32, position: usize) -> Self {
+        Self { id, position }
+    }
+}
+
+#[derive(Clone)]
 pub struct Buffer {
     is_mono: bool,
+    sample_rate: u32,
+    loop_region: Option<LoopRegion>,
+    // Sorted by position.
+    markers: Vec<CueMarker>,
     /// Interleaved decoded samples (mono sounds: L..., stereo sounds: LR...)
     pub samples: Vec<f32>,
 }
 
+impl Default for Buffer {
+    fn default() -> Self {
+        Self {
+            is_mono: false,
+            sample_rate: SAMPLE_RATE,
+            loop_region: None,
+            markers: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+}
+
 impl Debug for Buffer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let channels = if self.is_mono { "Mono" } else { "Stereo" };
         f.debug_struct(format!("Buffer ({channels})").as_str())
+            .field("sample_rate", &self.sample_rate)
+            .field("loop_region", &self.loop_region)
+            .field("markers", &self.markers)
             .field("samples", &format!("[..{} samples]", &self.samples.len()))
             .finish()
     }
@@ -42,9 +101,46 @@ impl Buffer {
         Self {
             samples: samples.to_owned(),
             is_mono,
+            sample_rate: SAMPLE_RATE,
+            loop_region: None,
+            markers: Vec::new(),
         }
     }
 
+    /// Sets sample rate of the samples. Buffers with a sample rate different from the output one are
+    /// resampled on the fly during playback.
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate.max(1);
+        self
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Sets the default loop region of sources playing the buffer. WAV files store it in the `smpl` chunk, see
+    /// [`Buffer::read_wav`].
+    pub fn with_loop_region(mut self, loop_region: Option<LoopRegion>) -> Self {
+        self.loop_region = loop_region;
+        self
+    }
+
+    pub fn loop_region(&self) -> Option<LoopRegion> {
+        self.loop_region
+    }
+
+    /// Sets cue markers of the buffer. WAV files store them in the `cue ` chunk, see [`Buffer::read_wav`].
+    pub fn with_markers(mut self, mut markers: Vec<CueMarker>) -> Self {
+        markers.sort_by_key(|marker| marker.position);
+        self.markers = markers;
+        self
+    }
+
+    /// Returns cue markers of the buffer sorted by position.
+    pub fn markers(&self) -> &[CueMarker] {
+        &self.markers
+    }
+
     /// Applies a function to every sample in-place
     pub fn apply<F>(&mut self, mut f: F)
     where
@@ -75,7 +171,153 @@ impl Buffer {
     #[inline]
     pub fn duration(&self) -> Duration {
         Duration::from_nanos(
-            (self.channel_duration_in_samples() as u64 * 1_000_000_000u64) / SAMPLE_RATE as u64,
+            (self.channel_duration_in_samples() as u64 * 1_000_000_000u64) / self.sample_rate as u64,
         )
     }
 }
+
+/// Shared, immutable sample data. Any amount of sound sources could play the same buffer without copying
+/// its samples: cloning is cheap and only increments a reference counter. Samples are freed when the last
+/// user of the buffer goes away.
+///
+/// # Examples
+///
+/// ```rust
+/// use audio::dissection::buffer::{Buffer, SharedBuffer};
+/// use audio::dissection::source::SoundSource;
+///
+/// let footstep = SharedBuffer::new(Buffer::new(vec![0.0; 1024], true));
+/// let sources = (0..50)
+///     .map(|_| {
+///         let mut source = SoundSource::default();
+///         source.set_buffer(Some(footstep.clone()));
+///         source
+///     })
+///     .collect::<Vec<_>>();
+/// assert_eq!(footstep.use_count(), 51);
+/// ```
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Arc<Buffer>);
+
+impl Debug for SharedBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedBuffer")
+            .field("buffer", &self.0)
+            .field("use_count", &self.use_count())
+            .finish()
+    }
+}
+
+impl Deref for SharedBuffer {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Buffer> for SharedBuffer {
+    fn from(buffer: Buffer) -> Self {
+        Self::new(buffer)
+    }
+}
+
+impl PartialEq for SharedBuffer {
+    /// Shared buffers are equal if they point to the same sample data.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl SharedBuffer {
+    pub fn new(buffer: Buffer) -> Self {
+        Self(Arc::new(buffer))
+    }
+
+    /// Returns amount of users (sound sources, clones held by the user, etc.) of the sample data.
+    pub fn use_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    /// Returns the buffer back if this is the only user of it.
+    pub fn try_unwrap(self) -> Result<Buffer, Self> {
+        Arc::try_unwrap(self.0).map_err(Self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        buffer::{Buffer, CueMarker, LoopRegion, SharedBuffer},
+        source::SoundSource,
+    };
+    use crate::mess::fileio::make_wav_header;
+
+    #[test]
+    fn test_shared_buffer_is_not_copied() {
+        let buffer = SharedBuffer::new(Buffer::new(vec![0.5; 100], true));
+        let mut sources = Vec::new();
+        for _ in 0..50 {
+            let mut source = SoundSource::default();
+            source.set_buffer(Some(buffer.clone()));
+            sources.push(source);
+        }
+        assert_eq!(buffer.use_count(), 51);
+        assert!(sources
+            .iter()
+            .all(|source| source.buffer() == Some(&buffer)));
+
+        // The data is reclaimed when the last user goes away.
+        drop(sources);
+        assert_eq!(buffer.use_count(), 1);
+        assert!(buffer.try_unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_wav_loop_region() {
+        let mut bytes = make_wav_header(1, 44100, 16).to_vec();
+        for i in 0..16 {
+            bytes.extend_from_slice(&(i as f32).to_le_bytes());
+        }
+        // Sampler chunk with one loop over frames 4..=7.
+        let mut sampler = [0u8; 36 + 24];
+        sampler[28..32].copy_from_slice(&1u32.to_le_bytes());
+        sampler[44..48].copy_from_slice(&4u32.to_le_bytes());
+        sampler[48..52].copy_from_slice(&7u32.to_le_bytes());
+        bytes.extend_from_slice(b"smpl");
+        bytes.extend_from_slice(&(sampler.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&sampler);
+
+        let buffer = Buffer::read_wav(bytes.as_slice()).unwrap();
+        assert_eq!(buffer.channel_duration_in_samples(), 16);
+        assert_eq!(buffer.loop_region(), Some(LoopRegion::new(4, 8)));
+        let mut source = SoundSource::default();
+        source.set_buffer(Some(buffer.into()));
+        assert_eq!(source.loop_region(), Some(LoopRegion::new(4, 8)));
+    }
+
+    #[test]
+    fn test_wav_cue_markers() {
+        let mut bytes = make_wav_header(2, 44100, 16).to_vec();
+        bytes.extend_from_slice(&[0; 16 * 2 * 4]);
+        // Cue points are stored in arbitrary order, the last one is beyond the end of the data.
+        let points = [(1u32, 12u32), (2, 3), (3, 100)];
+        let mut cue = (points.len() as u32).to_le_bytes().to_vec();
+        for (id, position) in points {
+            let mut point = [0u8; 24];
+            point[0..4].copy_from_slice(&id.to_le_bytes());
+            point[8..12].copy_from_slice(b"data");
+            point[20..24].copy_from_slice(&position.to_le_bytes());
+            cue.extend_from_slice(&point);
+        }
+        bytes.extend_from_slice(b"cue ");
+        bytes.extend_from_slice(&(cue.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&cue);
+
+        let buffer = Buffer::read_wav(bytes.as_slice()).unwrap();
+        assert_eq!(
+            buffer.markers(),
+            [CueMarker::new(2, 3), CueMarker::new(1, 12)]
+        );
+    }
+}
@@ -1,8 +1,12 @@
 //! Everything related to audio buses and audio bus graphs. See docs of [`AudioBus`] and [`AudioBusGraph`]
 //! for more info and examples
 
+use crate::dissection::ambisonics::{world_to_listener, AmbisonicDecoder, BFormatSample};
+use crate::dissection::automation::Automation;
 use crate::dissection::effects::{Effect, EffectRenderTrait};
+use crate::dissection::listener::Listener;
 use crate::dissection::pool::{Handle, Pool, Ticket};
+use crate::{lerp, SAMPLE_RATE};
 use std::fmt::{Debug, Formatter};
 
 #[derive(Default, Clone)]
@@ -79,14 +83,35 @@ impl PingPongBuffer {
 #[derive(Debug, Clone)]
 pub struct AudioBus {
     pub(crate) name: String,
-    effects: Vec<Effect>,
+    effects: Pool<Effect>,
+    // Processing order of the effects.
+    chain: Vec<ChainEntry>,
     gain: f32,
+    muted: bool,
+    soloed: bool,
+    // Solo state computed by the graph at each block: the bus is a soloed bus or one of its descendants
+    // (`solo_input`), or the signal of a soloed bus goes through it (`solo_output`).
+    solo_input: bool,
+    solo_output: bool,
+    // Gain used in the previous block, it is interpolated to remove clicks.
+    last_gain: Option<f32>,
+    // Fade between audible (1) and silent (0) states, see `set_muted` and `set_soloed`.
+    audibility: Option<Fade>,
+    gain_automation: Automation,
+    // Per-frame gain in the current block, empty if the gain is not automated during the block.
+    gain_curve: Vec<f32>,
 
     child_buses: Vec<Handle<AudioBus>>,
 
     parent_bus: Handle<AudioBus>,
 
     ping_pong_buffer: PingPongBuffer,
+
+    // B-format input of the bus, used only when the graph is in ambisonic mode. Effects are not applied
+    // to this buffer.
+    ambisonic_buffer: Vec<BFormatSample>,
+
+    sends: Vec<AuxSend>,
 }
 
 impl Default for AudioBus {
@@ -95,9 +120,20 @@ impl Default for AudioBus {
             name: "Bus".to_string(),
             child_buses: Default::default(),
             effects: Default::default(),
+            chain: Default::default(),
             gain: 1.0,
+            muted: false,
+            soloed: false,
+            solo_input: false,
+            solo_output: false,
+            last_gain: None,
+            audibility: None,
+            gain_automation: Default::default(),
+            gain_curve: Default::default(),
             ping_pong_buffer: Default::default(),
             parent_bus: Default::default(),
+            ambisonic_buffer: Default::default(),
+            sends: Default::default(),
         }
     }
 }
@@ -144,58 +180,633 @@ impl AudioBus {
         self.gain
     }
 
-    pub(crate) fn input_buffer(&mut self) -> &mut [(f32, f32)] {
-        self.ping_pong_buffer.input_mut()
+    /// Returns automation of the gain of the audio bus. See [`crate::dissection::automation`] docs.
+    pub fn gain_automation(&self) -> &Automation {
+        &self.gain_automation
+    }
+
+    /// Returns automation of the gain of the audio bus, that could be used to schedule gain changes. See
+    /// [`crate::dissection::automation`] docs.
+    pub fn gain_automation_mut(&mut self) -> &mut Automation {
+        &mut self.gain_automation
+    }
+
+    /// Returns automation of a parameter of an effect, if the effect exists and has such parameter (see
+    /// [`EffectRenderTrait::parameter`]).
+    pub fn effect_automation(
+        &self,
+        effect: Handle<Effect>,
+        parameter: usize,
+    ) -> Option<&Automation> {
+        self.chain_entry(effect)?
+            .lanes
+            .iter()
+            .find(|lane| lane.parameter == parameter)
+            .map(|lane| &lane.automation)
+    }
+
+    /// Returns automation of a parameter of an effect, that could be used to schedule changes of the
+    /// parameter. Returns `None` if there's no such effect or the effect has no such parameter (see
+    /// [`EffectRenderTrait::parameter`]). See [`crate::dissection::automation`] docs.
+    pub fn effect_automation_mut(
+        &mut self,
+        effect: Handle<Effect>,
+        parameter: usize,
+    ) -> Option<&mut Automation> {
+        self.effects.try_borrow(effect)?.parameter(parameter)?;
+        let lanes = &mut self.chain_entry_mut(effect)?.lanes;
+        let index = match lanes.iter().position(|lane| lane.parameter == parameter) {
+            Some(index) => index,
+            None => {
+                lanes.push(ParameterLane {
+                    parameter,
+                    automation: Default::default(),
+                    curve: Default::default(),
+                });
+                lanes.len() - 1
+            }
+        };
+        Some(&mut lanes[index].automation)
     }
 
-    pub(crate) fn begin_render(&mut self, buffer_size: usize) {
+    // Evaluates automation of the gain and effect parameters for the block of `amount` frames starting from
+    // `time`.
+    fn update_automation(&mut self, time: u64, amount: usize) {
+        self.gain_curve.clear();
+        if !self.gain_automation.is_idle(time, amount) {
+            self.gain_curve.resize(amount, 0.0);
+            self.gain_automation
+                .render(time, &mut self.gain, &mut self.gain_curve);
+        }
+
+        for entry in self.chain.iter_mut() {
+            let effect = self.effects.try_borrow(entry.effect);
+            for lane in entry.lanes.iter_mut() {
+                lane.curve.clear();
+                if !lane.automation.is_idle(time, amount) {
+                    // Parameters are changed while rendering, see `apply_effects`.
+                    let mut value = effect
+                        .and_then(|effect| effect.parameter(lane.parameter))
+                        .unwrap_or_default();
+                    lane.curve.resize(amount, 0.0);
+                    lane.automation.render(time, &mut value, &mut lane.curve);
+                }
+            }
+        }
+    }
+
+    /// Mutes or unmutes the audio bus. Muted bus outputs nothing, neither to its parent nor to its sends.
+    /// The change is faded over 5 milliseconds to remove clicks.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Returns `true` if the audio bus is muted.
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Solos or unsolos the audio bus. While at least one bus of the graph is soloed, only soloed buses,
+    /// their descendants and buses their signal goes through (parents up to the primary bus and targets of
+    /// sends) are audible, every other bus is silenced. Sound sources that output directly to a bus on the
+    /// path of a soloed bus stay audible. The change is faded over 5 milliseconds to remove clicks.
+    pub fn set_soloed(&mut self, soloed: bool) {
+        self.soloed = soloed;
+    }
+
+    /// Returns `true` if the audio bus is soloed.
+    pub fn is_soloed(&self) -> bool {
+        self.soloed
+    }
+
+    /// Returns stereo and B-format input buffers. B-format buffer is empty if the graph is not in
+    /// ambisonic mode.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn input_buffers(&mut self) -> (&mut [(f32, f32)], &mut [BFormatSample]) {
+        (
+            self.ping_pong_buffer.input_mut(),
+            &mut self.ambisonic_buffer,
+        )
+    }
+
+    pub(crate) fn begin_render(&mut self, buffer_size: usize, ambisonic: bool) {
         if self.ping_pong_buffer.capacity() < buffer_size {
             self.ping_pong_buffer.resize(buffer_size);
         } else {
             self.ping_pong_buffer.clear();
         }
+
+        self.ambisonic_buffer.clear();
+        if ambisonic {
+            self.ambisonic_buffer
+                .resize(buffer_size, BFormatSample::default());
+        }
     }
 
-    fn apply_effects(&mut self) {
+    // `frames` is the size of the rendered block, the buffers could be longer.
+    fn apply_effects(&mut self, frames: usize) {
         // Pass through the chain of effects.
-        for effect in self.effects.iter_mut() {
+        for entry in self.chain.iter_mut() {
+            let wet = if entry.bypassed { 0.0 } else { 1.0 };
+            let fade = entry.wet.get_or_insert(Fade::new(wet));
+            let was_bypassed = fade.is_done() && fade.target() == 0.0;
+            fade.set_target(wet);
+            if was_bypassed && wet == 0.0 {
+                // Bypassed effects are not rendered at all.
+                continue;
+            }
+            let Some(effect) = self.effects.try_borrow_mut(entry.effect) else {
+                continue;
+            };
+            if was_bypassed {
+                // The effect is put back into the chain, its state is outdated.
+                effect.reset();
+            }
+
             let (input, output) = self.ping_pong_buffer.input_output_buffers();
-            effect.render(input, output);
+            if entry.lanes.iter().all(|lane| lane.curve.is_empty()) {
+                effect.render(input, output);
+            } else {
+                render_automated(effect, &entry.lanes, input, output);
+            }
+            if !fade.is_done() {
+                // Crossfade between dry and processed signal when the bypass state changes.
+                for (i, ((input_left, input_right), (output_left, output_right))) in
+                    input.iter().zip(output.iter_mut()).enumerate()
+                {
+                    let t = fade.value_at(i);
+                    *output_left = lerp(*input_left, *output_left, t);
+                    *output_right = lerp(*input_right, *output_right, t);
+                }
+                fade.advance(frames);
+            }
             self.ping_pong_buffer.swap();
         }
     }
 
-    /// Adds new effect to the effects chain.
-    pub fn add_effect(&mut self, effect: Effect) {
-        self.effects.push(effect)
+    fn chain_entry(&self, effect: Handle<Effect>) -> Option<&ChainEntry> {
+        self.chain.iter().find(|entry| entry.effect == effect)
+    }
+
+    fn chain_entry_mut(&mut self, effect: Handle<Effect>) -> Option<&mut ChainEntry> {
+        self.chain.iter_mut().find(|entry| entry.effect == effect)
+    }
+
+    /// Adds new effect to the end of the effects chain and returns its handle. The handle stays valid until
+    /// the effect is removed, no matter how the chain is changed.
+    pub fn add_effect(&mut self, effect: Effect) -> Handle<Effect> {
+        self.insert_effect(self.chain.len(), effect)
+    }
+
+    /// Inserts new effect into the effects chain at the given position (it is clamped to the length of the
+    /// chain) and returns its handle.
+    pub fn insert_effect(&mut self, position: usize, effect: Effect) -> Handle<Effect> {
+        let effect = self.effects.spawn(effect);
+        self.chain
+            .insert(position.min(self.chain.len()), ChainEntry::new(effect));
+        effect
     }
 
     /// Removes an effect by the given handle.
-    pub fn remove_effect(&mut self, index: usize) {
-        self.effects.remove(index);
+    pub fn remove_effect(&mut self, effect: Handle<Effect>) -> Option<Effect> {
+        let position = self.effect_position(effect)?;
+        self.chain.remove(position);
+        self.effects.try_free(effect)
+    }
+
+    /// Moves an effect to the given position in the effects chain (it is clamped to the length of the chain).
+    /// Returns `false` if there's no such effect.
+    pub fn move_effect(&mut self, effect: Handle<Effect>, position: usize) -> bool {
+        let Some(current) = self.effect_position(effect) else {
+            return false;
+        };
+        let entry = self.chain.remove(current);
+        self.chain.insert(position.min(self.chain.len()), entry);
+        true
+    }
+
+    /// Replaces an effect at the given handle with a new one, the handle and position of the effect in the
+    /// chain stay the same. Returns the previous effect or `None` if there's no such effect.
+    pub fn replace_effect(&mut self, effect: Handle<Effect>, new_effect: Effect) -> Option<Effect> {
+        self.effects
+            .try_borrow_mut(effect)
+            .map(|effect| std::mem::replace(effect, new_effect))
+    }
+
+    /// Returns position of an effect in the effects chain.
+    pub fn effect_position(&self, effect: Handle<Effect>) -> Option<usize> {
+        self.chain.iter().position(|entry| entry.effect == effect)
     }
 
     /// Returns a shared reference to an effect at the given handle.
-    pub fn effect(&self, index: usize) -> Option<&Effect> {
-        self.effects.get(index)
+    pub fn effect(&self, effect: Handle<Effect>) -> Option<&Effect> {
+        self.effects.try_borrow(effect)
     }
 
     /// Returns mutable reference to effect at given handle.
-    pub fn effect_mut(&mut self, index: usize) -> Option<&mut Effect> {
-        self.effects.get_mut(index)
+    pub fn effect_mut(&mut self, effect: Handle<Effect>) -> Option<&mut Effect> {
+        self.effects.try_borrow_mut(effect)
     }
 
-    /// Returns an iterator over effects used by this audio bus.
+    /// Returns handles of the effects in the order they're applied.
+    pub fn effect_handles(&self) -> impl Iterator<Item = Handle<Effect>> + '_ {
+        self.chain.iter().map(|entry| entry.effect)
+    }
+
+    /// Returns an iterator over effects used by this audio bus, in the order they're applied.
     pub fn effects(&self) -> impl Iterator<Item = &Effect> {
-        self.effects.iter()
+        self.chain
+            .iter()
+            .filter_map(|entry| self.effects.try_borrow(entry.effect))
     }
 
-    /// Returns an iterator over effects used by this audio bus.
+    /// Returns an iterator over effects used by this audio bus, in no particular order.
     pub fn effects_mut(&mut self) -> impl Iterator<Item = &mut Effect> {
         self.effects.iter_mut()
     }
+
+    /// Returns total delay (in samples) added to the signal by the effects of the bus. Bypassed effects are not
+    /// taken into account.
+    pub fn latency(&self) -> usize {
+        self.chain
+            .iter()
+            .filter(|entry| !entry.bypassed)
+            .filter_map(|entry| self.effects.try_borrow(entry.effect))
+            .map(|effect| effect.latency())
+            .sum()
+    }
+
+    /// Clears internal state of every effect of the bus, see [`EffectRenderTrait::reset`].
+    pub fn reset_effects(&mut self) {
+        for effect in self.effects.iter_mut() {
+            effect.reset();
+        }
+    }
+
+    /// Bypasses an effect at the given handle or puts it back into the chain. Bypassed effect passes its input
+    /// unchanged and is not rendered. The change is crossfaded over 5 milliseconds to remove clicks. Does
+    /// nothing if there's no such effect.
+    pub fn set_effect_bypassed(&mut self, effect: Handle<Effect>, bypassed: bool) {
+        if let Some(entry) = self.chain_entry_mut(effect) {
+            entry.bypassed = bypassed;
+        }
+    }
+
+    /// Returns `true` if an effect at the given handle is bypassed.
+    pub fn is_effect_bypassed(&self, effect: Handle<Effect>) -> bool {
+        self.chain_entry(effect).is_some_and(|entry| entry.bypassed)
+    }
+
+    /// Returns aux sends of the audio bus. Sends are added and removed by [`AudioBusGraph::add_send`] and
+    /// [`AudioBusGraph::remove_send`], because they change the order in which the buses are processed.
+    pub fn sends(&self) -> &[AuxSend] {
+        &self.sends
+    }
+
+    /// Returns mutable reference to an aux send at the given index. Only level and tap point of the send
+    /// could be changed this way.
+    pub fn send_mut(&mut self, index: usize) -> Option<&mut AuxSend> {
+        self.sends.get_mut(index)
+    }
+}
+
+// Renders the effect in parts with constant values of automated parameters, so changes of the parameters
+// are sample-accurate.
+fn render_automated(
+    effect: &mut Effect,
+    lanes: &[ParameterLane],
+    input: &[(f32, f32)],
+    output: &mut [(f32, f32)],
+) {
+    let automated = lanes
+        .iter()
+        .filter(|lane| !lane.curve.is_empty())
+        .map(|lane| lane.curve.len())
+        .min()
+        .unwrap_or_default()
+        .min(output.len());
+    let mut start = 0;
+    while start < automated {
+        let mut end = automated;
+        for lane in lanes.iter().filter(|lane| !lane.curve.is_empty()) {
+            let value = lane.curve[start];
+            effect.set_parameter(lane.parameter, value);
+            if let Some(length) = lane.curve[start..automated]
+                .iter()
+                .position(|other| *other != value)
+            {
+                end = end.min(start + length);
+            }
+        }
+        effect.render(&input[start..end], &mut output[start..end]);
+        start = end;
+    }
+    // Buffers of the bus could be longer than the block.
+    if automated < output.len() {
+        effect.render(&input[automated..], &mut output[automated..]);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ParameterLane {
+    parameter: usize,
+    automation: Automation,
+    // Per-frame values of the parameter in the current block, empty if it doesn't change during the block.
+    curve: Vec<f32>,
+}
+
+#[derive(Debug, Clone)]
+struct ChainEntry {
+    effect: Handle<Effect>,
+    bypassed: bool,
+    // Amount of processed signal, faded when the bypass state changes.
+    wet: Option<Fade>,
+    lanes: Vec<ParameterLane>,
+}
+
+impl ChainEntry {
+    fn new(effect: Handle<Effect>) -> Self {
+        Self {
+            effect,
+            bypassed: false,
+            wet: None,
+            lanes: Default::default(),
+        }
+    }
+}
+
+/// Length of mute, solo and bypass fades in frames (5 milliseconds).
+const FADE_FRAMES: usize = SAMPLE_RATE as usize / 200;
+
+// Linear fade to a target value that lasts `FADE_FRAMES` frames, no matter how big the rendered blocks are.
+#[derive(Debug, Clone, Copy)]
+struct Fade {
+    from: f32,
+    to: f32,
+    // Frames of the fade rendered before the current block.
+    elapsed: usize,
+}
+
+impl Fade {
+    fn new(value: f32) -> Self {
+        Self {
+            from: value,
+            to: value,
+            elapsed: FADE_FRAMES,
+        }
+    }
+
+    fn target(&self) -> f32 {
+        self.to
+    }
+
+    // Starts a new fade from the current value if the target is changed.
+    fn set_target(&mut self, target: f32) {
+        if target != self.to {
+            self.from = self.value_at(0);
+            self.to = target;
+            self.elapsed = 0;
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.elapsed >= FADE_FRAMES
+    }
+
+    // Returns the value at the given frame of the current block.
+    fn value_at(&self, frame: usize) -> f32 {
+        let t = (self.elapsed + frame).min(FADE_FRAMES) as f32 / FADE_FRAMES as f32;
+        lerp(self.from, self.to, t)
+    }
+
+    fn advance(&mut self, frames: usize) {
+        self.elapsed = (self.elapsed + frames).min(FADE_FRAMES);
+    }
+}
+
+/// A point of the signal chain an aux send takes its signal from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SendTap {
+    /// The signal before the fader. For a sound source it is the signal of the source before its gain,
+    /// distance attenuation and panning are applied (fades and the gain of its group still apply). For an
+    /// audio bus it is the output of its effects before the gain of the bus is applied.
+    PreFader,
+    /// The signal after the fader, the same signal that goes to the main output of a source or a bus.
+    #[default]
+    PostFader,
+}
+
+/// Aux send mixes a copy of the signal of a sound source or an audio bus into the input of another audio
+/// bus (usually a bus with some shared effect, like reverberation), in addition to the normal routing.
+///
+/// # Examples
+///
+/// ```rust
+/// use audio::dissection::bus::{AudioBus, AuxSend, SendTap};
+/// use audio::dissection::engine::SoundContext;
+/// use audio::dissection::source::SoundSource;
+///
+/// let mut context = SoundContext::new();
+/// let graph = context.bus_graph_mut();
+/// let primary_bus = graph.primary_bus_handle();
+/// let reverb = graph
+///     .add_bus(AudioBus::new("Reverb".to_string()), primary_bus)
+///     .unwrap();
+///
+/// let mut source = SoundSource::default();
+/// source.add_send(AuxSend::new(reverb, 0.3, SendTap::PostFader));
+/// context.add_source(source);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AuxSend {
+    target: Handle<AudioBus>,
+    level: f32,
+    tap: SendTap,
+    // Level used in the previous block, levels are interpolated to remove clicks.
+    last_level: Option<f32>,
 }
 
+impl AuxSend {
+    /// Creates a new aux send to the `target` bus with the given level and tap point.
+    pub fn new(target: Handle<AudioBus>, level: f32, tap: SendTap) -> Self {
+        Self {
+            target,
+            level,
+            tap,
+            last_level: None,
+        }
+    }
+
+    /// Returns a handle of the target audio bus.
+    pub fn target(&self) -> Handle<AudioBus> {
+        self.target
+    }
+
+    /// Sets new level of the send. The level is applied on top of the tapped signal.
+    pub fn set_level(&mut self, level: f32) -> &mut Self {
+        self.level = level;
+        self
+    }
+
+    /// Returns current level of the send.
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// Sets new tap point of the send.
+    pub fn set_tap(&mut self, tap: SendTap) -> &mut Self {
+        self.tap = tap;
+        self
+    }
+
+    /// Returns current tap point of the send.
+    pub fn tap(&self) -> SendTap {
+        self.tap
+    }
+
+    // Returns levels at the beginning and at the end of the current block.
+    fn level_ramp(&mut self) -> (f32, f32) {
+        let last_level = self.last_level.replace(self.level).unwrap_or(self.level);
+        (last_level, self.level)
+    }
+}
+
+// Mixes `input` into `output` with the gain linearly changing from `from` to `to` over the block.
+fn mix_with_ramp(input: &[(f32, f32)], output: &mut [(f32, f32)], from: f32, to: f32) {
+    let step = (to - from) / input.len().max(1) as f32;
+    let mut gain = from;
+    for ((input_left, input_right), (output_left, output_right)) in input.iter().zip(output) {
+        *output_left += *input_left * gain;
+        *output_right += *input_right * gain;
+        gain += step;
+    }
+}
+
+fn mix_ambisonic_with_ramp(
+    input: &[BFormatSample],
+    output: &mut [BFormatSample],
+    from: f32,
+    to: f32,
+) {
+    let step = (to - from) / input.len().max(1) as f32;
+    let mut gain = from;
+    for (input, output) in input.iter().zip(output) {
+        *output += *input * gain;
+        gain += step;
+    }
+}
+
+// Same as `mix_with_ramp`, but the gain is multiplied by the fade too.
+fn mix_with_ramp_and_fade(
+    input: &[(f32, f32)],
+    output: &mut [(f32, f32)],
+    from: f32,
+    to: f32,
+    fade: &Fade,
+) {
+    if fade.is_done() {
+        return mix_with_ramp(input, output, from * fade.target(), to * fade.target());
+    }
+    let step = (to - from) / input.len().max(1) as f32;
+    for (i, ((input_left, input_right), (output_left, output_right))) in
+        input.iter().zip(output).enumerate()
+    {
+        let gain = (from + step * i as f32) * fade.value_at(i);
+        *output_left += *input_left * gain;
+        *output_right += *input_right * gain;
+    }
+}
+
+// Same as `mix_ambisonic_with_ramp`, but the gain is multiplied by the fade too.
+fn mix_ambisonic_with_ramp_and_fade(
+    input: &[BFormatSample],
+    output: &mut [BFormatSample],
+    from: f32,
+    to: f32,
+    fade: &Fade,
+) {
+    if fade.is_done() {
+        return mix_ambisonic_with_ramp(input, output, from * fade.target(), to * fade.target());
+    }
+    let step = (to - from) / input.len().max(1) as f32;
+    for (i, (input, output)) in input.iter().zip(output).enumerate() {
+        *output += *input * ((from + step * i as f32) * fade.value_at(i));
+    }
+}
+
+/// Mixes the given signal into the target buses of the sends with the given tap point. Sends to invalid
+/// buses are skipped.
+pub(crate) fn mix_sends(
+    sends: &mut [AuxSend],
+    tap: SendTap,
+    input: &[(f32, f32)],
+    ambisonic_input: &[BFormatSample],
+    graph: &mut AudioBusGraph,
+) {
+    for send in sends.iter_mut().filter(|send| send.tap == tap) {
+        let (from, to) = send.level_ramp();
+        if let Some(target) = graph.buses.try_borrow_mut(send.target) {
+            mix_with_ramp(input, target.ping_pong_buffer.input_mut(), from, to);
+            mix_ambisonic_with_ramp(ambisonic_input, &mut target.ambisonic_buffer, from, to);
+        }
+    }
+}
+
+/// An error that could occur when changing the structure of an [`AudioBusGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusGraphError {
+    /// The handle does not point to any bus of the graph.
+    InvalidHandle(Handle<AudioBus>),
+    /// The operation is not allowed for the primary bus (it can't be removed or attached to other bus).
+    PrimaryBus,
+    /// Routing `child` into `parent` (by attaching it or by an aux send) would create a loop, because the
+    /// signal of `parent` already goes to `child`.
+    Cycle {
+        child: Handle<AudioBus>,
+        parent: Handle<AudioBus>,
+    },
+    /// Parent of the bus does not list it as a child (or vice versa).
+    InconsistentLink {
+        bus: Handle<AudioBus>,
+        parent: Handle<AudioBus>,
+    },
+    /// The bus is not connected to the primary bus.
+    Unreachable(Handle<AudioBus>),
+}
+
+impl std::fmt::Display for BusGraphError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidHandle(handle) => {
+                write!(f, "There's no audio bus at {handle} handle.")
+            }
+            Self::PrimaryBus => {
+                write!(f, "The operation is not allowed for the primary audio bus.")
+            }
+            Self::Cycle { child, parent } => {
+                write!(
+                    f,
+                    "Routing {child} audio bus to {parent} audio bus would create a loop."
+                )
+            }
+            Self::InconsistentLink { bus, parent } => {
+                write!(
+                    f,
+                    "The link between {bus} audio bus and its parent {parent} is inconsistent."
+                )
+            }
+            Self::Unreachable(handle) => {
+                write!(
+                    f,
+                    "The {handle} audio bus is not connected to the primary bus."
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BusGraphError {}
+
 /// Audio bus graph is a complex audio data processing entity; it allows you to route samples from
 /// audio sources through a chain of audio buses or directly to an audio playback device. To get a
 /// better understanding of how the audio graph works take a look the data flow diagram below:
@@ -254,35 +865,89 @@ impl AudioBus {
 ///
 /// # Sound source binding
 ///
-/// Each sound source binds to an audio bus using its name; this is so called implicit binding. While it may
-/// look weird, it is actually very useful. Explicit binding requires you to know the exact handle of an
-/// audio bus to which a sound is "attached". This makes it less convenient to re-route data from one bus to
-/// another. Implicit binding is as much more effective: all you need to do is to set a new name of a bus
-/// to which output the samples from a sound source and the engine will do the rest for you. A simple example
-/// of a such binding is something like this:
+/// A sound source could be bound to an audio bus explicitly, by the handle of the bus (see
+/// [`crate::dissection::source::SoundSource::set_bus_handle`]), or implicitly, by its name (see
+/// [`crate::dissection::source::SoundSource::set_bus`]). Implicit binding is convenient: all you need to
+/// do is to set a new name of a bus to which output the samples from a sound source and the engine will do
+/// the rest for you. The name is resolved into a handle once and resolved again only when the graph changes,
+/// so both ways of binding cost the same while rendering. A simple example of a such binding is something
+/// like this:
 ///
 /// ```rust
-/// # use fyrox_sound::bus::AudioBus;
-/// # use fyrox_sound::context::SoundContext;
-/// # use fyrox_sound::source::SoundSourceBuilder;
-/// let context = SoundContext::new();
-/// let mut state = context.state();
+/// use audio::dissection::bus::AudioBus;
+/// use audio::dissection::engine::SoundContext;
+/// use audio::dissection::source::SoundSource;
+///
+/// let mut context = SoundContext::new();
 ///
 /// let sfx_bus = AudioBus::new("SFX".to_string());
-/// let bus_graph = state.bus_graph_mut();
+/// let bus_graph = context.bus_graph_mut();
 /// let primary_bus = bus_graph.primary_bus_handle();
-/// bus_graph.add_bus(sfx_bus, primary_bus);
+/// bus_graph.add_bus(sfx_bus, primary_bus).unwrap();
 ///
 /// // Create a source and implicitly bind to the SFX audio bus. By default each source
 /// // is bound to the primary audio bus.
-/// state.add_source(SoundSourceBuilder::new().with_bus("SFX").build().unwrap());
+/// let mut source = SoundSource::default();
+/// source.set_bus("SFX");
+/// let source = context.add_source(source);
+/// assert_eq!(context.misrouted_sources().count(), 0);
 /// ```
 ///
 /// If you delete an audio bus to which a bunch of sound sources is bound, then they will simply stop playing.
+/// Such sources are reported by [`crate::dissection::engine::SoundContext::misrouted_sources`].
+///
+/// # Aux sends
+///
+/// Besides the normal routing, sound sources and audio buses could send a copy of their signal to other
+/// buses via aux sends (see [`AuxSend`]). Sends of buses are taken into account when the processing order is
+/// built: a bus is processed only after every bus that sends to it. Sends that would create a loop are
+/// rejected by [`AudioBusGraph::add_send`].
+///
+/// # Ambisonic mode
+///
+/// In [`AudioBusGraphMode::Ambisonic`] mode each bus has an additional B-format input (see
+/// [`crate::dissection::ambisonics`]). Spatial sound sources are encoded into it by their direction
+/// instead of being panned. B-format signal follows the same routing as the stereo signal (and it is
+/// scaled by gain of each bus), but bus effects are not applied to it. At the output the sound field is
+/// rotated with the listener and decoded to the speaker layout of the decoder. Decoded speaker feeds are
+/// available via [`AudioBusGraph::decoded_output`], their stereo fold-down is mixed into the output
+/// device buffer.
 #[derive(Default, Debug, Clone)]
 pub struct AudioBusGraph {
     buses: Pool<AudioBus>,
     root: Handle<AudioBus>,
+    // Processing order of the buses: every bus goes after all of its children and all buses that send to it.
+    // It is updated on every change of the graph structure, so rendering doesn't need to traverse the graph.
+    order: Vec<Handle<AudioBus>>,
+    // Incremented on every change of the graph that could affect resolution of bus names.
+    version: u64,
+    mode: AudioBusGraphMode,
+    // B-format output of the primary bus.
+    ambisonic_output: Vec<BFormatSample>,
+    // Interleaved speaker feeds decoded from the ambisonic output.
+    decoded_output: Vec<f32>,
+    scratch: GraphScratch,
+}
+
+// Scratch space of graph traversals. It is kept in the graph, so changes of the routing (which are applied
+// on the render thread) don't allocate memory once the graph stops growing.
+#[derive(Default, Debug, Clone)]
+struct GraphScratch {
+    handles: Vec<Handle<AudioBus>>,
+    stack: Vec<Handle<AudioBus>>,
+    // Per-bus counters indexed by handle index.
+    counts: Vec<Option<usize>>,
+}
+
+/// Defines how audio bus graph handles spatial sound sources.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum AudioBusGraphMode {
+    /// Spatial sound sources are panned directly to stereo.
+    #[default]
+    Stereo,
+    /// Spatial sound sources are encoded into first-order Ambisonics and decoded with the given decoder at
+    /// the output. See [`AudioBusGraph`] docs for more info.
+    Ambisonic(AmbisonicDecoder),
 }
 
 impl AudioBusGraph {
@@ -295,30 +960,195 @@ impl AudioBusGraph {
         let root = AudioBus::new(Self::PRIMARY_BUS.to_string());
         let mut buses = Pool::new();
         let root = buses.spawn(root);
-        Self { buses, root }
+        let mut graph = Self {
+            buses,
+            root,
+            ..Default::default()
+        };
+        graph.update_order();
+        graph
+    }
+
+    // Rebuilds processing order of the buses. Buses connected to the root are collected breadth-first, then
+    // sorted topologically: a bus is ready once all of its inputs (children and buses that send to it) are
+    // processed. Buses that aren't connected to the root are not processed.
+    fn update_order(&mut self) {
+        self.version += 1;
+        let mut order = std::mem::take(&mut self.order);
+        order.clear();
+        let mut scratch = std::mem::take(&mut self.scratch);
+        let GraphScratch {
+            handles: connected,
+            stack: ready,
+            counts: pending,
+        } = &mut scratch;
+
+        connected.clear();
+        if self.buses.is_valid_handle(self.root) {
+            connected.push(self.root);
+        }
+        let mut i = 0;
+        while i < connected.len() {
+            if let Some(bus) = self.buses.try_borrow(connected[i]) {
+                connected.extend_from_slice(&bus.child_buses);
+            }
+            i += 1;
+        }
+
+        // Amount of unprocessed inputs of each connected bus, indexed by handle index. `None` for buses that
+        // aren't connected.
+        pending.clear();
+        pending.resize(self.buses.get_capacity() as usize, None);
+        for &handle in connected.iter() {
+            pending[handle.index() as usize] = Some(0usize);
+        }
+        for &handle in connected.iter() {
+            for output in self.outputs(handle) {
+                if let Some(Some(count)) = pending.get_mut(output.index() as usize) {
+                    *count += 1;
+                }
+            }
+        }
+
+        ready.clear();
+        ready.extend(
+            connected
+                .iter()
+                .copied()
+                .filter(|handle| pending[handle.index() as usize] == Some(0)),
+        );
+        while let Some(handle) = ready.pop() {
+            order.push(handle);
+            for output in self.outputs(handle) {
+                if let Some(Some(count)) = pending.get_mut(output.index() as usize) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(self.buses.handle_from_index(output.index()));
+                    }
+                }
+            }
+        }
+
+        // Loops are rejected when the graph is changed, but buses of a loop still must be rendered somehow.
+        if order.len() < connected.len() {
+            for &handle in connected.iter() {
+                if pending[handle.index() as usize] != Some(0) {
+                    order.push(handle);
+                }
+            }
+        }
+        self.order = order;
+        self.scratch = scratch;
+    }
+
+    // Returns handles of the buses the signal of the given bus goes to: its parent and targets of its sends.
+    fn outputs(&self, handle: Handle<AudioBus>) -> impl Iterator<Item = Handle<AudioBus>> + '_ {
+        self.buses
+            .try_borrow(handle)
+            .into_iter()
+            .flat_map(|bus| {
+                std::iter::once(bus.parent_bus).chain(bus.sends.iter().map(|send| send.target))
+            })
+            .filter(|&output| self.buses.is_valid_handle(output))
+    }
+
+    // Returns `true` if the signal of `from` bus goes to `to` bus, directly or through other buses.
+    fn feeds(
+        &self,
+        from: Handle<AudioBus>,
+        to: Handle<AudioBus>,
+        scratch: &mut GraphScratch,
+    ) -> bool {
+        let GraphScratch {
+            stack,
+            counts: visited,
+            ..
+        } = scratch;
+        visited.clear();
+        visited.resize(self.buses.get_capacity() as usize, None);
+        stack.clear();
+        stack.push(from);
+        while let Some(handle) = stack.pop() {
+            if handle == to {
+                return true;
+            }
+            if let Some(visited @ None) = visited.get_mut(handle.index() as usize) {
+                *visited = Some(1);
+                stack.extend(self.outputs(handle));
+            }
+        }
+        false
+    }
+
+    // Same as `feeds`, but uses the scratch space of the graph.
+    fn feeds_mut(&mut self, from: Handle<AudioBus>, to: Handle<AudioBus>) -> bool {
+        let mut scratch = std::mem::take(&mut self.scratch);
+        let feeds = self.feeds(from, to, &mut scratch);
+        self.scratch = scratch;
+        feeds
+    }
+
+    /// Sets new mode of the graph. See [`AudioBusGraphMode`] docs for more info.
+    pub fn set_mode(&mut self, mode: AudioBusGraphMode) {
+        self.mode = mode;
+    }
+
+    /// Returns current mode of the graph.
+    pub fn mode(&self) -> &AudioBusGraphMode {
+        &self.mode
+    }
+
+    /// Returns `true` if the graph is in ambisonic mode.
+    pub fn is_ambisonic(&self) -> bool {
+        matches!(self.mode, AudioBusGraphMode::Ambisonic(_))
     }
 
-    /// Adds a new audio bus to the graph and attaches it to the given parent. `parent` handle must be
-    /// valid, otherwise the method will panic. In most cases you can use primary bus as a parent.
+    /// Returns interleaved speaker feeds of the last rendered block decoded from the ambisonic output. Channel
+    /// order is defined by the speaker layout of the decoder. Empty if the graph is not in ambisonic mode.
+    pub fn decoded_output(&self) -> &[f32] {
+        &self.decoded_output
+    }
+
+    /// Adds a new audio bus to the graph and attaches it to the given parent. Fails if `parent` handle is
+    /// invalid. In most cases you can use primary bus as a parent.
     ///
     /// # Examples
     ///
     /// ```rust
-    /// use fyrox_sound::bus::{AudioBus, AudioBusGraph};
+    /// use audio::dissection::bus::{AudioBus, AudioBusGraph};
     ///
     /// // By default it has one primary audio bus.
     /// let mut graph = AudioBusGraph::new();
     ///
     /// // Add another bus to the graph and attach it to the primary bus.
     /// let primary_bus_handle = graph.primary_bus_handle();
-    /// graph.add_bus(AudioBus::new("SFX".to_owned()), primary_bus_handle);
-    ///
+    /// graph
+    ///     .add_bus(AudioBus::new("SFX".to_owned()), primary_bus_handle)
+    ///     .unwrap();
     /// ```
-    pub fn add_bus(&mut self, mut bus: AudioBus, parent: Handle<AudioBus>) -> Handle<AudioBus> {
+    pub fn add_bus(
+        &mut self,
+        mut bus: AudioBus,
+        parent: Handle<AudioBus>,
+    ) -> Result<Handle<AudioBus>, BusGraphError> {
+        self.check_handle(parent)?;
         bus.parent_bus = parent;
+        bus.child_buses.clear();
+        // The new bus has no inputs yet, so its sends can't create a loop.
+        bus.sends
+            .retain(|send| self.buses.is_valid_handle(send.target));
         let bus = self.buses.spawn(bus);
         self.buses[parent].child_buses.push(bus);
-        bus
+        self.update_order();
+        Ok(bus)
+    }
+
+    fn check_handle(&self, handle: Handle<AudioBus>) -> Result<(), BusGraphError> {
+        if self.buses.is_valid_handle(handle) {
+            Ok(())
+        } else {
+            Err(BusGraphError::InvalidHandle(handle))
+        }
     }
 
     fn unlink_internal(&mut self, node_handle: Handle<AudioBus>) {
@@ -334,40 +1164,238 @@ impl AudioBusGraph {
         }
     }
 
-    /// Attaches the `child` audio bus to the `parent` audio bus. **Important:** this method does not
-    /// checks for any loops, adding any loops to the graph will cause infinite loop in the mixer thread.
-    #[inline]
-    pub fn link_buses(&mut self, child: Handle<AudioBus>, parent: Handle<AudioBus>) {
+    /// Returns `true` if `descendant` bus is `ancestor` bus or one of its (direct or indirect) children.
+    pub fn is_descendant_of(
+        &self,
+        descendant: Handle<AudioBus>,
+        ancestor: Handle<AudioBus>,
+    ) -> bool {
+        let mut current = descendant;
+        // The amount of steps is limited, so it terminates even on malformed graphs.
+        for _ in 0..=self.buses.alive_count() {
+            if current == ancestor {
+                return true;
+            }
+            match self.buses.try_borrow(current) {
+                Some(bus) => current = bus.parent_bus,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Attaches the `child` audio bus to the `parent` audio bus. Fails if any of the handles is invalid, if
+    /// `child` is the primary bus or if the signal of `parent` already goes to `child` (the link would create
+    /// a loop), for example when `parent` is one of descendants of `child`.
+    pub fn link_buses(
+        &mut self,
+        child: Handle<AudioBus>,
+        parent: Handle<AudioBus>,
+    ) -> Result<(), BusGraphError> {
+        self.check_handle(child)?;
+        self.check_handle(parent)?;
+        if child == self.root {
+            return Err(BusGraphError::PrimaryBus);
+        }
+        if self.feeds_mut(parent, child) {
+            return Err(BusGraphError::Cycle { child, parent });
+        }
+
         self.unlink_internal(child);
         self.buses[child].parent_bus = parent;
         self.buses[parent].child_buses.push(child);
+        self.update_order();
+        Ok(())
     }
 
-    pub(crate) fn try_get_bus_input_buffer(&mut self, name: &str) -> Option<&mut [(f32, f32)]> {
-        self.buses.iter_mut().find_map(|bus| {
-            if bus.name == name {
-                Some(bus.input_buffer())
-            } else {
-                None
+    /// Adds an aux send to the audio bus at the given handle and returns its index in the list of sends of
+    /// the bus. Fails if any of the handles is invalid or if the signal of the target bus already goes to the
+    /// bus (the send would create a loop). See [`AuxSend`] docs for more info.
+    pub fn add_send(
+        &mut self,
+        bus: Handle<AudioBus>,
+        send: AuxSend,
+    ) -> Result<usize, BusGraphError> {
+        self.check_handle(bus)?;
+        self.check_handle(send.target)?;
+        if self.feeds_mut(send.target, bus) {
+            return Err(BusGraphError::Cycle {
+                child: bus,
+                parent: send.target,
+            });
+        }
+
+        let sends = &mut self.buses[bus].sends;
+        sends.push(send);
+        let index = sends.len() - 1;
+        self.update_order();
+        Ok(index)
+    }
+
+    /// Removes an aux send at the given index from the audio bus at the given handle.
+    pub fn remove_send(&mut self, bus: Handle<AudioBus>, index: usize) -> Option<AuxSend> {
+        let sends = &mut self.buses.try_borrow_mut(bus)?.sends;
+        if index >= sends.len() {
+            return None;
+        }
+        let send = sends.remove(index);
+        self.update_order();
+        Some(send)
+    }
+
+    // Removes sends to buses that no longer exist.
+    fn remove_dangling_sends(&mut self) {
+        let buses = &mut self.buses;
+        for index in 0..buses.get_capacity() {
+            let handle = buses.handle_from_index(index);
+            let Some(mut sends) = buses
+                .try_borrow_mut(handle)
+                .map(|bus| std::mem::take(&mut bus.sends))
+            else {
+                continue;
+            };
+            sends.retain(|send| buses.is_valid_handle(send.target));
+            buses[handle].sends = sends;
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn try_get_bus_input_buffers(
+        &mut self,
+        handle: Handle<AudioBus>,
+    ) -> Option<(&mut [(f32, f32)], &mut [BFormatSample])> {
+        self.buses
+            .try_borrow_mut(handle)
+            .map(|bus| bus.input_buffers())
+    }
+
+    /// Returns a handle of the first audio bus with the given name, or [`Handle::NONE`] if there's no such
+    /// bus. This method scans every bus of the graph.
+    pub fn find_bus_by_name(&self, name: &str) -> Handle<AudioBus> {
+        self.buses
+            .pair_iter()
+            .find_map(|(handle, bus)| (bus.name == name).then_some(handle))
+            .unwrap_or_default()
+    }
+
+    /// Returns a number that changes every time the graph is changed in a way that could affect resolution of
+    /// bus names (structure changes and mutable access to buses).
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Removes an audio bus at the given handle. Children of the bus are attached to its parent. Primary bus
+    /// can't be removed. Returned bus has no parent and no children.
+    pub fn remove_bus(&mut self, handle: Handle<AudioBus>) -> Result<AudioBus, BusGraphError> {
+        self.check_handle(handle)?;
+        if handle == self.root {
+            return Err(BusGraphError::PrimaryBus);
+        }
+
+        // Children are attached to the parent of the bus, or to the primary bus if the bus has no parent.
+        let parent = self.buses[handle].parent_bus;
+        let parent = if self.buses.is_valid_handle(parent) {
+            parent
+        } else {
+            self.root
+        };
+        self.unlink_internal(handle);
+        let mut bus = self.buses.free(handle);
+        for child in bus.child_buses.drain(..) {
+            if let Some(child_ref) = self.buses.try_borrow_mut(child) {
+                child_ref.parent_bus = parent;
+                self.buses[parent].child_buses.push(child);
             }
-        })
+        }
+        self.remove_dangling_sends();
+        self.update_order();
+
+        Ok(bus)
     }
 
-    /// Removes an audio bus at the given handle.
-    pub fn remove_bus(&mut self, handle: Handle<AudioBus>) -> AudioBus {
-        assert_ne!(handle, self.root);
+    /// Removes an audio bus at the given handle with all of its descendants. Primary bus can't be removed.
+    /// Removed buses are returned in the order they were removed (the given bus goes first).
+    pub fn remove_bus_tree(
+        &mut self,
+        handle: Handle<AudioBus>,
+    ) -> Result<Vec<AudioBus>, BusGraphError> {
+        self.check_handle(handle)?;
+        if handle == self.root {
+            return Err(BusGraphError::PrimaryBus);
+        }
 
-        let bus = self.buses.free(handle);
-        let parent_bus = &mut self.buses[bus.parent_bus];
+        self.unlink_internal(handle);
+        let mut removed = Vec::new();
+        let mut stack = vec![handle];
+        while let Some(handle) = stack.pop() {
+            if let Some(mut bus) = self.buses.try_free(handle) {
+                bus.parent_bus = Handle::NONE;
+                stack.append(&mut bus.child_buses);
+                removed.push(bus);
+            }
+        }
+        self.remove_dangling_sends();
+        self.update_order();
 
-        let position = parent_bus
-            .child_buses
-            .iter()
-            .position(|h| *h == handle)
-            .expect("Malformed bus graph!");
-        parent_bus.child_buses.remove(position);
+        Ok(removed)
+    }
 
-        bus
+    /// Checks that the graph is well-formed: the primary bus exists and has no parent, every parent/child
+    /// link is consistent in both directions, every bus is reachable from the primary bus (which means
+    /// there are no loops) and aux sends point to existing buses and do not create loops. Buses that were
+    /// taken out of the graph by [`Self::try_take_reserve_bus`] make the graph invalid until they're put
+    /// back.
+    pub fn validate(&self) -> Result<(), BusGraphError> {
+        let mut scratch = GraphScratch::default();
+        let root = self
+            .buses
+            .try_borrow(self.root)
+            .ok_or(BusGraphError::InvalidHandle(self.root))?;
+        if root.parent_bus.is_some() {
+            return Err(BusGraphError::InconsistentLink {
+                bus: self.root,
+                parent: root.parent_bus,
+            });
+        }
+
+        for (handle, bus) in self.buses.pair_iter() {
+            if handle != self.root {
+                let linked = self.buses.try_borrow(bus.parent_bus).is_some_and(|parent| {
+                    parent.child_buses.iter().filter(|&&h| h == handle).count() == 1
+                });
+                if !linked {
+                    return Err(BusGraphError::InconsistentLink {
+                        bus: handle,
+                        parent: bus.parent_bus,
+                    });
+                }
+            }
+
+            for &child in bus.child_buses.iter() {
+                if self.buses.try_borrow(child).map(|child| child.parent_bus) != Some(handle) {
+                    return Err(BusGraphError::InconsistentLink {
+                        bus: child,
+                        parent: handle,
+                    });
+                }
+            }
+
+            if !self.is_descendant_of(handle, self.root) {
+                return Err(BusGraphError::Unreachable(handle));
+            }
+
+            for send in bus.sends.iter() {
+                self.check_handle(send.target)?;
+                if self.feeds(send.target, handle, &mut scratch) {
+                    return Err(BusGraphError::Cycle {
+                        child: handle,
+                        parent: send.target,
+                    });
+                }
+            }
+        }
+
+        Ok(())
     }
 
     /// Returns a handle of the primary audio bus. Primary bus outputs its samples directly to an audio playback
@@ -383,6 +1411,8 @@ impl AudioBusGraph {
 
     /// Returns a reference to the primary audio bus.
     pub fn primary_bus_mut(&mut self) -> &mut AudioBus {
+        // The name of the bus could be changed.
+        self.version += 1;
         &mut self.buses[self.root]
     }
 
@@ -393,6 +1423,8 @@ impl AudioBusGraph {
 
     /// Tries to borrow an audio bus by its handle.
     pub fn try_get_bus_mut(&mut self, handle: Handle<AudioBus>) -> Option<&mut AudioBus> {
+        // The name of the bus could be changed.
+        self.version += 1;
         self.buses.try_borrow_mut(handle)
     }
 
@@ -412,19 +1444,24 @@ impl AudioBusGraph {
         &mut self,
         handle: Handle<AudioBus>,
     ) -> Option<(Ticket<AudioBus>, AudioBus)> {
-        self.buses.try_take_reserve(handle)
+        let result = self.buses.try_take_reserve(handle);
+        self.update_order();
+        result
     }
 
     /// Puts the audio bus back to graph on its previous place by the given ticket. See [`Pool::put_back`] method docs
     /// for more info.
     pub fn put_bus_back(&mut self, ticket: Ticket<AudioBus>, bus: AudioBus) -> Handle<AudioBus> {
-        self.buses.put_back(ticket, bus)
+        let handle = self.buses.put_back(ticket, bus);
+        self.update_order();
+        handle
     }
 
     /// Forget an audio bus ticket making the respective handle free again. See [`Pool::forget_ticket`] method docs for
     /// more info.
     pub fn forget_bus_ticket(&mut self, ticket: Ticket<AudioBus>) {
-        self.buses.forget_ticket(ticket)
+        self.buses.forget_ticket(ticket);
+        self.update_order();
     }
 
     /// Returns an iterator over each audio bus in the graph.
@@ -434,6 +1471,8 @@ impl AudioBusGraph {
 
     /// Returns an iterator over each audio bus in the graph.
     pub fn buses_iter_mut(&mut self) -> impl Iterator<Item = &mut AudioBus> {
+        // The name of the bus could be changed.
+        self.version += 1;
         self.buses.iter_mut()
     }
 
@@ -446,47 +1485,206 @@ impl AudioBusGraph {
     pub fn buses_pair_iter_mut(
         &mut self,
     ) -> impl Iterator<Item = (Handle<AudioBus>, &mut AudioBus)> {
+        self.version += 1;
         self.buses.pair_iter_mut()
     }
 
     pub(crate) fn begin_render(&mut self, output_device_buffer_size: usize) {
+        let ambisonic = self.is_ambisonic();
         for bus in self.buses.iter_mut() {
-            bus.begin_render(output_device_buffer_size);
+            bus.begin_render(output_device_buffer_size, ambisonic);
+        }
+
+        self.ambisonic_output.clear();
+        self.decoded_output.clear();
+        if let AudioBusGraphMode::Ambisonic(decoder) = &self.mode {
+            self.ambisonic_output
+                .resize(output_device_buffer_size, BFormatSample::default());
+            self.decoded_output.resize(
+                output_device_buffer_size * decoder.layout().channel_count(),
+                0.0,
+            );
         }
     }
 
-    pub(crate) fn end_render(&mut self, output_device_buffer: &mut [(f32, f32)]) {
-        let mut leafs = Vec::new();
-        for (handle, bus) in self.buses.pair_iter_mut() {
-            bus.apply_effects();
+    pub(crate) fn update_automation(&mut self, time: u64, amount: usize) {
+        for bus in self.buses.iter_mut() {
+            bus.update_automation(time, amount);
+        }
+    }
 
-            if bus.child_buses.is_empty() {
-                leafs.push(handle);
+    // Marks buses on the path of soloed buses, see `AudioBus::set_soloed`. Returns `true` if any bus is soloed.
+    fn update_solo(&mut self) -> bool {
+        let mut solo = false;
+        for &handle in self.order.iter() {
+            if let Some(bus) = self.buses.try_borrow_mut(handle) {
+                bus.solo_input = bus.soloed;
+                bus.solo_output = bus.soloed;
+                solo |= bus.soloed;
             }
         }
+        if !solo {
+            return false;
+        }
 
-        for mut leaf in leafs {
-            while leaf.is_some() {
-                let ctx = self.buses.begin_multi_borrow();
+        // Inputs of a bus go before it in the processing order, so the state flows from soloed buses to their
+        // outputs in one pass, and from soloed buses to their descendants in one reversed pass.
+        for &handle in self.order.iter() {
+            let Some(bus) = self.buses.try_borrow(handle) else {
+                continue;
+            };
+            if !bus.solo_output {
+                continue;
+            }
+            let parent = bus.parent_bus;
+            if let Some(parent) = self.buses.try_borrow_mut(parent) {
+                parent.solo_output = true;
+            }
+            for i in 0..self.buses[handle].sends.len() {
+                let target = self.buses[handle].sends[i].target;
+                if let Some(target) = self.buses.try_borrow_mut(target) {
+                    target.solo_output = true;
+                }
+            }
+        }
+        for &handle in self.order.iter().rev() {
+            let Some(bus) = self.buses.try_borrow(handle) else {
+                continue;
+            };
+            if self
+                .buses
+                .try_borrow(bus.parent_bus)
+                .is_some_and(|parent| parent.solo_input)
+            {
+                self.buses[handle].solo_input = true;
+            }
+        }
 
-                let leaf_ref = ctx.try_get_mut(leaf).expect("Malformed bus graph!");
+        true
+    }
 
-                let input_buffer = leaf_ref.ping_pong_buffer.input_ref();
-                let leaf_gain = leaf_ref.gain;
-                let mut parent_buffer = ctx.try_get_mut(leaf_ref.parent_bus);
-                let output_buffer = parent_buffer
-                    .as_mut()
-                    .map(|parent| parent.ping_pong_buffer.input_mut())
-                    // Special case for the root bus - it writes directly to the output device buffer.
-                    .unwrap_or(&mut *output_device_buffer);
-                for ((input_left, input_right), (output_left, output_right)) in
-                    input_buffer.iter().zip(output_buffer)
-                {
-                    *output_left += *input_left * leaf_gain;
-                    *output_right += *input_right * leaf_gain;
+    pub(crate) fn end_render(
+        &mut self,
+        output_device_buffer: &mut [(f32, f32)],
+        listener: &Listener,
+    ) {
+        // Children are processed before their parents: effects of a bus are applied once all of its children
+        // (and all buses that send to it) are mixed into it, then the result is passed to the parent and to
+        // targets of the sends.
+        let solo = self.update_solo();
+        let frames = output_device_buffer.len();
+        for &handle in self.order.iter() {
+            let ctx = self.buses.begin_multi_borrow();
+
+            let Ok(mut bus) = ctx.try_get_mut(handle) else {
+                // The bus is taken out of the graph.
+                continue;
+            };
+            bus.apply_effects(frames);
+
+            let audible = !bus.muted && (!solo || bus.solo_input || bus.solo_output);
+            let audibility = if audible { 1.0 } else { 0.0 };
+            let fade = bus.audibility.get_or_insert(Fade::new(audibility));
+            fade.set_target(audibility);
+            let fade = *fade;
+            let gain = bus.gain;
+            let last_gain = bus.last_gain.replace(gain).unwrap_or(gain);
+            // Automated gain is applied to the signal per frame once pre-fader sends are mixed, the rest of
+            // the chain sees unit gain.
+            let (last_gain, gain) = if bus.gain_curve.is_empty() {
+                (last_gain, gain)
+            } else {
+                (1.0, 1.0)
+            };
+
+            let AudioBus {
+                sends,
+                ping_pong_buffer,
+                ambisonic_buffer,
+                gain_curve,
+                ..
+            } = &mut *bus;
+            for tap in [SendTap::PreFader, SendTap::PostFader] {
+                if tap == SendTap::PostFader && !gain_curve.is_empty() {
+                    for ((left, right), gain) in ping_pong_buffer
+                        .input_mut()
+                        .iter_mut()
+                        .zip(gain_curve.iter())
+                    {
+                        *left *= *gain;
+                        *right *= *gain;
+                    }
+                    for (sample, gain) in ambisonic_buffer.iter_mut().zip(gain_curve.iter()) {
+                        *sample = *sample * *gain;
+                    }
                 }
 
-                leaf = leaf_ref.parent_bus;
+                for send in sends.iter_mut().filter(|send| send.tap == tap) {
+                    let (from, to) = send.level_ramp();
+                    let Ok(mut target) = ctx.try_get_mut(send.target) else {
+                        continue;
+                    };
+                    let (fader_from, fader_to) = match tap {
+                        SendTap::PreFader => (1.0, 1.0),
+                        SendTap::PostFader => (last_gain, gain),
+                    };
+                    mix_with_ramp_and_fade(
+                        ping_pong_buffer.input_ref(),
+                        target.ping_pong_buffer.input_mut(),
+                        from * fader_from,
+                        to * fader_to,
+                        &fade,
+                    );
+                    mix_ambisonic_with_ramp_and_fade(
+                        ambisonic_buffer,
+                        &mut target.ambisonic_buffer,
+                        from * fader_from,
+                        to * fader_to,
+                        &fade,
+                    );
+                }
+            }
+
+            let mut parent_buffer = ctx.try_get_mut(bus.parent_bus).ok();
+            let output_buffer = parent_buffer
+                .as_mut()
+                .map(|parent| parent.ping_pong_buffer.input_mut())
+                // Special case for the root bus - it writes directly to the output device buffer.
+                .unwrap_or(&mut *output_device_buffer);
+            let input = bus.ping_pong_buffer.input_ref();
+            mix_with_ramp_and_fade(input, output_buffer, last_gain, gain, &fade);
+
+            let ambisonic_output_buffer = parent_buffer
+                .as_mut()
+                .map(|parent| parent.ambisonic_buffer.as_mut_slice())
+                .unwrap_or(&mut self.ambisonic_output);
+            let ambisonic_input = &bus.ambisonic_buffer;
+            mix_ambisonic_with_ramp_and_fade(
+                ambisonic_input,
+                ambisonic_output_buffer,
+                last_gain,
+                gain,
+                &fade,
+            );
+
+            if let Some(fade) = bus.audibility.as_mut() {
+                fade.advance(frames);
+            }
+        }
+
+        if let AudioBusGraphMode::Ambisonic(decoder) = &self.mode {
+            let rotation = world_to_listener(listener);
+            let layout = decoder.layout();
+            for ((sample, frame), (output_left, output_right)) in self
+                .ambisonic_output
+                .iter()
+                .zip(self.decoded_output.chunks_exact_mut(layout.channel_count()))
+                .zip(output_device_buffer.iter_mut())
+            {
+                decoder.decode(&sample.rotate(&rotation), frame);
+                let (left, right) = layout.downmix_to_stereo(frame);
+                *output_left += left;
+                *output_right += right;
             }
         }
     }
@@ -495,8 +1693,14 @@ impl AudioBusGraph {
 #[cfg(test)]
 mod test {
     use crate::dissection::{
-        bus::{AudioBus, AudioBusGraph},
-        effects::{Attenuate, Effect},
+        ambisonics::{AmbisonicDecoder, BFormatSample, SpeakerLayout},
+        bus::{
+            AudioBus, AudioBusGraph, AudioBusGraphMode, AuxSend, BusGraphError, SendTap,
+            FADE_FRAMES,
+        },
+        effects::{Attenuate, Effect, EffectRenderTrait},
+        listener::Listener,
+        pool::Handle,
     };
 
     #[test]
@@ -505,23 +1709,27 @@ mod test {
 
         let mut graph = AudioBusGraph::new();
 
-        let bus1 = graph.add_bus(AudioBus::new("Bus1".to_string()), graph.root);
-        let bus2 = graph.add_bus(AudioBus::new("Bus2".to_string()), bus1);
+        let bus1 = graph
+            .add_bus(AudioBus::new("Bus1".to_string()), graph.root)
+            .unwrap();
+        let bus2 = graph
+            .add_bus(AudioBus::new("Bus2".to_string()), bus1)
+            .unwrap();
 
         graph.begin_render(output_buffer.len());
 
         // Simulate output of sound sources to each bus.
-        for (left, right) in graph.buses[bus1].input_buffer() {
+        for (left, right) in graph.buses[bus1].input_buffers().0 {
             *left = 1.0;
             *right = 1.0;
         }
 
-        for (left, right) in graph.buses[bus2].input_buffer() {
+        for (left, right) in graph.buses[bus2].input_buffers().0 {
             *left = 1.0;
             *right = 1.0;
         }
 
-        graph.end_render(&mut output_buffer);
+        graph.end_render(&mut output_buffer, &Listener::new());
 
         assert_eq!(output_buffer[0], (2.0, 2.0));
     }
@@ -535,12 +1743,12 @@ mod test {
         graph.begin_render(output_buffer.len());
 
         // Simulate output of sound sources to each bus.
-        for (left, right) in graph.buses[graph.root].input_buffer() {
+        for (left, right) in graph.buses[graph.root].input_buffers().0 {
             *left = 1.0;
             *right = 1.0;
         }
 
-        graph.end_render(&mut output_buffer);
+        graph.end_render(&mut output_buffer, &Listener::new());
 
         assert_eq!(output_buffer[0], (1.0, 1.0));
     }
@@ -555,27 +1763,539 @@ mod test {
         bus1.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
         bus1.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
 
-        let bus1 = graph.add_bus(bus1, graph.root);
+        let bus1 = graph.add_bus(bus1, graph.root).unwrap();
 
         let mut bus2 = AudioBus::new("Bus2".to_string());
         bus2.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
-        let bus2 = graph.add_bus(bus2, bus1);
+        let bus2 = graph.add_bus(bus2, bus1).unwrap();
 
         graph.begin_render(output_buffer.len());
 
         // Simulate output of sound sources to each bus.
-        for (left, right) in graph.buses[bus1].input_buffer() {
+        for (left, right) in graph.buses[bus1].input_buffers().0 {
             *left = 1.0;
             *right = 1.0;
         }
 
-        for (left, right) in graph.buses[bus2].input_buffer() {
+        for (left, right) in graph.buses[bus2].input_buffers().0 {
             *left = 1.0;
             *right = 1.0;
         }
 
-        graph.end_render(&mut output_buffer);
+        graph.end_render(&mut output_buffer, &Listener::new());
+
+        // Bus2: 1.0 * 0.5 = 0.5, Bus1: (1.0 + 0.5) * 0.5 * 0.5 = 0.375
+        assert_eq!(output_buffer[0], (0.375, 0.375));
+    }
+
+    fn fill_input(graph: &mut AudioBusGraph, bus: Handle<AudioBus>, value: f32) {
+        for sample in graph.buses[bus].input_buffers().0 {
+            *sample = (value, value);
+        }
+    }
+
+    #[test]
+    fn test_wide_tree_data_flow() {
+        let mut output_buffer = [(0.0f32, 0.0f32)];
+
+        let mut graph = AudioBusGraph::new();
+        graph
+            .primary_bus_mut()
+            .add_effect(Effect::Attenuate(Attenuate::new(0.5)));
+
+        // A parent with two children must be passed to its parent only once.
+        let group = graph
+            .add_bus(AudioBus::new("Group".to_string()), graph.root)
+            .unwrap();
+        let children = (0..2)
+            .map(|i| {
+                graph
+                    .add_bus(AudioBus::new(format!("Child{i}")), group)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut attenuated = AudioBus::new("Attenuated".to_string());
+        attenuated.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
+        let attenuated = graph.add_bus(attenuated, graph.root).unwrap();
+
+        graph.begin_render(output_buffer.len());
+        for &child in children.iter() {
+            fill_input(&mut graph, child, 1.0);
+        }
+        fill_input(&mut graph, attenuated, 1.0);
+        graph.end_render(&mut output_buffer, &Listener::new());
+
+        // Primary: (2.0 + 0.5) * 0.5
+        assert_eq!(output_buffer[0], (1.25, 1.25));
+    }
+
+    #[test]
+    fn test_deep_tree_data_flow() {
+        let mut output_buffer = [(0.0f32, 0.0f32)];
 
+        let mut graph = AudioBusGraph::new();
+        let mut parent = graph.root;
+        let mut chain = Vec::new();
+        for i in 0..4 {
+            let mut bus = AudioBus::new(format!("Bus{i}"));
+            // Effects of intermediate buses must see the audio of their children.
+            bus.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
+            parent = graph.add_bus(bus, parent).unwrap();
+            chain.push(parent);
+        }
+
+        graph.begin_render(output_buffer.len());
+        for &bus in chain.iter() {
+            fill_input(&mut graph, bus, 1.0);
+        }
+        graph.end_render(&mut output_buffer, &Listener::new());
+
+        // Bus3: 0.5, Bus2: 0.75, Bus1: 0.875, Bus0: 0.9375
+        assert_eq!(output_buffer[0], (0.9375, 0.9375));
+
+        // Relinking updates the order.
+        graph.link_buses(chain[3], graph.root).unwrap();
+        graph.begin_render(output_buffer.len());
+        output_buffer[0] = (0.0, 0.0);
+        fill_input(&mut graph, chain[3], 1.0);
+        graph.end_render(&mut output_buffer, &Listener::new());
+        assert_eq!(output_buffer[0], (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_link_buses_rejects_cycles() {
+        let mut graph = AudioBusGraph::new();
+        let a = graph
+            .add_bus(AudioBus::new("A".to_string()), graph.root)
+            .unwrap();
+        let b = graph.add_bus(AudioBus::new("B".to_string()), a).unwrap();
+        let c = graph.add_bus(AudioBus::new("C".to_string()), b).unwrap();
+
+        assert_eq!(
+            graph.link_buses(a, c),
+            Err(BusGraphError::Cycle {
+                child: a,
+                parent: c
+            })
+        );
+        assert_eq!(
+            graph.link_buses(a, a),
+            Err(BusGraphError::Cycle {
+                child: a,
+                parent: a
+            })
+        );
+        assert_eq!(
+            graph.link_buses(graph.root, a),
+            Err(BusGraphError::PrimaryBus)
+        );
+        assert_eq!(
+            graph.link_buses(a, Handle::NONE),
+            Err(BusGraphError::InvalidHandle(Handle::NONE))
+        );
+        assert!(graph.validate().is_ok());
+
+        // Moving a subtree elsewhere is fine.
+        graph.link_buses(c, graph.root).unwrap();
+        graph.link_buses(a, c).unwrap();
+        assert!(graph.validate().is_ok());
+        assert!(graph.is_descendant_of(b, c));
+    }
+
+    #[test]
+    fn test_remove_bus() {
+        let mut graph = AudioBusGraph::new();
+        let a = graph
+            .add_bus(AudioBus::new("A".to_string()), graph.root)
+            .unwrap();
+        let b = graph.add_bus(AudioBus::new("B".to_string()), a).unwrap();
+        let c = graph.add_bus(AudioBus::new("C".to_string()), b).unwrap();
+        let d = graph.add_bus(AudioBus::new("D".to_string()), b).unwrap();
+
+        assert_eq!(
+            graph.remove_bus(graph.root).unwrap_err(),
+            BusGraphError::PrimaryBus
+        );
+
+        // Children are reparented.
+        let removed = graph.remove_bus(b).unwrap();
+        assert!(removed.children().is_empty());
+        assert_eq!(graph.try_get_bus_ref(a).unwrap().children(), &[c, d]);
+        assert_eq!(graph.try_get_bus_ref(c).unwrap().parent(), a);
+        assert!(graph.validate().is_ok());
+        assert_eq!(
+            graph.remove_bus(b).unwrap_err(),
+            BusGraphError::InvalidHandle(b)
+        );
+
+        // Whole subtree is removed.
+        let removed = graph.remove_bus_tree(a).unwrap();
+        assert_eq!(removed.len(), 3);
+        assert_eq!(graph.len(), 1);
+        assert!(graph.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut graph = AudioBusGraph::new();
+        let a = graph
+            .add_bus(AudioBus::new("A".to_string()), graph.root)
+            .unwrap();
+        assert!(graph.validate().is_ok());
+
+        graph.buses[a].parent_bus = Handle::NONE;
+        assert!(matches!(
+            graph.validate(),
+            Err(BusGraphError::InconsistentLink { bus, .. }) if bus == a
+        ));
+    }
+
+    #[test]
+    fn test_bus_sends_data_flow() {
+        let mut output_buffer = [(0.0f32, 0.0f32)];
+
+        let mut graph = AudioBusGraph::new();
+        // The reverb bus is deeper than the dry bus, but it must be processed after it anyway.
+        let group = graph
+            .add_bus(AudioBus::new("Group".to_string()), graph.root)
+            .unwrap();
+        let mut reverb = AudioBus::new("Reverb".to_string());
+        reverb.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
+        let reverb = graph.add_bus(reverb, group).unwrap();
+        let mut dry = AudioBus::new("Dry".to_string());
+        dry.set_gain(0.5);
+        let dry = graph.add_bus(dry, graph.root).unwrap();
+        assert_eq!(
+            graph.add_send(dry, AuxSend::new(reverb, 1.0, SendTap::PostFader)),
+            Ok(0)
+        );
+
+        graph.begin_render(output_buffer.len());
+        fill_input(&mut graph, dry, 1.0);
+        graph.end_render(&mut output_buffer, &Listener::new());
+        // Dry: 1.0 * 0.5 = 0.5, Reverb: 0.5 * 0.5 = 0.25
         assert_eq!(output_buffer[0], (0.75, 0.75));
+
+        graph
+            .try_get_bus_mut(dry)
+            .unwrap()
+            .send_mut(0)
+            .unwrap()
+            .set_tap(SendTap::PreFader);
+        output_buffer[0] = (0.0, 0.0);
+        graph.begin_render(output_buffer.len());
+        fill_input(&mut graph, dry, 1.0);
+        graph.end_render(&mut output_buffer, &Listener::new());
+        // Dry: 1.0 * 0.5 = 0.5, Reverb: 1.0 * 0.5 = 0.5
+        assert_eq!(output_buffer[0], (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_sends_reject_cycles() {
+        let mut graph = AudioBusGraph::new();
+        let group = graph
+            .add_bus(AudioBus::new("Group".to_string()), graph.root)
+            .unwrap();
+        let reverb = graph
+            .add_bus(AudioBus::new("Reverb".to_string()), group)
+            .unwrap();
+        let dry = graph
+            .add_bus(AudioBus::new("Dry".to_string()), graph.root)
+            .unwrap();
+        graph
+            .add_send(dry, AuxSend::new(reverb, 1.0, SendTap::PostFader))
+            .unwrap();
+
+        assert_eq!(
+            graph.add_send(reverb, AuxSend::new(dry, 1.0, SendTap::PostFader)),
+            Err(BusGraphError::Cycle {
+                child: reverb,
+                parent: dry
+            })
+        );
+        assert_eq!(
+            graph.add_send(dry, AuxSend::new(dry, 1.0, SendTap::PostFader)),
+            Err(BusGraphError::Cycle {
+                child: dry,
+                parent: dry
+            })
+        );
+        assert_eq!(
+            graph.link_buses(group, dry),
+            Err(BusGraphError::Cycle {
+                child: group,
+                parent: dry
+            })
+        );
+        assert_eq!(
+            graph.add_send(dry, AuxSend::new(Handle::NONE, 1.0, SendTap::PostFader)),
+            Err(BusGraphError::InvalidHandle(Handle::NONE))
+        );
+        assert!(graph.validate().is_ok());
+
+        // Sends to removed buses are removed too.
+        graph.remove_bus_tree(group).unwrap();
+        assert!(graph.try_get_bus_ref(dry).unwrap().sends().is_empty());
+        assert!(graph.validate().is_ok());
+        assert!(graph.remove_send(dry, 0).is_none());
+    }
+
+    fn render_block(
+        graph: &mut AudioBusGraph,
+        inputs: &[(Handle<AudioBus>, f32)],
+    ) -> [(f32, f32); 4] {
+        let mut output_buffer = [(0.0f32, 0.0f32); 4];
+        graph.begin_render(output_buffer.len());
+        for &(bus, value) in inputs {
+            fill_input(graph, bus, value);
+        }
+        graph.end_render(&mut output_buffer, &Listener::new());
+        output_buffer
+    }
+
+    // Renders blocks until mute, solo and bypass fades are finished, returns the last block.
+    fn render_settled(
+        graph: &mut AudioBusGraph,
+        inputs: &[(Handle<AudioBus>, f32)],
+    ) -> [(f32, f32); 4] {
+        for _ in 0..FADE_FRAMES.div_ceil(4) {
+            render_block(graph, inputs);
+        }
+        render_block(graph, inputs)
+    }
+
+    #[test]
+    fn test_mute_and_solo() {
+        let mut graph = AudioBusGraph::new();
+        let a = graph
+            .add_bus(AudioBus::new("A".to_string()), graph.root)
+            .unwrap();
+        let a1 = graph.add_bus(AudioBus::new("A1".to_string()), a).unwrap();
+        let b = graph
+            .add_bus(AudioBus::new("B".to_string()), graph.root)
+            .unwrap();
+        let inputs = [(a, 1.0), (a1, 1.0), (b, 1.0)];
+        assert_eq!(render_block(&mut graph, &inputs)[0], (3.0, 3.0));
+
+        // Soloed bus keeps its descendants and path to the primary bus, B is faded out.
+        graph.try_get_bus_mut(a).unwrap().set_soloed(true);
+        let block = render_block(&mut graph, &inputs);
+        assert_eq!(block[0], (3.0, 3.0));
+        assert!(block[3].0 < 3.0 && block[3].0 > 2.0);
+        assert_eq!(render_settled(&mut graph, &inputs)[0], (2.0, 2.0));
+
+        graph.try_get_bus_mut(a).unwrap().set_soloed(false);
+        graph.try_get_bus_mut(a1).unwrap().set_muted(true);
+        assert_eq!(render_settled(&mut graph, &inputs)[0], (2.0, 2.0));
+
+        // Muted primary bus silences everything.
+        graph.primary_bus_mut().set_muted(true);
+        assert_eq!(render_settled(&mut graph, &inputs)[0], (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_solo_follows_sends() {
+        let mut graph = AudioBusGraph::new();
+        let reverb = graph
+            .add_bus(AudioBus::new("Reverb".to_string()), graph.root)
+            .unwrap();
+        let a = graph
+            .add_bus(AudioBus::new("A".to_string()), graph.root)
+            .unwrap();
+        graph
+            .add_send(a, AuxSend::new(reverb, 0.5, SendTap::PostFader))
+            .unwrap();
+        let b = graph
+            .add_bus(AudioBus::new("B".to_string()), graph.root)
+            .unwrap();
+        graph.try_get_bus_mut(a).unwrap().set_soloed(true);
+
+        let inputs = [(a, 1.0), (b, 1.0)];
+        render_block(&mut graph, &inputs);
+        // A: 1.0, Reverb: 0.5, B is silenced.
+        assert_eq!(render_block(&mut graph, &inputs)[0], (1.5, 1.5));
+    }
+
+    #[test]
+    fn test_effect_bypass() {
+        let mut graph = AudioBusGraph::new();
+        let mut bus = AudioBus::new("Bus".to_string());
+        let effect = bus.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
+        let bus = graph.add_bus(bus, graph.root).unwrap();
+        let inputs = [(bus, 1.0)];
+        assert_eq!(render_block(&mut graph, &inputs)[0], (0.5, 0.5));
+
+        let bus_ref = graph.try_get_bus_mut(bus).unwrap();
+        bus_ref.set_effect_bypassed(effect, true);
+        assert!(bus_ref.is_effect_bypassed(effect));
+        assert!(!bus_ref.is_effect_bypassed(Handle::NONE));
+        let block = render_block(&mut graph, &inputs);
+        assert_eq!(block[0], (0.5, 0.5));
+        assert!(block[3].0 > 0.5 && block[3].0 < 1.0);
+        assert_eq!(render_settled(&mut graph, &inputs)[0], (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_fades_do_not_depend_on_block_size() {
+        let render = |block_size: usize| {
+            let mut graph = AudioBusGraph::new();
+            let mut bus = AudioBus::new("Bus".to_string());
+            let effect = bus.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
+            let bus = graph.add_bus(bus, graph.root).unwrap();
+            let mut output = Vec::new();
+            let mut block = vec![(0.0, 0.0); block_size];
+            for i in 0..512 / block_size {
+                if i * block_size == 64 {
+                    let bus = graph.try_get_bus_mut(bus).unwrap();
+                    bus.set_effect_bypassed(effect, true);
+                    bus.set_muted(true);
+                }
+                block.fill((0.0, 0.0));
+                graph.begin_render(block_size);
+                fill_input(&mut graph, bus, 1.0);
+                graph.end_render(&mut block, &Listener::new());
+                output.extend_from_slice(&block);
+            }
+            output
+        };
+
+        let output = render(16);
+        assert_eq!(output, render(64));
+        // The fade lasts the same amount of frames for any block size.
+        assert_eq!(output[63], (0.5, 0.5));
+        assert!(output[64 + FADE_FRAMES - 1].0 > 0.0);
+        assert_eq!(output[64 + FADE_FRAMES], (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_effect_handles_are_stable() {
+        let mut bus = AudioBus::new("Bus".to_string());
+        let a = bus.add_effect(Effect::Attenuate(Attenuate::new(0.5)));
+        let b = bus.add_effect(Effect::Attenuate(Attenuate::new(0.25)));
+        let c = bus.insert_effect(0, Effect::Attenuate(Attenuate::new(1.0)));
+        assert_eq!(bus.effect_handles().collect::<Vec<_>>(), [c, a, b]);
+
+        assert!(bus.move_effect(c, 10));
+        assert_eq!(bus.effect_handles().collect::<Vec<_>>(), [a, b, c]);
+        assert_eq!(
+            bus.remove_effect(a),
+            Some(Effect::Attenuate(Attenuate::new(0.5)))
+        );
+        assert_eq!(bus.remove_effect(a), None);
+        assert!(!bus.move_effect(a, 0));
+        assert_eq!(bus.effect_position(b), Some(0));
+        assert_eq!(bus.effect_position(c), Some(1));
+
+        assert_eq!(
+            bus.replace_effect(b, Effect::Attenuate(Attenuate::new(0.5))),
+            Some(Effect::Attenuate(Attenuate::new(0.25)))
+        );
+        assert_eq!(bus.effect(b), Some(&Effect::Attenuate(Attenuate::new(0.5))));
+        assert_eq!(bus.effect_position(b), Some(0));
+
+        let mut graph = AudioBusGraph::new();
+        let bus = graph.add_bus(bus, graph.root).unwrap();
+        assert_eq!(render_block(&mut graph, &[(bus, 1.0)])[0], (0.5, 0.5));
+    }
+
+    // Outputs the previous sample of the input.
+    #[derive(Debug, Clone, Default)]
+    struct OneSampleDelay {
+        last: (f32, f32),
+    }
+
+    impl EffectRenderTrait for OneSampleDelay {
+        fn render(&mut self, input: &[(f32, f32)], output: &mut [(f32, f32)]) {
+            for (input, output) in input.iter().zip(output) {
+                *output = std::mem::replace(&mut self.last, *input);
+            }
+        }
+
+        fn reset(&mut self) {
+            self.last = (0.0, 0.0);
+        }
+
+        fn latency(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn test_user_effect() {
+        let mut bus = AudioBus::new("Bus".to_string());
+        let delay = bus.add_effect(Effect::User(Box::new(OneSampleDelay::default())));
+        bus.add_effect(Effect::User(Box::new(OneSampleDelay::default())));
+        assert_eq!(bus.latency(), 2);
+        let copy = bus.effect(delay).unwrap().clone();
+        assert_ne!(bus.effect(delay), Some(&copy));
+        assert_eq!(bus.effect(delay), bus.effect(delay));
+
+        let mut graph = AudioBusGraph::new();
+        let bus = graph.add_bus(bus, graph.root).unwrap();
+        let block = render_block(&mut graph, &[(bus, 1.0)]);
+        assert_eq!(block, [(0.0, 0.0), (0.0, 0.0), (1.0, 1.0), (1.0, 1.0)]);
+
+        let bus_ref = graph.try_get_bus_mut(bus).unwrap();
+        bus_ref.set_effect_bypassed(delay, true);
+        assert_eq!(bus_ref.latency(), 1);
+
+        // After reset the effects start from silence.
+        bus_ref.reset_effects();
+        let block = render_block(&mut graph, &[(bus, 0.0)]);
+        assert_eq!(block[0], (0.0, 0.0));
+    }
+
+    #[derive(Debug, Clone)]
+    struct Silence;
+
+    impl EffectRenderTrait for Silence {
+        fn render(&mut self, _input: &[(f32, f32)], output: &mut [(f32, f32)]) {
+            output.fill((0.0, 0.0));
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Passthrough;
+
+    impl EffectRenderTrait for Passthrough {
+        fn render(&mut self, input: &[(f32, f32)], output: &mut [(f32, f32)]) {
+            output.copy_from_slice(input);
+        }
+    }
+
+    #[test]
+    fn test_zero_sized_user_effects_are_not_equal() {
+        let silence = Effect::User(Box::new(Silence));
+        assert_ne!(silence, Effect::User(Box::new(Silence)));
+        assert_ne!(silence, Effect::User(Box::new(Passthrough)));
+    }
+
+    #[test]
+    fn test_ambisonic_data_flow() {
+        let mut output_buffer = [(0.0f32, 0.0f32)];
+
+        let mut graph = AudioBusGraph::new();
+        graph.set_mode(AudioBusGraphMode::Ambisonic(AmbisonicDecoder::new(
+            SpeakerLayout::Quad,
+        )));
+
+        let mut bus1 = AudioBus::new("Bus1".to_string());
+        bus1.set_gain(0.5);
+        let bus1 = graph.add_bus(bus1, graph.root).unwrap();
+
+        graph.begin_render(output_buffer.len());
+        assert_eq!(graph.decoded_output().len(), 4);
+
+        // Sound from the right side of the default listener.
+        for sample in graph.buses[bus1].input_buffers().1 {
+            *sample = BFormatSample::encode(glam::Vec3::new(1.0, 0.0, 0.0), 1.0);
+        }
+
+        graph.end_render(&mut output_buffer, &Listener::new());
+
+        let decoded = graph.decoded_output();
+        // Right speakers are louder than the left ones.
+        assert!(decoded[1] > decoded[0] && decoded[3] > decoded[2]);
+        assert!(output_buffer[0].1 > output_buffer[0].0);
     }
 }
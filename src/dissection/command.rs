@@ -0,0 +1,226 @@
+//! Commands sent from control threads to the render thread.
+//!
+//! # Overview
+//!
+//! Control threads never touch a [`SoundContext`] that is being rendered. Instead, every change is sent as
+//! a [`Command`] through a wait-free queue (see [`crate::dissection::queue`]) and applied by the render
+//! thread at the beginning of the next block. Everything the render thread no longer needs (removed sources,
+//! executed commands) is sent back as [`Garbage`] through another queue, so that memory is freed on a
+//! control thread. See [`crate::dissection::engine::SharedSoundContext`] for the public API.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::dissection::buffer::SharedBuffer;
+use crate::dissection::engine::{ContextState, SoundContext};
+use crate::dissection::pool::{Handle, Pool};
+use crate::dissection::queue::{Consumer, Producer};
+use crate::dissection::source::{SoundEvent, SoundSource};
+
+/// An arbitrary change of a context. Implementations keep their data inside, so the boxed edit could be
+/// returned to a control thread and freed there.
+pub(crate) trait ContextEdit: Send {
+    fn apply(&mut self, context: &mut SoundContext);
+}
+
+pub(crate) struct Edit<F>(pub Option<F>);
+
+impl<F> ContextEdit for Edit<F>
+where
+    F: FnOnce(&mut SoundContext) + Send,
+{
+    fn apply(&mut self, context: &mut SoundContext) {
+        if let Some(edit) = self.0.take() {
+            edit(context)
+        }
+    }
+}
+
+pub(crate) struct Query<F, R> {
+    pub query: Option<F>,
+    pub slot: Arc<ReplySlot<R>>,
+}
+
+impl<F, R> ContextEdit for Query<F, R>
+where
+    F: FnOnce(&SoundContext) -> R + Send,
+    R: Send,
+{
+    fn apply(&mut self, context: &mut SoundContext) {
+        if let Some(query) = self.query.take() {
+            self.slot.set(query(context))
+        }
+    }
+}
+
+/// Replaces the buffer of every source that plays `old` with `new`. Both buffers are kept inside the edit,
+/// so the old sample data is never freed on the render thread.
+pub(crate) struct ReplaceBuffer {
+    pub old: SharedBuffer,
+    pub new: SharedBuffer,
+}
+
+impl ContextEdit for ReplaceBuffer {
+    fn apply(&mut self, context: &mut SoundContext) {
+        for source in context.sources_mut().iter_mut() {
+            if source.buffer.as_ref() == Some(&self.old) {
+                // The source holds a clone of `old`, so dropping it here only decrements the counter.
+                source.replace_buffer(self.new.clone());
+            }
+        }
+    }
+}
+
+// Sound sources are passed by value (not boxed), so moving them in and out of the pool does not allocate
+// or free memory on the render thread.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Command {
+    /// Empty storage for the source pool, made on a control thread so the render thread doesn't allocate
+    /// memory when it adds sources.
+    ReserveSources(Pool<SoundSource>),
+    AddSource(Handle<SoundSource>, SoundSource),
+    RemoveSource(Handle<SoundSource>),
+    Edit(Box<dyn ContextEdit>),
+}
+
+// Values are never read, they're held only to be dropped on a control thread.
+#[allow(clippy::large_enum_variant, dead_code)]
+pub(crate) enum Garbage {
+    /// A source removed by a control thread, its handle is already released.
+    RemovedSource(SoundSource),
+    /// A source removed by the render thread (for example a finished one-shot sound), its handle must be
+    /// released by the control thread.
+    ReleasedSource(Handle<SoundSource>, SoundSource),
+    /// Previous storage of the source pool.
+    SourceStorage(Pool<SoundSource>),
+    /// A buffer a source is done with, for example a finished queued buffer.
+    Buffer(SharedBuffer),
+    Edit(Box<dyn ContextEdit>),
+}
+
+/// Render side of the connection between a context and its control handle.
+pub(crate) struct RenderLink {
+    pub commands: Consumer<Command>,
+    pub garbage: Producer<Garbage>,
+    // Garbage that didn't fit into the queue, it is sent on the next block. Its capacity is reserved up front.
+    pub overflow: Vec<Garbage>,
+    pub events: Producer<SoundEvent>,
+    // Where the context should be returned to when it is detached from an engine.
+    pub home: Weak<ContextState>,
+}
+
+impl std::fmt::Debug for RenderLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderLink")
+            .field("commands", &self.commands)
+            .field("garbage", &self.garbage)
+            .field("overflow", &self.overflow.len())
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+impl RenderLink {
+    /// Returns `true` if one more value could be disposed without growing the overflow storage. The render
+    /// thread checks it before producing garbage and postpones the work to the next block otherwise.
+    pub fn can_dispose(&self) -> bool {
+        self.overflow.len() < self.overflow.capacity()
+    }
+
+    /// Sends garbage back to the control thread. If the queue is full (no one collects garbage), the value is
+    /// kept in the overflow storage until [`Self::flush`] succeeds. It is never dropped on the render thread.
+    pub fn dispose(&mut self, garbage: Garbage) {
+        if !self.overflow.is_empty() {
+            self.overflow.push(garbage);
+        } else if let Err(garbage) = self.garbage.push(garbage) {
+            self.overflow.push(garbage);
+        }
+    }
+
+    /// Moves as much of the overflow storage into the queue as it can take.
+    pub fn flush(&mut self) {
+        while let Some(garbage) = self.overflow.pop() {
+            if let Err(garbage) = self.garbage.push(garbage) {
+                self.overflow.push(garbage);
+                break;
+            }
+        }
+    }
+}
+
+const REPLY_PENDING: u8 = 0;
+const REPLY_READY: u8 = 1;
+const REPLY_TAKEN: u8 = 2;
+
+pub(crate) struct ReplySlot<R> {
+    state: AtomicU8,
+    value: UnsafeCell<Option<R>>,
+}
+
+// The value is written once by the render thread before the state becomes ready, and read only by the thread
+// that moves the state from ready to taken, so there's at most one reader.
+unsafe impl<R: Send> Sync for ReplySlot<R> {}
+
+impl<R> ReplySlot<R> {
+    pub fn new() -> Self {
+        Self {
+            state: AtomicU8::new(REPLY_PENDING),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    fn set(&self, value: R) {
+        if self.state.load(Ordering::Acquire) == REPLY_PENDING {
+            // Safety: no one reads the value until the state is ready.
+            unsafe { *self.value.get() = Some(value) };
+            self.state.store(REPLY_READY, Ordering::Release);
+        }
+    }
+}
+
+/// A result of [`crate::dissection::engine::SharedSoundContext::query`] that will be available once the
+/// render thread executes the query.
+pub struct Reply<R> {
+    pub(crate) slot: Arc<ReplySlot<R>>,
+}
+
+impl<R> Reply<R> {
+    /// Returns `true` if the query was executed and its result is not taken yet.
+    pub fn is_ready(&self) -> bool {
+        self.slot.state.load(Ordering::Acquire) == REPLY_READY
+    }
+
+    /// Takes the result if the query was executed, does not block. The result is taken only once, even if
+    /// the reply is shared between threads.
+    pub fn try_take(&self) -> Option<R> {
+        self.slot
+            .state
+            .compare_exchange(
+                REPLY_READY,
+                REPLY_TAKEN,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()?;
+        // Safety: the render thread doesn't touch the value after the state is ready, and only the thread that
+        // moved the state to taken gets here.
+        unsafe { (*self.slot.value.get()).take() }
+    }
+
+    /// Waits until the query is executed, but no longer than the given timeout. The render thread executes
+    /// queries at the beginning of each block, so a timeout of a few blocks is usually enough.
+    pub fn wait(&self, timeout: Duration) -> Option<R> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(value) = self.try_take() {
+                return Some(value);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Randomized sound containers.
+//!
+//! # Overview
+//!
+//! Playing the same sound over and over (footsteps, gunshots, impacts) quickly becomes noticeable. A sound
+//! container holds several variations of a sound and picks one of them every time it is triggered, see
+//! [`SelectionMode`]. Pitch and gain of every triggered sound are randomly varied within the given ranges,
+//! so even the same variation sounds a bit different.
+//!
+//! Triggering a container spawns a new sound source into the context. The source is a copy of the template
+//! source of the container, so its bus, group, position and other properties could be configured once, see
+//! [`SoundContainer::template_mut`]. By default the template is a one-shot source, which is removed from the
+//! context once it is played.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use audio::dissection::buffer::{Buffer, SharedBuffer};
+//! use audio::dissection::container::{SelectionMode, SoundContainer};
+//! use audio::dissection::engine::SoundContext;
+//!
+//! let variations = (0..4)
+//!     .map(|_| SharedBuffer::new(Buffer::new(vec![0.0; 1024], true)))
+//!     .collect();
+//! let mut footsteps = SoundContainer::new(variations);
+//! footsteps
+//!     .set_mode(SelectionMode::Shuffle)
+//!     .set_pitch_range(0.95..=1.05)
+//!     .set_gain_range(0.8..=1.0);
+//! // Footsteps are not spatial.
+//! footsteps.template_mut().spatial_blend = 0.0;
+//!
+//! let mut context = SoundContext::new();
+//! let footstep = footsteps.trigger(&mut context);
+//! assert!(footstep.is_some());
+//! ```
+//!
+//! Sources of a [`crate::dissection::engine::SharedSoundContext`] are added with
+//! [`SoundContainer::next_source`]:
+//!
+//! ```rust,no_run
+//! # use audio::dissection::container::SoundContainer;
+//! # use audio::dissection::engine::SharedSoundContext;
+//! # let mut footsteps = SoundContainer::new(Vec::new());
+//! # let context = SharedSoundContext::new();
+//! if let Some(source) = footsteps.next_source() {
+//!     context.add_source(source);
+//! }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::RangeInclusive;
+
+use super::buffer::SharedBuffer;
+use super::engine::SoundContext;
+use super::pool::Handle;
+use super::source::{SoundSource, Status};
+
+/// Defines how a container picks a variation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionMode {
+    /// Every variation is equally likely to be picked every time, the same one could be picked twice in a
+    /// row.
+    Random,
+    /// Variations are picked in random order, but each one is picked once until all of them are played.
+    /// The same variation is never picked twice in a row.
+    #[default]
+    Shuffle,
+    /// Variations are picked one after another in the order they were added (round-robin).
+    Sequential,
+}
+
+// Small xorshift generator, randomness of the variations doesn't need anything better.
+#[derive(Debug, Clone)]
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // Zero state would produce only zeros.
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // Returns a number in `0.0..1.0`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Returns a number in `0..bound`.
+    fn next_index(&mut self, bound: usize) -> usize {
+        (self.next_f64() * bound as f64) as usize
+    }
+
+    fn in_range(&mut self, range: &RangeInclusive<f64>) -> f64 {
+        range.start() + (range.end() - range.start()) * self.next_f64()
+    }
+}
+
+/// Set of sound variations with a selection mode and random pitch and gain. See module docs.
+#[derive(Debug, Clone)]
+pub struct SoundContainer {
+    buffers: Vec<SharedBuffer>,
+    mode: SelectionMode,
+    pitch_range: RangeInclusive<f64>,
+    gain_range: RangeInclusive<f32>,
+    template: SoundSource,
+    random: Random,
+    // Indices of variations that are left in the current shuffle round, the last one is picked next.
+    bag: Vec<usize>,
+    last: Option<usize>,
+}
+
+impl SoundContainer {
+    /// Creates a container with the given variations, the shuffle mode and no pitch or gain variation. The
+    /// container is seeded randomly, see [`Self::with_seed`].
+    pub fn new(buffers: Vec<SharedBuffer>) -> Self {
+        let mut template = SoundSource::default();
+        template.play_once = true;
+        Self {
+            buffers,
+            mode: Default::default(),
+            pitch_range: 1.0..=1.0,
+            gain_range: 1.0..=1.0,
+            template,
+            random: Random::new(RandomState::new().build_hasher().finish()),
+            bag: Default::default(),
+            last: None,
+        }
+    }
+
+    /// Sets the seed of the random generator, so the same seed produces the same sequence of variations,
+    /// pitches and gains.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.random = Random::new(seed);
+        self
+    }
+
+    /// Replaces the variations of the container and starts the selection over.
+    pub fn set_buffers(&mut self, buffers: Vec<SharedBuffer>) -> &mut Self {
+        self.buffers = buffers;
+        self.bag.clear();
+        self.last = None;
+        self
+    }
+
+    /// Returns the variations of the container.
+    pub fn buffers(&self) -> &[SharedBuffer] {
+        &self.buffers
+    }
+
+    /// Sets the selection mode and starts the selection over.
+    pub fn set_mode(&mut self, mode: SelectionMode) -> &mut Self {
+        self.mode = mode;
+        self.bag.clear();
+        self.last = None;
+        self
+    }
+
+    /// Returns the selection mode.
+    pub fn mode(&self) -> SelectionMode {
+        self.mode
+    }
+
+    /// Sets the range of a random multiplier of the pitch of triggered sources, for example `0.95..=1.05`.
+    /// Default is `1.0..=1.0`, which means no variation.
+    pub fn set_pitch_range(&mut self, range: RangeInclusive<f64>) -> &mut Self {
+        self.pitch_range = range;
+        self
+    }
+
+    /// Returns the range of a random multiplier of the pitch.
+    pub fn pitch_range(&self) -> RangeInclusive<f64> {
+        self.pitch_range.clone()
+    }
+
+    /// Sets the range of a random multiplier of the gain of triggered sources, for example `0.8..=1.0`.
+    /// Default is `1.0..=1.0`, which means no variation.
+    pub fn set_gain_range(&mut self, range: RangeInclusive<f32>) -> &mut Self {
+        self.gain_range = range;
+        self
+    }
+
+    /// Returns the range of a random multiplier of the gain.
+    pub fn gain_range(&self) -> RangeInclusive<f32> {
+        self.gain_range.clone()
+    }
+
+    /// Returns the source every triggered source is copied from.
+    pub fn template(&self) -> &SoundSource {
+        &self.template
+    }
+
+    /// Returns the source every triggered source is copied from. Its buffer is replaced by a variation and
+    /// its pitch and gain are multiplied by random values.
+    pub fn template_mut(&mut self) -> &mut SoundSource {
+        &mut self.template
+    }
+
+    // Picks the index of the next variation.
+    fn next_index(&mut self) -> Option<usize> {
+        let count = self.buffers.len();
+        if count == 0 {
+            return None;
+        }
+        let index = match self.mode {
+            SelectionMode::Random => self.random.next_index(count),
+            SelectionMode::Sequential => self.last.map_or(0, |last| (last + 1) % count),
+            SelectionMode::Shuffle => {
+                if self.bag.is_empty() {
+                    self.bag.extend(0..count);
+                    // Fisher-Yates shuffle.
+                    for i in (1..count).rev() {
+                        let j = self.random.next_index(i + 1);
+                        self.bag.swap(i, j);
+                    }
+                    // The last variation of the previous round must not start the new one.
+                    if count > 1 && self.bag.last() == self.last.as_ref() {
+                        self.bag.swap(0, count - 1);
+                    }
+                }
+                self.bag.pop()?
+            }
+        };
+        self.last = Some(index);
+        Some(index)
+    }
+
+    /// Creates a playing copy of the template source with the next variation and random pitch and gain.
+    /// Returns `None` if the container has no variations.
+    pub fn next_source(&mut self) -> Option<SoundSource> {
+        let index = self.next_index()?;
+        let pitch = self.random.in_range(&self.pitch_range);
+        let gain_range = *self.gain_range.start() as f64..=*self.gain_range.end() as f64;
+        let gain = self.random.in_range(&gain_range) as f32;
+
+        let mut source = self.template.clone();
+        source.set_buffer(Some(self.buffers[index].clone()));
+        let (template_pitch, template_gain) = (source.pitch(), source.gain);
+        source
+            .set_pitch(template_pitch * pitch)
+            .set_gain(template_gain * gain);
+        source.status = Status::Playing;
+        Some(source)
+    }
+
+    /// Adds a playing copy of the template source with the next variation to the context, see
+    /// [`Self::next_source`]. Returns `None` if the container has no variations.
+    pub fn trigger(&mut self, context: &mut SoundContext) -> Option<Handle<SoundSource>> {
+        let source = self.next_source()?;
+        Some(context.add_source(source))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        buffer::{Buffer, SharedBuffer},
+        container::{SelectionMode, SoundContainer},
+        engine::SoundContext,
+        source::Status,
+    };
+
+    fn make_container(mode: SelectionMode) -> SoundContainer {
+        let buffers = (0..4)
+            .map(|i| SharedBuffer::new(Buffer::new(vec![i as f32; 16], true)))
+            .collect();
+        let mut container = SoundContainer::new(buffers).with_seed(42);
+        container.set_mode(mode);
+        container
+    }
+
+    // Returns indices of the next picked variations.
+    fn pick(container: &mut SoundContainer, count: usize) -> Vec<usize> {
+        (0..count)
+            .map(|_| {
+                let source = container.next_source().unwrap();
+                let buffer = source.buffer().unwrap();
+                container
+                    .buffers()
+                    .iter()
+                    .position(|other| other == buffer)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sequential_selection() {
+        let mut container = make_container(SelectionMode::Sequential);
+        assert_eq!(pick(&mut container, 6), [0, 1, 2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn test_shuffle_selection() {
+        let mut container = make_container(SelectionMode::Shuffle);
+        let picked = pick(&mut container, 40);
+        for round in picked.chunks(4) {
+            let mut round = round.to_vec();
+            round.sort();
+            assert_eq!(round, [0, 1, 2, 3]);
+        }
+        assert!(picked.windows(2).all(|pair| pair[0] != pair[1]));
+
+        // The same seed gives the same sequence.
+        assert_eq!(
+            pick(&mut make_container(SelectionMode::Shuffle), 40),
+            picked
+        );
+    }
+
+    #[test]
+    fn test_random_selection_and_jitter() {
+        let mut container = make_container(SelectionMode::Random);
+        container
+            .set_pitch_range(0.9..=1.1)
+            .set_gain_range(0.5..=1.0);
+        container.template_mut().set_gain(0.5);
+        let picked = pick(&mut container, 100);
+        assert!((0..4).all(|index| picked.contains(&index)));
+
+        for _ in 0..100 {
+            let source = container.next_source().unwrap();
+            assert!((0.9..=1.1).contains(&source.pitch()));
+            assert!((0.25..=0.5).contains(&source.gain));
+        }
+    }
+
+    #[test]
+    fn test_trigger() {
+        let mut context = SoundContext::new();
+        let mut container = make_container(SelectionMode::Sequential);
+        let handle = container.trigger(&mut context).unwrap();
+        let source = context.source(handle);
+        assert_eq!(source.status, Status::Playing);
+        assert!(source.play_once);
+
+        assert!(SoundContainer::new(Vec::new())
+            .trigger(&mut context)
+            .is_none());
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Debug;
+
 /// Attenuation effect.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Attenuate {
@@ -11,6 +13,9 @@ impl Default for Attenuate {
 }
 
 impl Attenuate {
+    /// Index of the gain parameter, see [`EffectRenderTrait::parameter`].
+    pub const GAIN: usize = 0;
+
     /// Creates new attenuation effect.
     pub fn new(gain: f32) -> Self {
         Self {
@@ -28,15 +33,31 @@ impl EffectRenderTrait for Attenuate {
             *output_right = *input_right * self.gain;
         }
     }
+
+    fn parameter(&self, index: usize) -> Option<f32> {
+        (index == Self::GAIN).then_some(self.gain)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        if index == Self::GAIN {
+            self.gain = value.max(0.0);
+        }
+    }
 }
 
 /// Effects is a digital signal processing (DSP) unit that transforms input signal in a specific way.
 /// For example, [`LowPassFilterEffect`] could be used to muffle audio sources; to create "underwater"
 /// effect.
+///
+/// Built-in effects are dispatched statically, effects defined outside of the crate are boxed into
+/// [`Effect::User`], see [`UserEffect`] docs.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Effect {
     /// See [`Attenuate`] docs for more info.
     Attenuate(Attenuate),
+    /// An effect defined outside of the crate. Two user effects are equal only if it is the same instance.
+    /// Instances of zero-sized effects can't be told apart, so such effects are never equal.
+    User(Box<dyn UserEffect>),
 }
 
 impl Default for Effect {
@@ -45,14 +66,90 @@ impl Default for Effect {
     }
 }
 
-pub(crate) trait EffectRenderTrait {
+/// Signal processing part of an effect. Effects are rendered on the render thread, so implementations
+/// must not allocate memory, take locks or do anything else that could block.
+pub trait EffectRenderTrait {
+    /// Processes one block of stereo samples. `input` and `output` have the same length.
     fn render(&mut self, input: &[(f32, f32)], output: &mut [(f32, f32)]);
+
+    /// Clears internal state of the effect (delay lines, filter history and so on), so it starts from
+    /// silence. It is called when the effect is put back into the chain after being bypassed.
+    fn reset(&mut self) {}
+
+    /// Returns delay (in samples) the effect adds to the signal.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Returns current value of an automatable parameter with the given index, or `None` if there's no such
+    /// parameter. Indices of parameters are defined by the effect.
+    fn parameter(&self, index: usize) -> Option<f32> {
+        let _ = index;
+        None
+    }
+
+    /// Sets new value of an automatable parameter with the given index. The engine calls it while rendering
+    /// to apply automation (see [`crate::dissection::automation`]), so it must be cheap.
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        let _ = (index, value);
+    }
+}
+
+/// An effect defined outside of the crate. The trait is implemented automatically for every type that
+/// implements [`EffectRenderTrait`], [`Debug`], [`Clone`] and [`Send`].
+///
+/// # Examples
+///
+/// ```rust
+/// use audio::dissection::bus::AudioBus;
+/// use audio::dissection::effects::{Effect, EffectRenderTrait};
+///
+/// #[derive(Debug, Clone)]
+/// struct SwapChannels;
+///
+/// impl EffectRenderTrait for SwapChannels {
+///     fn render(&mut self, input: &[(f32, f32)], output: &mut [(f32, f32)]) {
+///         for (&(left, right), output) in input.iter().zip(output) {
+///             *output = (right, left);
+///         }
+///     }
+/// }
+///
+/// let mut bus = AudioBus::new("Swap".to_string());
+/// bus.add_effect(Effect::User(Box::new(SwapChannels)));
+/// ```
+pub trait UserEffect: EffectRenderTrait + Debug + Send + 'static {
+    /// Clones the effect into a new box.
+    fn clone_box(&self) -> Box<dyn UserEffect>;
+}
+
+impl<T> UserEffect for T
+where
+    T: EffectRenderTrait + Debug + Clone + Send + 'static,
+{
+    fn clone_box(&self) -> Box<dyn UserEffect> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn UserEffect> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl PartialEq for dyn UserEffect {
+    fn eq(&self, other: &Self) -> bool {
+        // Boxes of zero-sized values share the same dangling address, even for different types.
+        std::mem::size_of_val(self) != 0 && std::ptr::addr_eq(self, other)
+    }
 }
 
 macro_rules! static_dispatch {
     ($self:ident, $func:ident, $($args:expr),*) => {
         match $self {
             Effect::Attenuate(v) => v.$func($($args),*),
+            Effect::User(v) => v.$func($($args),*),
         }
     };
 }
@@ -61,4 +158,20 @@ impl EffectRenderTrait for Effect {
     fn render(&mut self, input: &[(f32, f32)], output: &mut [(f32, f32)]) {
         static_dispatch!(self, render, input, output)
     }
+
+    fn reset(&mut self) {
+        static_dispatch!(self, reset,)
+    }
+
+    fn latency(&self) -> usize {
+        static_dispatch!(self, latency,)
+    }
+
+    fn parameter(&self, index: usize) -> Option<f32> {
+        static_dispatch!(self, parameter, index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        static_dispatch!(self, set_parameter, index, value)
+    }
 }
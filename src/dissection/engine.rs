@@ -1,107 +1,669 @@
-
 use std::error::Error;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 
+use crate::dissection::ambisonics::BFormatSample;
+use crate::dissection::backend::{OutputBackend, OutputParameters, TinyaudioBackend};
+use crate::dissection::bus::AudioBusGraph;
+use crate::dissection::buffer::SharedBuffer;
+use crate::dissection::command::{
+    Command, ContextEdit, Edit, Garbage, Query, RenderLink, ReplaceBuffer, Reply, ReplySlot,
+};
+use crate::dissection::group::{SoundGroup, StealPolicy};
+use crate::dissection::listener::Listener;
 use crate::dissection::pool::handle::Handle;
 use crate::dissection::pool::Pool;
-use crate::dissection::source::{SoundSource, Status};
-use crate::dissection::bus::AudioBusGraph;
-use crate::{lerp, SAMPLES_PER_CHANNEL, SAMPLE_RATE};
+use crate::dissection::queue::{spsc_queue, Consumer, Producer};
+use crate::dissection::source::{SoundEvent, SoundSource, Status};
+use crate::{lerp, SAMPLE_RATE};
+
+/// Maximum amount of commands that could wait for the render thread. Control threads wait if the queue is
+/// full.
+const COMMAND_QUEUE_CAPACITY: usize = 1024;
+
+/// Maximum amount of freed resources that could wait for a control thread. The render thread keeps the same
+/// amount aside if the queue is full and stops producing garbage (applying commands, removing finished
+/// sources) when both are full.
+const GARBAGE_QUEUE_CAPACITY: usize = 1024;
+
+/// Maximum amount of playback events that could wait for a control thread. If the queue is full, new events
+/// are dropped.
+const EVENT_QUEUE_CAPACITY: usize = 1024;
+
+/// The source pool of a shared context is grown by at least this amount of sources at once.
+const MIN_SOURCE_CAPACITY: u32 = 16;
+
+/// Sources that are already rendered are ranked as if they were this much louder, so voices of sources with
+/// similar loudness don't swap every block.
+const VOICE_HYSTERESIS: f32 = 1.25;
+
+enum EngineCommand {
+    SetContext(Option<SoundContext>),
+}
 
 pub struct SoundEngine {
-    pub context: SharedSoundContext,
-    output_device: Option<tinyaudio::OutputDevice>,
-    internal_buffer: Vec<(f32, f32)>
+    context: SharedSoundContext,
+    backend: Option<Box<dyn OutputBackend>>,
+    commands: Producer<EngineCommand>,
+    // Contexts that were detached from the render thread.
+    detached: Consumer<SoundContext>,
+}
+
+impl SoundEngine {
+    /// Returns the context that is currently rendered by the engine.
+    pub fn context(&self) -> &SharedSoundContext {
+        &self.context
+    }
+
+    fn collect_detached(&mut self) {
+        while let Some(context) = self.detached.pop() {
+            context.return_home();
+        }
+    }
+}
+
+// The part of the engine that lives on the render thread.
+struct EngineRenderer {
+    context: Option<SoundContext>,
+    internal_buffer: Vec<(f32, f32)>,
+    channels_count: usize,
+    commands: Consumer<EngineCommand>,
+    detached: Producer<SoundContext>,
+}
+
+impl EngineRenderer {
+    fn render(&mut self, buf: &mut [f32]) {
+        while let Some(command) = self.commands.pop() {
+            match command {
+                EngineCommand::SetContext(context) => {
+                    if let Some(previous) = std::mem::replace(&mut self.context, context) {
+                        if let Err(previous) = self.detached.push(previous) {
+                            previous.return_home();
+                        }
+                    }
+                }
+            }
+        }
+
+        match self.context.as_mut() {
+            Some(context) => context.render(&mut self.internal_buffer),
+            // No context attached yet.
+            None => self.internal_buffer.fill((0.0, 0.0)),
+        }
+
+        // Copy to the output buffer of the backend. Mono output gets a downmix, extra channels are silent.
+        for (frame, &(left, right)) in buf
+            .chunks_exact_mut(self.channels_count)
+            .zip(self.internal_buffer.iter())
+        {
+            match frame {
+                [mono] => *mono = (left + right) * 0.5,
+                [l, r, rest @ ..] => {
+                    *l = left;
+                    *r = right;
+                    rest.fill(0.0);
+                }
+                [] => (),
+            }
+        }
+    }
 }
 
+impl Drop for EngineRenderer {
+    fn drop(&mut self) {
+        // The output stream is stopped, give the context back to its owner.
+        if let Some(context) = self.context.take() {
+            context.return_home();
+        }
+    }
+}
+
+/// Sound engine renders a [`SharedSoundContext`] using an output backend. The render thread owns the context
+/// while it is attached to the engine and it never waits for any lock, control threads communicate with it
+/// using wait-free queues.
 #[derive(Clone)]
 pub struct SharedSoundEngine(Arc<Mutex<SoundEngine>>);
 
 impl SharedSoundEngine {
+    /// Creates new engine that outputs samples to the default sound card. See [`Self::with_backend`] for
+    /// other outputs.
     pub fn new() -> Result<Self, Box<dyn Error>> {
-        let buffer_size = SAMPLES_PER_CHANNEL;
-        let engine = Self(Arc::new(Mutex::new(SoundEngine {
+        Self::with_backend(TinyaudioBackend::default(), OutputParameters::default())
+    }
+
+    /// Creates new engine that outputs samples using the given backend and stream parameters. See
+    /// [`crate::dissection::backend`] docs for available backends. Any amount of channels is supported: mono
+    /// output gets a downmix of the stereo signal, channels after the first two are silent. Contexts always
+    /// render at [`SAMPLE_RATE`], other sample rates are rejected.
+    pub fn with_backend<B>(
+        mut backend: B,
+        parameters: OutputParameters,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        B: OutputBackend + 'static,
+    {
+        if parameters.sample_rate != SAMPLE_RATE as usize {
+            return Err(format!("Unsupported sample rate {} Hz!", parameters.sample_rate).into());
+        }
+        if parameters.channels_count == 0 || parameters.channel_sample_count == 0 {
+            return Err("The output stream must have at least one channel and one frame!".into());
+        }
+
+        let (commands, render_commands) = spsc_queue(16);
+        let (render_detached, detached) = spsc_queue(16);
+        let mut renderer = EngineRenderer {
+            context: None,
+            internal_buffer: vec![(0.0, 0.0); parameters.channel_sample_count],
+            channels_count: parameters.channels_count,
+            commands: render_commands,
+            detached: render_detached,
+        };
+
+        backend.start(parameters, Box::new(move |buf| renderer.render(buf)))?;
+
+        Ok(Self(Arc::new(Mutex::new(SoundEngine {
             context: Default::default(),
-            output_device: None,
-            internal_buffer: vec![(0.0, 0.0); buffer_size]
-        })));
-        let state = engine.clone();
-
-        let device = tinyaudio::run_output_device(
-            tinyaudio::OutputDeviceParameters {
-                sample_rate: SAMPLE_RATE as usize,
-                channels_count: 2,
-                channel_sample_count: SAMPLES_PER_CHANNEL,
-            },
-            move |buf| SharedSoundEngine::render_callback(buf, &state),
-        )?;
-        engine.lock().output_device = Some(device);
-        Ok(engine)
-    }
-    pub fn lock(&self) -> MutexGuard<SoundEngine> {
-        self.0.lock().unwrap()
+            backend: Some(Box::new(backend)),
+            commands,
+            detached,
+        }))))
     }
-    fn render_callback(buf: &mut [f32], engine: &SharedSoundEngine) {
-        let mut engine = engine.lock();
-        // engine.context.clone().lock().mock_render(&mut engine.internal_buffer);
-        engine
-            .context
-            .clone()
-            .lock()
-            .render(&mut engine.internal_buffer);
 
-        // Copy to tinyaudio's buffer
-        let stereo_samples = buf.len() / 2;
-        let output_device_buffer = unsafe {
-            std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut (f32, f32), stereo_samples)
+    /// Attaches the context to the engine, the render thread takes ownership of it until another context is
+    /// set or the engine is stopped. Previous context (if any) is detached. Passing
+    /// `SharedSoundContext::default()` just detaches current context. A context could be attached to only one
+    /// engine at a time.
+    pub fn set_context(&self, context: SharedSoundContext) -> Result<(), Box<dyn Error>> {
+        let mut engine = self.lock();
+        engine.collect_detached();
+
+        if engine.backend.is_none() {
+            return Err("The engine is stopped!".into());
+        }
+
+        let render_context = match context.state {
+            Some(_) => Some(
+                context
+                    .take_detached()
+                    .ok_or("The context is already attached to an engine!")?,
+            ),
+            None => None,
         };
 
-        output_device_buffer.copy_from_slice(&engine.internal_buffer[..stereo_samples]);
+        let mut command = EngineCommand::SetContext(render_context);
+        while let Err(rejected) = engine.commands.push(command) {
+            // The render thread hasn't processed previous commands yet.
+            command = rejected;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        engine.context = context;
+        Ok(())
+    }
+
+    /// Stops the output backend. The engine won't render anything after this call and its context is
+    /// detached. Returns an error if the backend failed while it was running, see
+    /// [`OutputBackend::finish`].
+    pub fn stop(&self) -> Result<(), Box<dyn Error>> {
+        // Take the backend out first, so it's dropped when the engine isn't locked.
+        let backend = self.lock().backend.take();
+        let result = backend.map_or(Ok(()), |mut backend| backend.finish());
+        let mut engine = self.lock();
+        engine.collect_detached();
+        engine.context = Default::default();
+        result
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, SoundEngine> {
+        self.0.lock().unwrap()
     }
 }
 
-#[derive(Clone, Default, Debug)]
+// Control side of a context.
+pub(crate) struct ContextControl {
+    commands: Producer<Command>,
+    garbage: Consumer<Garbage>,
+    events: Consumer<SoundEvent>,
+    // Mirror of the source pool of the render side, it is used to give out handles of new sources right away.
+    handles: Pool<()>,
+    // Capacity of the source pool of the render side, including storage that is on its way there.
+    source_capacity: u32,
+}
+
+impl ContextControl {
+    // Makes bigger storage for the source pool of the render side if the handles don't fit into it anymore,
+    // so the render thread doesn't allocate memory when sources are added.
+    fn grow_sources(&mut self) -> Option<Pool<SoundSource>> {
+        let needed = self.handles.get_capacity();
+        if needed <= self.source_capacity {
+            return None;
+        }
+        self.source_capacity = needed
+            .max(self.source_capacity * 2)
+            .max(MIN_SOURCE_CAPACITY);
+        let mut storage = Pool::new();
+        storage.reserve(self.source_capacity);
+        Some(storage)
+    }
+
+    fn collect_garbage(&mut self) {
+        while let Some(garbage) = self.garbage.pop() {
+            if let Garbage::ReleasedSource(handle, _) = garbage {
+                self.handles.try_free(handle.transmute());
+            }
+        }
+    }
+}
+
+pub(crate) struct ContextState {
+    control: Mutex<ContextControl>,
+    // The context itself when it isn't attached to any engine.
+    detached: Mutex<Option<SoundContext>>,
+}
+
+/// Control handle of a sound context. It could be cloned and used from any amount of threads.
+///
+/// Changes made through the handle are sent to the render thread as commands and applied at the beginning
+/// of the next rendered block, so the render thread never waits for control threads (and vice versa).
+/// Reading the state of the context is done by [`Self::query`]. Freed resources (removed sources, executed
+/// edits) are sent back and freed on control threads.
+///
+/// When the context isn't attached to an engine, commands are applied right away on the calling thread.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use audio::dissection::engine::{SharedSoundContext, SharedSoundEngine};
+/// use audio::dissection::source::SoundSource;
+/// use std::time::Duration;
+///
+/// let engine = SharedSoundEngine::new().unwrap();
+/// let context = SharedSoundContext::new();
+/// engine.set_context(context.clone()).unwrap();
+///
+/// let source = context.add_source(SoundSource::default());
+/// context.edit_source(source, |source| {
+///     source.set_pitch(0.5);
+/// });
+/// let playback_time = context
+///     .query(move |context| context.source(source).playback_time())
+///     .wait(Duration::from_secs(1));
+/// ```
+#[derive(Clone, Default)]
 pub struct SharedSoundContext {
-    pub state: Option<Arc<Mutex<SoundContext>>>,
+    state: Option<Arc<ContextState>>,
+}
+
+impl std::fmt::Debug for SharedSoundContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedSoundContext")
+            .field("is_attached", &self.is_attached())
+            .finish()
+    }
 }
 
 impl SharedSoundContext {
-    /// Creates new instance of context. Internally context starts new thread which will call render all
-    /// sound source and send samples to default output device. This method returns `Arc<Mutex<Context>>`
-    /// because separate thread also uses context.
+    /// Creates new context with the primary audio bus and no sound sources. The context must be attached to
+    /// an engine (see [`SharedSoundEngine::set_context`]) to be heard.
     pub fn new() -> Self {
-        Self {
-            state: Some(Arc::new(Mutex::new(SoundContext {
-                sources: Pool::new(),
-                render_duration: Default::default(),
-                bus_graph: AudioBusGraph::new(),
-                paused: false,
-            }))),
+        let (commands, render_commands) = spsc_queue(COMMAND_QUEUE_CAPACITY);
+        let (render_garbage, garbage) = spsc_queue(GARBAGE_QUEUE_CAPACITY);
+        let (render_events, events) = spsc_queue(EVENT_QUEUE_CAPACITY);
+        let state = Arc::new_cyclic(|home| {
+            let mut context = SoundContext::new();
+            context.link = Some(RenderLink {
+                commands: render_commands,
+                garbage: render_garbage,
+                overflow: Vec::with_capacity(GARBAGE_QUEUE_CAPACITY),
+                events: render_events,
+                home: home.clone(),
+            });
+            ContextState {
+                control: Mutex::new(ContextControl {
+                    commands,
+                    garbage,
+                    events,
+                    handles: Pool::new(),
+                    source_capacity: 0,
+                }),
+                detached: Mutex::new(Some(context)),
+            }
+        });
+        Self { state: Some(state) }
+    }
+
+    fn state(&self) -> &ContextState {
+        self.state
+            .as_ref()
+            .expect("The handle does not point to any context!")
+    }
+
+    /// Returns `true` if the context is attached to an engine.
+    pub fn is_attached(&self) -> bool {
+        self.state
+            .as_ref()
+            .is_some_and(|state| state.detached.lock().unwrap().is_none())
+    }
+
+    fn send(&self, command: Command) {
+        let mut control = self.state().control.lock().unwrap();
+        self.push(&mut control, command);
+        drop(control);
+        self.apply_detached();
+    }
+
+    // Pushes the command while the control side is locked, so commands of concurrent calls aren't mixed.
+    fn push(&self, control: &mut ContextControl, mut command: Command) {
+        control.collect_garbage();
+        while let Err(rejected) = control.commands.push(command) {
+            command = rejected;
+            // The queue is full, help the render thread if there is none or wait for it.
+            if !self.apply_detached() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            control.collect_garbage();
         }
     }
 
-    pub fn lock(&self) -> MutexGuard<'_, SoundContext> {
-        self.state.as_ref().unwrap().lock().unwrap()
+    // Applies pending commands if the context isn't attached to an engine. Returns `false` if it is attached.
+    fn apply_detached(&self) -> bool {
+        match self.state().detached.lock().unwrap().as_mut() {
+            Some(context) => {
+                context.apply_commands();
+                true
+            }
+            None => false,
+        }
     }
+
+    /// Adds new sound source and returns its handle. The source will start playing (if its status is
+    /// `Playing`) at the beginning of the next rendered block.
+    pub fn add_source(&self, mut source: SoundSource) -> Handle<SoundSource> {
+        source.reserve_retired();
+        let mut control = self.state().control.lock().unwrap();
+        let handle = control.handles.spawn(()).transmute();
+        if let Some(storage) = control.grow_sources() {
+            self.push(&mut control, Command::ReserveSources(storage));
+        }
+        self.push(&mut control, Command::AddSource(handle, source));
+        drop(control);
+        self.apply_detached();
+        handle
+    }
+
+    /// Removes sound source from the context. The source is freed on a control thread.
+    pub fn remove_source(&self, handle: Handle<SoundSource>) {
+        let released = self
+            .state()
+            .control
+            .lock()
+            .unwrap()
+            .handles
+            .try_free(handle.transmute())
+            .is_some();
+        if released {
+            self.send(Command::RemoveSource(handle));
+        }
+    }
+
+    /// Sends an arbitrary edit to the render thread. Values captured by the closure and dropped by it are
+    /// dropped on the render thread, move anything that owns large chunks of memory into the context instead.
+    pub fn edit<F>(&self, edit: F)
+    where
+        F: FnOnce(&mut SoundContext) + Send + 'static,
+    {
+        self.send(Command::Edit(Box::new(Edit(Some(edit)))));
+    }
+
+    /// Edits a sound source at the given handle, does nothing if the handle is invalid.
+    pub fn edit_source<F>(&self, handle: Handle<SoundSource>, edit: F)
+    where
+        F: FnOnce(&mut SoundSource) + Send + 'static,
+    {
+        self.edit(move |context| {
+            if let Some(source) = context.try_get_source_mut(handle) {
+                edit(source);
+                source.reserve_retired();
+            }
+        })
+    }
+
+    /// Makes every source that plays `old` buffer play `new` one instead, playback positions are kept. It is
+    /// used by [`crate::dissection::resource::ResourceManager`] to hot reload sounds.
+    pub fn replace_buffer(&self, old: SharedBuffer, new: SharedBuffer) {
+        self.send(Command::Edit(Box::new(ReplaceBuffer { old, new })));
+    }
+
+    /// Reads the state of the context. The query is executed by the render thread at the beginning of the
+    /// next block (or right away if the context isn't attached to an engine).
+    pub fn query<F, R>(&self, query: F) -> Reply<R>
+    where
+        F: FnOnce(&SoundContext) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let slot = Arc::new(ReplySlot::new());
+        let edit: Box<dyn ContextEdit> = Box::new(Query {
+            query: Some(query),
+            slot: slot.clone(),
+        });
+        self.send(Command::Edit(edit));
+        Reply { slot }
+    }
+
+    /// Gives direct access to the context if it isn't attached to an engine, for example to render it with
+    /// [`crate::dissection::offline::OfflineRenderer`]. Returns `None` if the context is attached.
+    pub fn with_detached<F, R>(&self, func: F) -> Option<R>
+    where
+        F: FnOnce(&mut SoundContext) -> R,
+    {
+        let result = self
+            .state()
+            .detached
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(|context| {
+                context.apply_commands();
+                func(context)
+            });
+        self.collect_garbage();
+        result
+    }
+
+    // Takes the context out to be rendered by an engine, returns `None` if it is already attached.
+    pub(crate) fn take_detached(&self) -> Option<SoundContext> {
+        self.state().detached.lock().unwrap().take()
+    }
+
+    /// Frees resources returned by the render thread. It is called automatically by every method of the
+    /// handle, but could be called periodically if the handle isn't used for a long time.
+    pub fn collect_garbage(&self) {
+        self.state().control.lock().unwrap().collect_garbage();
+    }
+
+    /// Takes the oldest playback event reported by the render thread, see [`SoundEvent`]. Events should be
+    /// polled regularly (for example once per game frame), the render thread drops new events if too many of
+    /// them are waiting.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use audio::dissection::engine::SharedSoundContext;
+    /// use audio::dissection::source::SoundEventKind;
+    ///
+    /// # let context = SharedSoundContext::new();
+    /// while let Some(event) = context.poll_event() {
+    ///     if event.kind == SoundEventKind::Finished {
+    ///         println!("{:?} finished at frame {}", event.source, event.time);
+    ///     }
+    /// }
+    /// ```
+    pub fn poll_event(&self) -> Option<SoundEvent> {
+        self.state().control.lock().unwrap().events.pop()
+    }
+}
+
+/// Distance model defines how volume of sound will decay when distance to listener changes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[repr(u32)]
+pub enum DistanceModel {
+    /// No distance attenuation at all.
+    None = 0,
+
+    /// Distance will decay using following formula:
+    ///
+    /// `clamped_distance = min(max(distance, radius), max_distance)`
+    /// `attenuation = radius / (radius + rolloff_factor * (clamped_distance - radius))`
+    ///
+    /// where - `radius` - of source at which it has maximum volume,
+    ///         `max_distance` - distance at which decay will stop,
+    ///         `rolloff_factor` - coefficient that defines how fast volume will decay
+    #[default]
+    InverseDistance = 1,
+
+    /// Distance will decay using following formula:
+    ///
+    /// `clamped_distance = min(max(distance, radius), max_distance)`
+    /// `attenuation = max(1.0 - (clamped_distance - radius) / (max_distance - radius), 0.0)`
+    LinearDistance = 2,
+
+    /// Distance will decay using following formula:
+    ///
+    /// `clamped_distance = min(max(distance, radius), max_distance)`
+    /// `(clamped_distance / radius) ^ (-rolloff_factor)`
+    ExponentDistance = 3,
 }
 
 /// Internal state of context.
-#[derive(Default, Debug, Clone)]
+#[derive(Debug)]
 pub struct SoundContext {
     sources: Pool<SoundSource>,
     render_duration: Duration,
     bus_graph: AudioBusGraph,
     pub paused: bool,
+    listener: Listener,
+    distance_model: DistanceModel,
+    // Connection with the control handle, if the context is owned by a `SharedSoundContext`.
+    link: Option<RenderLink>,
+    // Amount of frames rendered so far.
+    current_frame: u64,
+    // Scratch buffers for sources with aux sends: their signal is rendered here first and then mixed into
+    // the bus of the source and into the targets of the sends.
+    send_buffer: Vec<(f32, f32)>,
+    ambisonic_send_buffer: Vec<BFormatSample>,
+    // Playback events of a context without a control handle, otherwise events are sent to the handle.
+    events: Vec<SoundEvent>,
+    max_voices: usize,
+    groups: Pool<SoundGroup>,
+    // Scratch buffer for ranking of playing sources: handle, priority and audibility.
+    voices: Vec<(Handle<SoundSource>, u8, f32)>,
+}
+
+impl Default for SoundContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SoundContext {
+    /// Creates new context with the primary audio bus and no sound sources. Such context is not connected to
+    /// any output device, so it has to be rendered manually (see [`SoundContext::render`] and
+    /// [`crate::dissection::offline::OfflineRenderer`]) or attached to an engine via [`SharedSoundContext`].
+    pub fn new() -> Self {
+        Self {
+            sources: Pool::new(),
+            render_duration: Default::default(),
+            bus_graph: AudioBusGraph::new(),
+            paused: false,
+            listener: Listener::new(),
+            distance_model: DistanceModel::InverseDistance,
+            link: None,
+            current_frame: 0,
+            send_buffer: Default::default(),
+            ambisonic_send_buffer: Default::default(),
+            events: Vec::with_capacity(EVENT_QUEUE_CAPACITY),
+            max_voices: usize::MAX,
+            groups: Pool::new(),
+            voices: Default::default(),
+        }
+    }
+
+    // Applies commands sent by the control handle.
+    pub(crate) fn apply_commands(&mut self) {
+        let Some(mut link) = self.link.take() else {
+            return;
+        };
+        link.flush();
+        // Every command produces at most one piece of garbage, the rest wait for the next block if there's
+        // no room for it.
+        while link.can_dispose() {
+            let Some(command) = link.commands.pop() else {
+                break;
+            };
+            match command {
+                Command::ReserveSources(storage) => {
+                    let storage = self.sources.grow_into(storage);
+                    link.dispose(Garbage::SourceStorage(storage));
+                }
+                Command::AddSource(handle, source) => {
+                    if let Err(source) = self.sources.spawn_at_handle(handle, source) {
+                        // Someone added a source directly to the context and took the handle.
+                        link.dispose(Garbage::ReleasedSource(handle, source));
+                    }
+                }
+                Command::RemoveSource(handle) => {
+                    if let Some(source) = self.sources.try_free(handle) {
+                        link.dispose(Garbage::RemovedSource(source));
+                    }
+                }
+                Command::Edit(mut edit) => {
+                    edit.apply(self);
+                    link.dispose(Garbage::Edit(edit));
+                }
+            }
+        }
+        self.link = Some(link);
+    }
+
+    // Sends buffers the sources are done with back to the control handle. Buffers that don't fit wait in
+    // their sources for the next block. Without a handle they're just dropped.
+    fn dispose_retired_buffers(&mut self) {
+        for source in self.sources.iter_mut() {
+            let retired = source.retired_buffers_mut();
+            match self.link.as_mut() {
+                Some(link) => {
+                    while link.can_dispose() {
+                        let Some(buffer) = retired.pop() else {
+                            break;
+                        };
+                        link.dispose(Garbage::Buffer(buffer));
+                    }
+                }
+                None => retired.clear(),
+            }
+        }
+    }
+
+    fn dispose(&mut self, garbage: Garbage) {
+        if let Some(link) = self.link.as_mut() {
+            link.dispose(garbage);
+        }
+    }
+
+    // Gives the context back to its control handle, or drops it if there's none.
+    pub(crate) fn return_home(self) {
+        if let Some(home) = self.link.as_ref().and_then(|link| link.home.upgrade()) {
+            *home.detached.lock().unwrap() = Some(self);
+        }
+    }
+
     /// Returns amount of time context spent on rendering all sound sources.
     pub fn full_render_duration(&self) -> Duration {
         self.render_duration
     }
     /// Adds new sound source and returns handle of it by which it can be accessed later on.
-    pub fn add_source(&mut self, source: SoundSource) -> Handle<SoundSource> {
+    pub fn add_source(&mut self, mut source: SoundSource) -> Handle<SoundSource> {
+        source.reserve_retired();
+        self.sources.reserve(1);
         self.sources.spawn(source)
     }
 
@@ -136,18 +698,114 @@ impl SoundContext {
     }
 
     /// Returns mutable reference to sound source at given handle. If handle is invalid, this method will panic.
-    pub fn try_get_source_mut(
-        &mut self,
-        handle: Handle<SoundSource>,
-    ) -> Option<&mut SoundSource> {
+    pub fn try_get_source_mut(&mut self, handle: Handle<SoundSource>) -> Option<&mut SoundSource> {
         self.sources.try_borrow_mut(handle)
     }
 
+    /// Returns handles of sources that are bound to a bus that does not exist (for example, because of a typo
+    /// in the bus name or because the bus was removed). Such sources produce no sound.
+    pub fn misrouted_sources(&self) -> impl Iterator<Item = Handle<SoundSource>> + '_ {
+        self.sources.pair_iter().filter_map(|(handle, source)| {
+            let bus = source.target_bus(&self.bus_graph);
+            self.bus_graph
+                .try_get_bus_ref(bus)
+                .is_none()
+                .then_some(handle)
+        })
+    }
+
+    /// Adds a new sound group and returns its handle, see [`crate::dissection::group`].
+    pub fn add_group(&mut self, group: SoundGroup) -> Handle<SoundGroup> {
+        self.groups.spawn(group)
+    }
+
+    /// Removes a sound group from the context. Its members keep playing as if they were in no group.
+    pub fn remove_group(&mut self, group: Handle<SoundGroup>) {
+        self.groups.free(group);
+    }
+
+    /// Returns shared reference to a pool with all sound groups.
+    pub fn groups(&self) -> &Pool<SoundGroup> {
+        &self.groups
+    }
+
+    /// Returns shared reference to sound group at given handle. If handle is invalid, this method will panic.
+    pub fn group(&self, handle: Handle<SoundGroup>) -> &SoundGroup {
+        self.groups.borrow(handle)
+    }
+
+    /// Returns mutable reference to sound group at given handle. If handle is invalid, this method will panic.
+    pub fn group_mut(&mut self, handle: Handle<SoundGroup>) -> &mut SoundGroup {
+        self.groups.borrow_mut(handle)
+    }
+
+    /// Returns mutable reference to sound group at given handle, or `None` if the handle is invalid.
+    pub fn try_get_group_mut(&mut self, handle: Handle<SoundGroup>) -> Option<&mut SoundGroup> {
+        self.groups.try_borrow_mut(handle)
+    }
+
+    /// Returns a handle of the first sound group with the given name, or [`Handle::NONE`] if there's no such
+    /// group.
+    pub fn find_group_by_name(&self, name: &str) -> Handle<SoundGroup> {
+        self.groups
+            .pair_iter()
+            .find_map(|(handle, group)| (group.name() == name).then_some(handle))
+            .unwrap_or_default()
+    }
+
+    /// Returns shared reference to the listener.
+    pub fn listener(&self) -> &Listener {
+        &self.listener
+    }
+
+    /// Returns mutable reference to the listener.
+    pub fn listener_mut(&mut self) -> &mut Listener {
+        &mut self.listener
+    }
+
+    /// Sets new distance model. See [`DistanceModel`] docs for more info.
+    pub fn set_distance_model(&mut self, distance_model: DistanceModel) {
+        self.distance_model = distance_model;
+    }
+
+    /// Returns current distance model.
+    pub fn distance_model(&self) -> DistanceModel {
+        self.distance_model
+    }
+
+    /// Sets the maximum amount of sources rendered at once (voices). If more sources are playing, the least
+    /// important ones become virtual: they produce no sound and cost almost nothing, but their playback
+    /// still advances and reports events. Sources are ranked by priority (see [`SoundSource::set_priority`])
+    /// and then by their loudness for the listener, which includes gain, distance attenuation and cone.
+    /// A source that becomes virtual or real again is faded over one block. Default is unlimited.
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        self.max_voices = max_voices;
+    }
+
+    /// Returns the maximum amount of sources rendered at once.
+    pub fn max_voices(&self) -> usize {
+        self.max_voices
+    }
+
+    /// Takes playback events reported since the last call, see [`SoundEvent`]. Events of a context owned by
+    /// a [`SharedSoundContext`] are sent to it instead, see [`SharedSoundContext::poll_event`]. If events are
+    /// not taken, new ones are dropped once there are too many of them.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = SoundEvent> + '_ {
+        self.events.drain(..)
+    }
+
     /// Returns a reference to the audio bus graph.
     pub fn bus_graph_ref(&self) -> &AudioBusGraph {
         &self.bus_graph
     }
 
+    /// Returns the amount of frames rendered by the context so far, the next rendered block starts at this
+    /// frame. The clock does not advance while the context is paused. Scheduled changes (see
+    /// [`crate::dissection::automation`]) use this clock.
+    pub fn current_frame(&self) -> u64 {
+        self.current_frame
+    }
+
     /// Returns a reference to the audio bus graph.
     pub fn bus_graph_mut(&mut self) -> &mut AudioBusGraph {
         &mut self.bus_graph
@@ -168,89 +826,263 @@ impl SoundContext {
         }
     }
 
+    /// Renders one block of samples into the given buffer. Once the context is warmed up (every source and
+    /// bus has rendered a block of the same or bigger size), this method does not allocate memory and does
+    /// not take any locks, so it is safe to call from a real-time thread. Adding buses, or adding sources
+    /// directly (not through [`SharedSoundContext`]), could still grow internal storage.
     pub fn render(&mut self, output_device_buffer: &mut [(f32, f32)]) {
-        // Clear output first so we can detect if audio is actually being written
+        self.apply_commands();
+
         output_device_buffer.fill((0.0, 0.0));
-        eprintln!(
-            "[Audio] Render started, buffer len: {}",
-            output_device_buffer.len()
-        );
 
         let last_time = Instant::now();
 
         if self.paused {
-            eprintln!("[Audio] System paused - no processing");
             return;
         }
 
-        // Check sources
-        let active_sources: usize = self
-            .sources
-            .iter()
-            .filter(|s| s.status == Status::Playing)
-            .count();
-        eprintln!("[Audio] Active sources: {}", active_sources);
-
-        self.sources.retain(|source| {
-            let done = source.play_once && source.status == Status::Stopped;
-            if done {
-                eprintln!("[Audio] Removing finished source");
+        for index in 0..self.sources.get_capacity() {
+            let handle = self.sources.handle_from_index(index);
+            let done = self.sources.try_borrow(handle).is_some_and(|source| {
+                source.play_once
+                    && source.status == Status::Stopped
+                    && source.scheduled_start().is_none()
+            });
+            // Finished sources stay in the pool until there's room to send them back.
+            if done && self.link.as_ref().is_none_or(|link| link.can_dispose()) {
+                if let Some(source) = self.sources.try_free(handle) {
+                    self.dispose(Garbage::ReleasedSource(handle, source));
+                }
             }
-            !done
-        });
+        }
 
-        // Verify bus graph
-        eprintln!("[Audio] Beginning bus graph render");
-        self.bus_graph.begin_render(output_device_buffer.len());
+        let time = self.current_frame;
+        let amount = output_device_buffer.len();
+        self.current_frame += amount as u64;
 
-        // Process each active source
-        for source in self
-            .sources
-            .iter_mut()
-            .filter(|s| s.status == Status::Playing)
-        {
-            eprintln!("[Audio] Processing source -> bus '{}'", source.bus);
+        self.bus_graph.begin_render(amount);
+        self.bus_graph.update_automation(time, amount);
+        // Automation runs even for sources that aren't playing, so their timelines stay in sync with the clock.
+        for source in self.sources.iter_mut() {
+            source.update_automation(time, amount);
+        }
 
-            if let Some(bus_input_buffer) = self.bus_graph.try_get_bus_input_buffer(&source.bus)
-            {
-                eprintln!(
-                    "[Audio]  Found bus buffer (len: {})",
-                    bus_input_buffer.len()
-                );
+        self.update_groups(time, amount);
+        self.update_voices();
 
+        let ambisonic = self.bus_graph.is_ambisonic();
+
+        // Render sounds to respective audio buses.
+        for (handle, source) in self.sources.pair_iter_mut().filter(|(_, s)| s.is_running()) {
+            if source.is_virtual() {
+                // Virtual sources only advance their playback.
                 source.render(output_device_buffer.len());
-                eprintln!(
-                    "[Audio]  Source rendered {} samples",
-                    source.frame_samples().len()
-                );
+                report_events(handle, source, time, &mut self.link, &mut self.events);
+                continue;
+            }
+
+            let bus = source.resolve_bus(&self.bus_graph);
+
+            if source.sends().is_empty() {
+                if let Some((bus_input_buffer, ambisonic_input_buffer)) =
+                    self.bus_graph.try_get_bus_input_buffers(bus)
+                {
+                    source.render(output_device_buffer.len());
+                    report_events(handle, source, time, &mut self.link, &mut self.events);
 
-                render_source_default(source, bus_input_buffer);
-
-                // Debug: Check if bus buffer was written to
-                let written_samples = bus_input_buffer
-                    .iter()
-                    .filter(|&&s| s != (0.0, 0.0))
-                    .count();
-                eprintln!(
-                    "[Audio]  Bus buffer modified samples: {}/{}",
-                    written_samples,
-                    bus_input_buffer.len()
+                    if ambisonic {
+                        render_source_ambisonic(
+                            source,
+                            &self.listener,
+                            self.distance_model,
+                            bus_input_buffer,
+                            ambisonic_input_buffer,
+                        );
+                    } else {
+                        render_source_default(
+                            source,
+                            &self.listener,
+                            self.distance_model,
+                            bus_input_buffer,
+                        );
+                    }
+                }
+                continue;
+            }
+
+            // The source has sends, so it is rendered into scratch buffers first. Such source is rendered even
+            // if its own bus is invalid.
+            source.render(output_device_buffer.len());
+            report_events(handle, source, time, &mut self.link, &mut self.events);
+            source.mix_pre_fader_sends(&mut self.bus_graph);
+
+            self.send_buffer.clear();
+            self.send_buffer
+                .resize(output_device_buffer.len(), (0.0, 0.0));
+            self.ambisonic_send_buffer.clear();
+            if ambisonic {
+                self.ambisonic_send_buffer
+                    .resize(output_device_buffer.len(), BFormatSample::default());
+                render_source_ambisonic(
+                    source,
+                    &self.listener,
+                    self.distance_model,
+                    &mut self.send_buffer,
+                    &mut self.ambisonic_send_buffer,
                 );
             } else {
-                eprintln!("[Audio]  No bus found for '{}'", source.bus);
+                render_source_default(
+                    source,
+                    &self.listener,
+                    self.distance_model,
+                    &mut self.send_buffer,
+                );
+            }
+
+            if let Some((bus_input_buffer, ambisonic_input_buffer)) =
+                self.bus_graph.try_get_bus_input_buffers(bus)
+            {
+                for ((left, right), (send_left, send_right)) in
+                    bus_input_buffer.iter_mut().zip(self.send_buffer.iter())
+                {
+                    *left += *send_left;
+                    *right += *send_right;
+                }
+                for (sample, send_sample) in ambisonic_input_buffer
+                    .iter_mut()
+                    .zip(self.ambisonic_send_buffer.iter())
+                {
+                    *sample += *send_sample;
+                }
             }
+            source.mix_post_fader_sends(
+                &mut self.bus_graph,
+                &self.send_buffer,
+                &self.ambisonic_send_buffer,
+            );
         }
 
-        // Final mix
-        eprintln!("[Audio] Final bus graph mix");
-        self.bus_graph.end_render(output_device_buffer);
+        self.dispose_retired_buffers();
 
-        // Verify output
-        let silent_output = output_device_buffer.iter().all(|&s| s == (0.0, 0.0));
-        eprintln!("[Audio] Output buffer silent: {}", silent_output);
+        self.bus_graph
+            .end_render(output_device_buffer, &self.listener);
 
         self.render_duration = Instant::now().duration_since(last_time);
-        eprintln!("[Audio] Render completed in {:?}", self.render_duration);
+    }
+
+    // Advances sound groups, passes their state to the members and enforces instance limits. See
+    // `crate::dissection::group`.
+    fn update_groups(&mut self, time: u64, amount: usize) {
+        for group in self.groups.iter_mut() {
+            group.update(time, amount);
+            group.instances = 0;
+        }
+
+        for source in self.sources.iter_mut() {
+            let Some(group) = self.groups.try_borrow_mut(source.group()) else {
+                source.set_group_state(1.0, 1.0, false);
+                continue;
+            };
+            if group.stop_members {
+                let _ = source.stop();
+            }
+            source.set_group_state(group.effective_gain(), group.pitch(), group.is_paused());
+            if source.status == Status::Playing && !source.is_fading_out_to_stop() {
+                group.instances += 1;
+            }
+        }
+
+        for index in 0..self.groups.get_capacity() {
+            let handle = self.groups.handle_from_index(index);
+            let Some(group) = self.groups.try_borrow(handle) else {
+                continue;
+            };
+            let policy = group.steal_policy();
+            for _ in group.max_instances()..group.instances {
+                let victim = self
+                    .sources
+                    .pair_iter()
+                    .filter(|(_, source)| {
+                        source.group() == handle
+                            && source.status == Status::Playing
+                            && !source.is_fading_out_to_stop()
+                    })
+                    .min_by(|(_, a), (_, b)| match policy {
+                        StealPolicy::Oldest => a.start_time.cmp(&b.start_time),
+                        StealPolicy::RejectNew => b.start_time.cmp(&a.start_time),
+                        StealPolicy::Quietest => a
+                            .audibility(&self.listener, self.distance_model)
+                            .total_cmp(&b.audibility(&self.listener, self.distance_model)),
+                    })
+                    .map(|(victim, _)| victim);
+                if let Some(victim) = victim {
+                    self.sources.borrow_mut(victim).steal();
+                }
+            }
+        }
+    }
+
+    // Picks the most important playing sources to be rendered, the rest become virtual. See
+    // `set_max_voices`.
+    fn update_voices(&mut self) {
+        let playing = self
+            .sources
+            .iter()
+            .filter(|source| source.is_running())
+            .count();
+        if playing <= self.max_voices {
+            for source in self.sources.iter_mut() {
+                source.set_voice_active(true);
+            }
+            return;
+        }
+
+        self.voices.clear();
+        self.voices.reserve(self.sources.get_capacity() as usize);
+        for (handle, source) in self
+            .sources
+            .pair_iter()
+            .filter(|(_, source)| source.is_running())
+        {
+            let mut audibility = source.audibility(&self.listener, self.distance_model);
+            if source.is_voice_active() {
+                audibility *= VOICE_HYSTERESIS;
+            }
+            self.voices.push((handle, source.priority(), audibility));
+        }
+        self.voices
+            .sort_unstable_by(|a, b| b.1.cmp(&a.1).then(b.2.total_cmp(&a.2)));
+        for (rank, (handle, _, _)) in self.voices.iter().enumerate() {
+            self.sources
+                .borrow_mut(*handle)
+                .set_voice_active(rank < self.max_voices);
+        }
+    }
+}
+
+// Sends events of a rendered source to the control handle of the context, or stores them in the context if
+// there's no handle.
+fn report_events(
+    handle: Handle<SoundSource>,
+    source: &SoundSource,
+    time: u64,
+    link: &mut Option<RenderLink>,
+    events: &mut Vec<SoundEvent>,
+) {
+    for &(kind, offset) in source.events() {
+        let event = SoundEvent {
+            source: handle,
+            kind,
+            time: time + offset as u64,
+        };
+        match link.as_mut() {
+            Some(link) => {
+                let _ = link.events.push(event);
+            }
+            // The capacity is reserved when the context is created, so this never allocates.
+            None if events.len() < events.capacity() => events.push(event),
+            None => (),
+        }
     }
 }
 
@@ -287,11 +1119,620 @@ fn render_with_params(
     }
 }
 
-pub fn render_source_default(source: &mut SoundSource, mix_buffer: &mut [(f32, f32)]) {
-    let panning = 0.0;
-    let left_gain = source.gain * (1.0 + panning);
-    let right_gain = source.gain * (1.0 - panning);
+pub fn render_source_default(
+    source: &mut SoundSource,
+    listener: &Listener,
+    distance_model: DistanceModel,
+    mix_buffer: &mut [(f32, f32)],
+) {
+    let spatial_blend = source.spatial_blend;
+
+    // Air absorption must be applied to the rendered samples before they're mixed into the bus.
+    let air_coefficient = lerp(
+        0.0,
+        source.calculate_air_absorption_coefficient(listener),
+        spatial_blend,
+    );
+    source.apply_air_absorption(air_coefficient);
+    source.apply_gain();
+
+    let distance_gain = lerp(
+        1.0,
+        source.calculate_distance_gain(listener, distance_model),
+        spatial_blend,
+    );
+    let cone_gain = lerp(1.0, source.calculate_cone_gain(listener), spatial_blend);
+    let panning = lerp(0.0, source.calculate_panning(listener), spatial_blend);
+    // Gain of the source itself is already applied to the samples.
+    let gain = distance_gain * cone_gain;
+    let left_gain = gain * (1.0 - panning);
+    let right_gain = gain * (1.0 + panning);
     render_with_params(source, left_gain, right_gain, mix_buffer);
     source.last_left_gain = Some(left_gain);
     source.last_right_gain = Some(right_gain);
 }
+
+/// Renders a source into ambisonic bus. Spatial part of the source (see `spatial_blend`) is downmixed to
+/// mono and encoded into B-format by direction from the listener to the source, while non-spatial part is
+/// mixed into stereo input of the bus with no panning.
+pub fn render_source_ambisonic(
+    source: &mut SoundSource,
+    listener: &Listener,
+    distance_model: DistanceModel,
+    mix_buffer: &mut [(f32, f32)],
+    ambisonic_mix_buffer: &mut [BFormatSample],
+) {
+    let spatial_blend = source.spatial_blend;
+
+    let air_coefficient = lerp(
+        0.0,
+        source.calculate_air_absorption_coefficient(listener),
+        spatial_blend,
+    );
+    source.apply_air_absorption(air_coefficient);
+    source.apply_gain();
+
+    let distance_gain = lerp(
+        1.0,
+        source.calculate_distance_gain(listener, distance_model),
+        spatial_blend,
+    );
+    let cone_gain = lerp(1.0, source.calculate_cone_gain(listener), spatial_blend);
+    let gain = distance_gain * cone_gain;
+
+    let direct_gain = gain * (1.0 - spatial_blend);
+    render_with_params(source, direct_gain, direct_gain, mix_buffer);
+    source.last_left_gain = Some(direct_gain);
+    source.last_right_gain = Some(direct_gain);
+
+    let encoding_gains = BFormatSample::encoding_gains(source.position() - listener.position())
+        * (gain * spatial_blend);
+    let last_encoding_gains = *source
+        .last_ambisonic_gains
+        .get_or_insert(encoding_gains);
+    let step = 1.0 / ambisonic_mix_buffer.len().max(1) as f32;
+    let mut t = 0.0;
+    for (output, &(raw_left, raw_right)) in
+        ambisonic_mix_buffer.iter_mut().zip(source.frame_samples())
+    {
+        // Interpolate encoding gains to remove clicks when a source moves fast.
+        let gains = last_encoding_gains.lerp(&encoding_gains, t);
+        *output += gains * (0.5 * (raw_left + raw_right));
+        t += step;
+    }
+    source.last_ambisonic_gains = Some(encoding_gains);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        backend::NullBackend,
+        buffer::{Buffer, CueMarker},
+        bus::{AudioBus, AuxSend, SendTap},
+        effects::{Attenuate, Effect},
+        engine::{SharedSoundContext, SharedSoundEngine, SoundContext, GARBAGE_QUEUE_CAPACITY},
+        group::{SoundGroup, StealPolicy},
+        source::{SoundEvent, SoundEventKind, SoundSource, SourceParameter, Status},
+    };
+    use crate::SAMPLE_RATE;
+    use std::time::Duration;
+
+    fn make_source(play_once: bool) -> SoundSource {
+        let mut source = SoundSource::default();
+        source.set_buffer(Some(Buffer::new(vec![0.5; 64], true).into()));
+        source.looping = !play_once;
+        source.play_once = play_once;
+        source.status = Status::Playing;
+        source
+    }
+
+    #[test]
+    fn test_bus_routing() {
+        let mut context = SoundContext::new();
+        let graph = context.bus_graph_mut();
+        let sfx = graph
+            .add_bus(AudioBus::new("SFX".to_string()), graph.primary_bus_handle())
+            .unwrap();
+
+        let mut by_name = make_source(false);
+        by_name.set_bus("SFX");
+        let by_name = context.add_source(by_name);
+        let mut by_handle = make_source(false);
+        by_handle.set_bus_handle(sfx);
+        let by_handle = context.add_source(by_handle);
+        let mut typo = make_source(false);
+        typo.set_bus("SXF");
+        let typo = context.add_source(typo);
+
+        assert_eq!(context.misrouted_sources().collect::<Vec<_>>(), [typo]);
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        assert_eq!(block[0], (1.0, 1.0));
+        assert_eq!(context.source(by_name).bus_handle(), sfx);
+        assert_eq!(context.source(by_handle).bus_handle(), sfx);
+
+        // Names are resolved again when the graph changes.
+        context
+            .bus_graph_mut()
+            .try_get_bus_mut(sfx)
+            .unwrap()
+            .set_name("SXF");
+        context.render(&mut block);
+        assert_eq!(context.source(typo).bus_handle(), sfx);
+        assert_eq!(context.misrouted_sources().collect::<Vec<_>>(), [by_name]);
+
+        context.bus_graph_mut().remove_bus(sfx).unwrap();
+        assert_eq!(context.misrouted_sources().count(), 3);
+        context.render(&mut block);
+        assert_eq!(block[0], (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_source_sends() {
+        let mut context = SoundContext::new();
+        let graph = context.bus_graph_mut();
+        let reverb = graph
+            .add_bus(
+                AudioBus::new("Reverb".to_string()),
+                graph.primary_bus_handle(),
+            )
+            .unwrap();
+        let mut source = make_source(false);
+        source.spatial_blend = 0.0;
+        source.gain = 0.5;
+        source.add_send(AuxSend::new(reverb, 0.5, SendTap::PostFader));
+        let source = context.add_source(source);
+
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        // Dry: 0.5 * 0.5 = 0.25, post-fader send: 0.25 * 0.5 = 0.125
+        assert_eq!(block[0], (0.375, 0.375));
+
+        context
+            .source_mut(source)
+            .send_mut(0)
+            .unwrap()
+            .set_tap(SendTap::PreFader);
+        context.render(&mut block);
+        // Pre-fader send: 0.5 * 0.5 = 0.25
+        assert_eq!(block[0], (0.5, 0.5));
+
+        // A source with sends is rendered even if its own bus does not exist.
+        context.source_mut(source).set_bus("None");
+        context.render(&mut block);
+        assert_eq!(block[0], (0.25, 0.25));
+        assert!(context.source_mut(source).remove_send(0).is_some());
+        context.render(&mut block);
+        assert_eq!(block[0], (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_source_automation() {
+        let mut context = SoundContext::new();
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        assert_eq!(context.current_frame(), 16);
+
+        let mut source = make_source(false);
+        source.spatial_blend = 0.0;
+        source
+            .automation_mut(SourceParameter::Gain)
+            .set_value_at(20, 0.5)
+            .linear_ramp_to(1.0, 24, 4);
+        let source = context.add_source(source);
+        context.render(&mut block);
+        assert_eq!(block[3], (0.5, 0.5));
+        assert_eq!(block[4], (0.25, 0.25));
+        assert_eq!(block[8], (0.25, 0.25));
+        assert_eq!(block[10], (0.375, 0.375));
+        assert_eq!(block[12], (0.5, 0.5));
+        assert_eq!(context.source(source).gain, 1.0);
+
+        // Pitch changes exactly at the scheduled frame.
+        context
+            .source_mut(source)
+            .automation_mut(SourceParameter::Pitch)
+            .set_value_at(40, 2.0);
+        context.render(&mut block);
+        assert_eq!(
+            context.source(source).playback_time(),
+            Duration::from_secs_f64(40.0 / SAMPLE_RATE as f64)
+        );
+    }
+
+    #[test]
+    fn test_scheduled_start_and_stop() {
+        let mut context = SoundContext::new();
+        let mut sources = Vec::new();
+        for play_once in [true, false] {
+            let mut source = make_source(play_once);
+            source.spatial_blend = 0.0;
+            source.status = Status::Stopped;
+            source.play_at(20);
+            sources.push(context.add_source(source));
+        }
+        context.source_mut(sources[0]).stop_at(28);
+
+        // Scheduled play-once source isn't removed before it starts.
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        assert_eq!(block, [(0.0, 0.0); 16]);
+        assert!(context.sources().is_valid_handle(sources[0]));
+
+        context.render(&mut block);
+        assert_eq!(block[3], (0.0, 0.0));
+        assert_eq!(block[4], (1.0, 1.0));
+        assert_eq!(block[11], (1.0, 1.0));
+        assert_eq!(block[12], (0.5, 0.5));
+        let first = context.source(sources[0]);
+        assert_eq!(first.status, Status::Stopped);
+        assert_eq!(first.playback_time(), Duration::ZERO);
+        let second = context.source(sources[1]);
+        assert_eq!(second.scheduled_start(), None);
+        assert_eq!(
+            second.playback_time(),
+            Duration::from_secs_f64(12.0 / SAMPLE_RATE as f64)
+        );
+    }
+
+    #[test]
+    fn test_fades() {
+        let mut context = SoundContext::new();
+        let mut source = make_source(false);
+        source.spatial_blend = 0.0;
+        source.status = Status::Stopped;
+        source.play_at(4).fade_in(8);
+        let source = context.add_source(source);
+
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        assert_eq!(block[3], (0.0, 0.0));
+        assert_eq!(block[4], (0.0, 0.0));
+        assert_eq!(block[6], (0.125, 0.125));
+        assert_eq!(block[12], (0.5, 0.5));
+
+        context.source_mut(source).fade_out_and_stop(8);
+        context.render(&mut block);
+        assert_eq!(block[0], (0.5, 0.5));
+        assert_eq!(block[4], (0.25, 0.25));
+        assert_eq!(block[8], (0.0, 0.0));
+        assert_eq!(context.source(source).status, Status::Stopped);
+
+        // The fade is reset when the source stops.
+        context.source_mut(source).status = Status::Playing;
+        context.render(&mut block);
+        assert_eq!(block[0], (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_fades_reach_pre_fader_sends() {
+        let mut context = SoundContext::new();
+        let graph = context.bus_graph_mut();
+        let reverb = graph
+            .add_bus(
+                AudioBus::new("Reverb".to_string()),
+                graph.primary_bus_handle(),
+            )
+            .unwrap();
+        // Only the send is heard.
+        let mut source = make_source(false);
+        source.spatial_blend = 0.0;
+        source.gain = 0.0;
+        source.add_send(AuxSend::new(reverb, 1.0, SendTap::PreFader));
+        let source = context.add_source(source);
+
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        assert_eq!(block[0], (0.5, 0.5));
+
+        context.source_mut(source).fade_out_and_stop(8);
+        context.render(&mut block);
+        assert_eq!(block[0], (0.5, 0.5));
+        assert_eq!(block[4], (0.25, 0.25));
+        assert_eq!(block[8], (0.0, 0.0));
+        assert_eq!(context.source(source).status, Status::Stopped);
+    }
+
+    #[test]
+    fn test_playback_events() {
+        let buffer = Buffer::new(vec![0.5; 64], true).with_markers(vec![CueMarker::new(7, 10)]);
+        let mut context = SoundContext::new();
+        let mut one_shot = make_source(true);
+        one_shot.set_buffer(Some(buffer.clone().into()));
+        let one_shot = context.add_source(one_shot);
+        let mut looping = make_source(false);
+        looping.set_buffer(Some(buffer.into()));
+        looping.status = Status::Stopped;
+        looping.play_at(4);
+        let looping = context.add_source(looping);
+
+        let mut block = [(0.0, 0.0); 16];
+        for _ in 0..5 {
+            context.render(&mut block);
+        }
+        let event = |source, kind, time| SoundEvent { source, kind, time };
+        assert_eq!(
+            context.drain_events().collect::<Vec<_>>(),
+            [
+                event(one_shot, SoundEventKind::Started, 0),
+                event(one_shot, SoundEventKind::Marker(7), 10),
+                event(looping, SoundEventKind::Started, 4),
+                event(looping, SoundEventKind::Marker(7), 14),
+                event(one_shot, SoundEventKind::Finished, 64),
+                event(looping, SoundEventKind::Looped, 68),
+                event(looping, SoundEventKind::Marker(7), 78),
+            ]
+        );
+
+        // Shared contexts send events to the control handle.
+        let context = SharedSoundContext::new();
+        let source = context.add_source(make_source(true));
+        for _ in 0..5 {
+            context.with_detached(|context| context.render(&mut block));
+        }
+        assert_eq!(
+            context.poll_event(),
+            Some(event(source, SoundEventKind::Started, 0))
+        );
+        assert_eq!(
+            context.poll_event(),
+            Some(event(source, SoundEventKind::Finished, 64))
+        );
+        assert_eq!(context.poll_event(), None);
+    }
+
+    #[test]
+    fn test_voice_limit() {
+        let mut context = SoundContext::new();
+        context.set_max_voices(2);
+        let handles = [1.0, 0.5, 0.25].map(|gain| {
+            let mut source = make_source(false);
+            source.set_gain(gain).spatial_blend = 0.0;
+            context.add_source(source)
+        });
+        let [loud, medium, quiet] = handles;
+
+        // The quietest source is virtual right from the start.
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        assert_eq!(block, [(0.75, 0.75); 16]);
+        assert!(context.source(quiet).is_virtual());
+        assert_eq!(
+            context.source(quiet).playback_time(),
+            context.source(loud).playback_time()
+        );
+
+        // Priority beats loudness, voices are swapped smoothly.
+        context.source_mut(quiet).set_priority(200);
+        context.render(&mut block);
+        assert_eq!(block[0], (0.75, 0.75));
+        assert!(block.iter().all(|(left, _)| *left > 0.6 && *left < 0.76));
+        assert!(!context.source(quiet).is_virtual());
+        context.render(&mut block);
+        assert_eq!(block, [(0.625, 0.625); 16]);
+        assert!(context.source(medium).is_virtual());
+
+        // Virtual sources still finish and report it.
+        context.source_mut(medium).looping = false;
+        for _ in 0..4 {
+            context.render(&mut block);
+        }
+        assert_eq!(context.source(medium).status, Status::Stopped);
+        assert!(context
+            .drain_events()
+            .any(|event| event.source == medium && event.kind == SoundEventKind::Finished));
+    }
+
+    #[test]
+    fn test_sound_groups() {
+        let mut context = SoundContext::new();
+        let mut group = SoundGroup::new("UI");
+        group.set_gain(0.5).set_pitch(2.0);
+        let group = context.add_group(group);
+        assert_eq!(context.find_group_by_name("UI"), group);
+        let mut source = make_source(false);
+        source.set_group(group).spatial_blend = 0.0;
+        let source = context.add_source(source);
+
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        assert_eq!(block, [(0.25, 0.25); 16]);
+        assert_eq!(
+            context.source(source).playback_time(),
+            Duration::from_secs_f64(32.0 / SAMPLE_RATE as f64)
+        );
+
+        // Members are faded out and paused, but keep their position.
+        context.group_mut(group).pause(16);
+        for _ in 0..3 {
+            context.render(&mut block);
+        }
+        assert!(context.group(group).is_paused());
+        assert_eq!(block, [(0.0, 0.0); 16]);
+        let position = context.source(source).playback_time();
+        context.render(&mut block);
+        assert_eq!(context.source(source).playback_time(), position);
+        assert_eq!(context.source(source).status, Status::Playing);
+
+        context.group_mut(group).resume(0);
+        context.render(&mut block);
+        context.render(&mut block);
+        assert_eq!(block, [(0.25, 0.25); 16]);
+
+        context.group_mut(group).stop(0);
+        context.render(&mut block);
+        assert_eq!(block[0], (0.25, 0.25));
+        context.render(&mut block);
+        assert_eq!(context.source(source).status, Status::Stopped);
+        assert_eq!(context.group(group).effective_gain(), 0.5);
+    }
+
+    #[test]
+    fn test_group_instance_limit() {
+        for (policy, stolen) in [
+            (StealPolicy::Oldest, 0),
+            (StealPolicy::Quietest, 1),
+            (StealPolicy::RejectNew, 2),
+        ] {
+            let mut context = SoundContext::new();
+            let mut group = SoundGroup::new("Footsteps");
+            group.set_max_instances(2).set_steal_policy(policy);
+            let group = context.add_group(group);
+
+            let mut block = [(0.0, 0.0); 512];
+            let sources = [1.0, 0.1, 1.0].map(|gain| {
+                let mut source = make_source(false);
+                source.set_group(group).set_gain(gain).spatial_blend = 0.0;
+                let source = context.add_source(source);
+                context.render(&mut block);
+                source
+            });
+            // Stolen sources are faded out quickly.
+            context.render(&mut block);
+            for (index, source) in sources.into_iter().enumerate() {
+                let expected = if index == stolen {
+                    Status::Stopped
+                } else {
+                    Status::Playing
+                };
+                assert_eq!(context.source(source).status, expected, "{policy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bus_and_effect_automation() {
+        let mut context = SoundContext::new();
+        let graph = context.bus_graph_mut();
+        let mut bus = AudioBus::new("Bus".to_string());
+        let effect = bus.add_effect(Effect::Attenuate(Attenuate::new(1.0)));
+        bus.gain_automation_mut().set_value_at(4, 0.5);
+        bus.effect_automation_mut(effect, Attenuate::GAIN)
+            .unwrap()
+            .set_value_at(8, 0.5);
+        assert!(bus.effect_automation_mut(effect, 1).is_none());
+        let bus = graph.add_bus(bus, graph.primary_bus_handle()).unwrap();
+
+        let mut source = make_source(false);
+        source.spatial_blend = 0.0;
+        source.set_bus_handle(bus);
+        context.add_source(source);
+
+        let mut block = [(0.0, 0.0); 16];
+        context.render(&mut block);
+        assert_eq!(block[3], (0.5, 0.5));
+        assert_eq!(block[4], (0.25, 0.25));
+        assert_eq!(block[8], (0.125, 0.125));
+        let bus = context.bus_graph_ref().try_get_bus_ref(bus).unwrap();
+        assert_eq!(bus.gain(), 0.5);
+        assert_eq!(
+            bus.effect(effect),
+            Some(&Effect::Attenuate(Attenuate::new(0.5)))
+        );
+    }
+
+    #[test]
+    fn test_reply_is_taken_once() {
+        let context = SharedSoundContext::new();
+        let source = context.add_source(make_source(false));
+        for _ in 0..100 {
+            let reply = context.query(move |context| context.is_valid_handle(source));
+            let taken = std::thread::scope(|scope| {
+                let threads = [(); 2].map(|_| scope.spawn(|| reply.try_take()));
+                threads.map(|thread| thread.join().unwrap())
+            });
+            assert_eq!(taken.iter().flatten().count(), 1);
+            assert!(!reply.is_ready());
+        }
+    }
+
+    #[test]
+    fn test_detached_context_applies_commands_immediately() {
+        let context = SharedSoundContext::new();
+        assert!(!context.is_attached());
+
+        let source = context.add_source(make_source(false));
+        context.edit_source(source, |source| {
+            source.set_pitch(0.5);
+        });
+        let reply = context.query(move |context| context.is_valid_handle(source));
+        assert_eq!(reply.try_take(), Some(true));
+
+        context.remove_source(source);
+        let removed = context.with_detached(move |context| !context.is_valid_handle(source));
+        assert_eq!(removed, Some(true));
+
+        // The handle is released, so it could be reused.
+        let other = context.add_source(make_source(false));
+        assert_ne!(source, other);
+    }
+
+    #[test]
+    fn test_garbage_overflow() {
+        let context = SharedSoundContext::new();
+        // Finished one-shot sources are removed by the next rendered block.
+        let count = 2 * GARBAGE_QUEUE_CAPACITY + 10;
+        for _ in 0..count {
+            let mut source = make_source(true);
+            source.status = Status::Stopped;
+            context.add_source(source);
+        }
+        let alive_handles = || {
+            let control = context.state().control.lock().unwrap();
+            control.handles.alive_count() as usize
+        };
+
+        // Nobody collects garbage while the block is rendered, the queue and the overflow storage are filled
+        // up and the rest of the sources wait.
+        let render = |context: &mut SoundContext| {
+            context.render(&mut [(0.0, 0.0); 16]);
+            context.sources.alive_count()
+        };
+        assert_eq!(context.with_detached(render), Some(10));
+        // Every released handle got back to the control side, nothing was dropped on the way.
+        assert_eq!(alive_handles(), 10 + GARBAGE_QUEUE_CAPACITY);
+        // The overflow is sent first, the last sources don't fit into the queue this time.
+        assert_eq!(context.with_detached(render), Some(0));
+        assert_eq!(alive_handles(), 10);
+        assert_eq!(context.with_detached(render), Some(0));
+        assert_eq!(alive_handles(), 0);
+    }
+
+    #[test]
+    fn test_attached_context() {
+        let engine =
+            SharedSoundEngine::with_backend(NullBackend::default(), Default::default()).unwrap();
+        let context = SharedSoundContext::new();
+        let looping = context.add_source(make_source(false));
+        let one_shot = context.add_source(make_source(true));
+        engine.set_context(context.clone()).unwrap();
+        assert!(context.is_attached());
+        assert!(context.with_detached(|_| ()).is_none());
+        assert!(engine.set_context(context.clone()).is_err());
+
+        // One-shot source is removed by the render thread once it's finished.
+        let timeout = Duration::from_secs(2);
+        let valid = context
+            .query(move |context| {
+                (
+                    context.is_valid_handle(looping),
+                    context.is_valid_handle(one_shot),
+                )
+            })
+            .wait(timeout);
+        std::thread::sleep(Duration::from_millis(100));
+        let finished = context
+            .query(move |context| context.is_valid_handle(one_shot))
+            .wait(timeout);
+        assert_eq!(valid.map(|(looping, _)| looping), Some(true));
+        assert_eq!(finished, Some(false));
+
+        // Stopped engine gives the context back.
+        engine.stop().unwrap();
+        assert!(!context.is_attached());
+        assert_eq!(
+            context.with_detached(move |context| context.is_valid_handle(looping)),
+            Some(true)
+        );
+    }
+}
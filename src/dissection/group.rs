@@ -0,0 +1,251 @@
+//! Sound groups for bulk control of many sources.
+//!
+//! # Overview
+//!
+//! A sound group is a named set of sound sources, for example "UI" or "Level", that could be controlled at
+//! once: its gain and pitch multiply the ones of every member, and it could be paused, resumed and stopped
+//! with a fade. Groups are independent of bus routing, sources of one group could play on different buses.
+//! A source joins a group by [`crate::dissection::source::SoundSource::set_group`].
+//!
+//! A group could limit the amount of its playing sources (instances), see [`SoundGroup::set_max_instances`].
+//! When a source starts in a full group, an instance is stolen according to the [`StealPolicy`].
+//!
+//! Changes of a group take effect at the next rendered block, gain changes and fades are smooth.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use audio::dissection::engine::SoundContext;
+//! use audio::dissection::group::{SoundGroup, StealPolicy};
+//! use audio::dissection::source::SoundSource;
+//!
+//! let mut context = SoundContext::new();
+//! let mut footsteps = SoundGroup::new("Footsteps");
+//! footsteps
+//!     .set_max_instances(4)
+//!     .set_steal_policy(StealPolicy::Oldest);
+//! let footsteps = context.add_group(footsteps);
+//!
+//! let mut source = SoundSource::default();
+//! source.set_group(footsteps);
+//! context.add_source(source);
+//!
+//! // Fade out every footstep over 100 milliseconds and pause them.
+//! context.group_mut(footsteps).pause(4410);
+//! ```
+
+use super::automation::Automation;
+
+/// Defines which instance is stopped when a source starts in a group that already has the maximum amount of
+/// playing instances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StealPolicy {
+    /// The instance that started the earliest is faded out.
+    #[default]
+    Oldest,
+    /// The instance that is the least audible for the listener is faded out.
+    Quietest,
+    /// The new instance is stopped before it makes any sound.
+    RejectNew,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupAction {
+    Pause,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingFade {
+    target: f32,
+    duration: u64,
+    action: Option<GroupAction>,
+}
+
+/// Named set of sound sources that are controlled together. See module docs.
+#[derive(Debug, Clone)]
+pub struct SoundGroup {
+    name: String,
+    gain: f32,
+    pitch: f64,
+    paused: bool,
+    max_instances: usize,
+    steal_policy: StealPolicy,
+    fade: Automation,
+    fade_gain: f32,
+    // Per-frame values of the fade in the current block, only the last one is used.
+    fade_curve: Vec<f32>,
+    // Fade requested by `pause`, `resume` or `stop`, it is scheduled when the clock time is known.
+    pending_fade: Option<PendingFade>,
+    // Action that is done when the fade is over and the frame it happens at.
+    action: Option<(GroupAction, u64)>,
+    // `true` if the members must be stopped in the current block.
+    pub(crate) stop_members: bool,
+    // Amount of playing members in the current block.
+    pub(crate) instances: usize,
+}
+
+impl Default for SoundGroup {
+    fn default() -> Self {
+        Self {
+            name: Default::default(),
+            gain: 1.0,
+            pitch: 1.0,
+            paused: false,
+            max_instances: usize::MAX,
+            steal_policy: Default::default(),
+            fade: Default::default(),
+            fade_gain: 1.0,
+            fade_curve: Default::default(),
+            pending_fade: None,
+            action: None,
+            stop_members: false,
+            instances: 0,
+        }
+    }
+}
+
+impl SoundGroup {
+    /// Creates a new group with the given name, unit gain and pitch and no instance limit.
+    pub fn new<S: AsRef<str>>(name: S) -> Self {
+        Self {
+            name: name.as_ref().to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the name of the group.
+    pub fn set_name<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
+        self.name = name.as_ref().to_string();
+        self
+    }
+
+    /// Returns the name of the group.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the gain of the group, it multiplies the gain of every member.
+    pub fn set_gain(&mut self, gain: f32) -> &mut Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Returns the gain of the group.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Sets the pitch multiplier of the group, it multiplies the pitch of every member. Negative values are
+    /// clamped to zero.
+    pub fn set_pitch(&mut self, pitch: f64) -> &mut Self {
+        self.pitch = pitch.max(0.0);
+        self
+    }
+
+    /// Returns the pitch multiplier of the group.
+    pub fn pitch(&self) -> f64 {
+        self.pitch
+    }
+
+    /// Sets the maximum amount of members that could play at once, see [`StealPolicy`]. Members that are
+    /// fading out to stop don't count. Default is unlimited.
+    pub fn set_max_instances(&mut self, max_instances: usize) -> &mut Self {
+        self.max_instances = max_instances;
+        self
+    }
+
+    /// Returns the maximum amount of members that could play at once.
+    pub fn max_instances(&self) -> usize {
+        self.max_instances
+    }
+
+    /// Sets the policy that is used when a member starts in a full group.
+    pub fn set_steal_policy(&mut self, steal_policy: StealPolicy) -> &mut Self {
+        self.steal_policy = steal_policy;
+        self
+    }
+
+    /// Returns the policy that is used when a member starts in a full group.
+    pub fn steal_policy(&self) -> StealPolicy {
+        self.steal_policy
+    }
+
+    /// Fades the group out over the given amount of frames and pauses every member. Paused members keep
+    /// their playback position and produce no sound until the group is resumed. The fade lasts at least until
+    /// the end of the next rendered block, so members are never cut off abruptly. Use
+    /// [`crate::dissection::automation::duration_to_frames`] to convert durations.
+    pub fn pause(&mut self, duration: u64) -> &mut Self {
+        self.pending_fade = Some(PendingFade {
+            target: 0.0,
+            duration,
+            action: Some(GroupAction::Pause),
+        });
+        self
+    }
+
+    /// Resumes paused members and fades the group in over the given amount of frames. This also cancels
+    /// a pause or stop that is in progress.
+    pub fn resume(&mut self, duration: u64) -> &mut Self {
+        self.paused = false;
+        self.pending_fade = Some(PendingFade {
+            target: 1.0,
+            duration,
+            action: None,
+        });
+        self
+    }
+
+    /// Fades the group out over the given amount of frames and stops every member, the group is audible
+    /// again after that. Just like [`Self::pause`], the fade lasts at least until the end of the next rendered
+    /// block.
+    pub fn stop(&mut self, duration: u64) -> &mut Self {
+        self.pending_fade = Some(PendingFade {
+            target: 0.0,
+            duration,
+            action: Some(GroupAction::Stop),
+        });
+        self
+    }
+
+    /// Returns `true` if the group is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Returns the gain applied to members in the current block, it includes the fade.
+    pub(crate) fn effective_gain(&self) -> f32 {
+        self.gain * self.fade_gain
+    }
+
+    // Advances fades and pending actions of the group to the block that starts at `time`.
+    pub(crate) fn update(&mut self, time: u64, amount: usize) {
+        if let Some(fade) = self.pending_fade.take() {
+            self.fade.cancel_from(time);
+            self.fade.linear_ramp_to(fade.target, time, fade.duration);
+            self.action = fade.action.map(|action| (action, time + fade.duration));
+        }
+
+        if !self.fade.is_idle(time, amount) {
+            self.fade_curve.clear();
+            self.fade_curve.resize(amount, 0.0);
+            self.fade
+                .render(time, &mut self.fade_gain, &mut self.fade_curve);
+        }
+
+        // The action happens in the block after the end of the fade, so the block with the end of the fade is
+        // still rendered.
+        self.stop_members = false;
+        if let Some((action, _)) = self.action.filter(|(_, at)| *at < time) {
+            self.action = None;
+            match action {
+                GroupAction::Pause => self.paused = true,
+                GroupAction::Stop => {
+                    self.stop_members = true;
+                    self.fade.cancel();
+                    self.fade_gain = 1.0;
+                }
+            }
+        }
+    }
+}
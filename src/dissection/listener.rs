@@ -0,0 +1,82 @@
+//! Listener module.
+//!
+//! # Overview
+//!
+//! Sound context has a single listener - an "ears" of the scene. Every spatial sound source is
+//! rendered relative to the listener: its position defines distance attenuation and its orientation
+//! defines panning. The listener is usually attached to a camera.
+
+use glam::{Mat3, Vec3};
+
+/// See module docs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listener {
+    // Columns are: right (ear) axis, up axis, look axis.
+    basis: Mat3,
+    position: Vec3,
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Self {
+            basis: Mat3::IDENTITY,
+            position: Vec3::ZERO,
+        }
+    }
+}
+
+impl Listener {
+    /// Creates new listener at the origin looking along the +Z axis with +Y as up vector.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets new basis from given vectors in left-handed coordinate system. See
+    /// [`Self::set_basis`] for more info.
+    pub fn set_orientation_lh(&mut self, look: Vec3, up: Vec3) {
+        self.basis = Mat3::from_cols(up.cross(look), up, look);
+    }
+
+    /// Sets new basis from given vectors in right-handed coordinate system. See
+    /// [`Self::set_basis`] for more info.
+    pub fn set_orientation_rh(&mut self, look: Vec3, up: Vec3) {
+        self.basis = Mat3::from_cols(look.cross(up), up, look);
+    }
+
+    /// Sets arbitrary basis. Basis defines orientation of the listener in space. In your basis
+    /// the first column is the right (ear) axis, the second one is up, and the third is look.
+    /// Basis must be orthonormal (consists of orthogonal unit vectors).
+    pub fn set_basis(&mut self, matrix: Mat3) {
+        self.basis = matrix;
+    }
+
+    /// Returns shared reference to the current basis.
+    pub fn basis(&self) -> &Mat3 {
+        &self.basis
+    }
+
+    /// Sets position of the listener in world space.
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+    }
+
+    /// Returns position of listener.
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Returns up axis from the current basis.
+    pub fn up_axis(&self) -> Vec3 {
+        self.basis.y_axis
+    }
+
+    /// Returns look axis from the current basis.
+    pub fn look_axis(&self) -> Vec3 {
+        self.basis.z_axis
+    }
+
+    /// Returns ear axis from the current basis. It points towards the right ear of the listener.
+    pub fn ear_axis(&self) -> Vec3 {
+        self.basis.x_axis
+    }
+}
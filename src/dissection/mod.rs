@@ -1,6 +1,18 @@
+pub mod allocator;
+pub mod ambisonics;
+pub mod automation;
+pub mod backend;
 pub mod buffer;
 pub mod bus;
+pub mod command;
+pub mod container;
 pub mod effects;
 pub mod engine;
+pub mod group;
+pub mod listener;
+pub mod offline;
 pub mod pool;
+pub mod queue;
+pub mod resource;
 pub mod source;
+pub mod stretch;
@@ -0,0 +1,203 @@
+//! Offline (headless) rendering.
+//!
+//! # Overview
+//!
+//! [`OfflineRenderer`] drives [`SoundContext::render`] block by block without any output device, as fast
+//! as possible. It is deterministic: the same context always produces the same samples, which makes it
+//! useful for tests, CI and render farms with no sound card. Rendered samples could be collected into a
+//! [`Buffer`], streamed into a WAV file or passed to an arbitrary closure.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use audio::dissection::engine::SoundContext;
+//! use audio::dissection::offline::OfflineRenderer;
+//!
+//! let mut context = SoundContext::new();
+//! // Add sources, buses, etc.
+//! let mut renderer = OfflineRenderer::new(&mut context);
+//! renderer.render_to_wav("output.wav", 44100).unwrap();
+//! ```
+
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::dissection::buffer::Buffer;
+use crate::dissection::engine::SoundContext;
+use crate::mess::fileio::{make_wav_header, max_wav_frames};
+use crate::{SAMPLES_PER_CHANNEL, SAMPLE_RATE};
+
+/// See module docs.
+pub struct OfflineRenderer<'a> {
+    context: &'a mut SoundContext,
+    block: Vec<(f32, f32)>,
+}
+
+impl<'a> OfflineRenderer<'a> {
+    /// Creates new offline renderer for the given context. The context is rendered in blocks of
+    /// [`SAMPLES_PER_CHANNEL`] frames, the same as an output device would do.
+    pub fn new(context: &'a mut SoundContext) -> Self {
+        Self::with_block_size(context, SAMPLES_PER_CHANNEL)
+    }
+
+    /// Creates new offline renderer with custom block size (in frames). Block size affects only how often
+    /// the context is updated, not the amount of rendered frames.
+    pub fn with_block_size(context: &'a mut SoundContext, block_size: usize) -> Self {
+        Self {
+            context,
+            block: vec![(0.0, 0.0); block_size.max(1)],
+        }
+    }
+
+    /// Returns amount of frames in the given duration, rounded to the nearest frame.
+    pub fn duration_to_frames(duration: Duration) -> usize {
+        (duration.as_secs_f64() * SAMPLE_RATE as f64).round() as usize
+    }
+
+    /// Renders exactly `frames` stereo frames and passes each rendered block to the given closure. The last
+    /// block is shortened if needed, so the total amount of frames is sample-accurate.
+    pub fn render<F>(&mut self, frames: usize, mut sink: F)
+    where
+        F: FnMut(&[(f32, f32)]),
+    {
+        let mut remaining = frames;
+        while remaining > 0 {
+            let count = remaining.min(self.block.len());
+            let block = &mut self.block[..count];
+            self.context.render(block);
+            sink(block);
+            remaining -= count;
+        }
+    }
+
+    /// Same as [`Self::render`], but frames count is defined by the given duration.
+    pub fn render_duration<F>(&mut self, duration: Duration, sink: F)
+    where
+        F: FnMut(&[(f32, f32)]),
+    {
+        self.render(Self::duration_to_frames(duration), sink)
+    }
+
+    /// Renders exactly `frames` stereo frames into a new stereo buffer.
+    pub fn render_to_buffer(&mut self, frames: usize) -> Buffer {
+        let mut samples = Vec::with_capacity(frames * 2);
+        self.render(frames, |block| {
+            for &(left, right) in block {
+                samples.push(left);
+                samples.push(right);
+            }
+        });
+        Buffer::new(samples, false)
+    }
+
+    /// Renders exactly `frames` stereo frames and streams them as 32-bit float WAV data into the given
+    /// writer. Only one block of samples is kept in memory.
+    pub fn render_to_writer<W: Write>(&mut self, writer: W, frames: usize) -> std::io::Result<()> {
+        let frames_u32 = u32::try_from(frames)
+            .ok()
+            .filter(|&frames| frames <= max_wav_frames(2))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Too many frames for a WAV file",
+                )
+            })?;
+
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(&make_wav_header(2, SAMPLE_RATE, frames_u32))?;
+
+        let mut result = Ok(());
+        self.render(frames, |block| {
+            if result.is_ok() {
+                result = block.iter().try_for_each(|(left, right)| {
+                    writer.write_all(&left.to_le_bytes())?;
+                    writer.write_all(&right.to_le_bytes())
+                });
+            }
+        });
+        result?;
+
+        writer.flush()
+    }
+
+    /// Renders exactly `frames` stereo frames into a WAV file at the given path.
+    pub fn render_to_wav<P: AsRef<Path>>(&mut self, path: P, frames: usize) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.render_to_writer(file, frames)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        buffer::Buffer,
+        engine::SoundContext,
+        offline::OfflineRenderer,
+        source::{SoundSource, Status},
+    };
+    use crate::mess::fileio::{make_wav_header, max_wav_frames};
+    use crate::{SAMPLES_PER_CHANNEL, SAMPLE_RATE};
+
+    fn make_context() -> SoundContext {
+        let mut context = SoundContext::new();
+        let mut source = SoundSource::default();
+        source.set_buffer(Some(Buffer::new(vec![0.5; 100], true).into()));
+        source.looping = true;
+        source.status = Status::Playing;
+        context.add_source(source);
+        context
+    }
+
+    #[test]
+    fn test_render_to_buffer_is_sample_accurate() {
+        let mut context = make_context();
+        let frames = SAMPLES_PER_CHANNEL * 2 + 17;
+        let buffer = OfflineRenderer::new(&mut context).render_to_buffer(frames);
+        assert_eq!(buffer.channel_count(), 2);
+        assert_eq!(buffer.channel_duration_in_samples(), frames);
+        assert!(buffer.samples.iter().all(|&s| s == 0.5));
+    }
+
+    #[test]
+    fn test_render_is_deterministic() {
+        let mut first = make_context();
+        let mut second = make_context();
+        let a = OfflineRenderer::with_block_size(&mut first, 64).render_to_buffer(1000);
+        let b = OfflineRenderer::with_block_size(&mut second, 64).render_to_buffer(1000);
+        assert_eq!(a.samples, b.samples);
+    }
+
+    #[test]
+    fn test_render_to_writer() {
+        let mut context = make_context();
+        let mut bytes = Vec::new();
+        OfflineRenderer::new(&mut context)
+            .render_to_writer(&mut bytes, 1234)
+            .unwrap();
+        assert_eq!(bytes.len(), 44 + 1234 * 2 * 4);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[44..48], &0.5f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_render_to_writer_rejects_too_long_files() {
+        let mut context = make_context();
+        let mut bytes = Vec::new();
+        let max = max_wav_frames(2) as usize;
+        let error = OfflineRenderer::new(&mut context)
+            .render_to_writer(&mut bytes, max + 1)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(bytes.is_empty());
+
+        // The longest allowed file still has a valid header.
+        let header = make_wav_header(2, SAMPLE_RATE, max as u32);
+        let data_size = u32::from_le_bytes(header[40..44].try_into().unwrap());
+        assert_eq!(data_size as usize, max * 8);
+        assert_eq!(
+            u32::from_le_bytes(header[4..8].try_into().unwrap()),
+            data_size + 36
+        );
+    }
+}
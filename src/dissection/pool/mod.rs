@@ -222,6 +222,36 @@ where
         }
     }
 
+    /// Reserves space for at least `additional` more objects. The free list is reserved too, so that freeing
+    /// any object of the pool won't allocate memory afterwards.
+    #[inline]
+    pub fn reserve(&mut self, additional: u32) {
+        let additional = usize::try_from(additional).expect("additional overflowed usize");
+        self.records.reserve(additional);
+        self.free_stack
+            .reserve(self.records.capacity() - self.free_stack.len());
+    }
+
+    /// Moves every record of the pool into `storage` (an empty pool made with [`Self::reserve`]) if it has
+    /// more room, so the pool could grow without allocating memory. Handles stay valid. Returns previous (now
+    /// empty) storage of the pool, or `storage` itself if it's too small, so that either of them could be
+    /// freed somewhere else.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `storage` is not empty.
+    pub fn grow_into(&mut self, mut storage: Self) -> Self {
+        assert!(storage.records.is_empty(), "The storage must be empty!");
+        if storage.records.capacity() <= self.records.capacity()
+            || storage.free_stack.capacity() < storage.records.capacity()
+        {
+            return storage;
+        }
+        storage.records.append(&mut self.records);
+        storage.free_stack.append(&mut self.free_stack);
+        std::mem::replace(self, storage)
+    }
+
     fn records_len(&self) -> u32 {
         u32::try_from(self.records.len()).expect("Number of records overflowed u32")
     }
@@ -1700,6 +1730,32 @@ mod test {
         assert_eq!(pool[b], 5);
     }
 
+    #[test]
+    fn test_grow_into() {
+        let mut pool = Pool::<u32>::new();
+        let a = pool.spawn(1);
+        let b = pool.spawn(2);
+        pool.free(a);
+
+        let mut storage = Pool::new();
+        storage.reserve(16);
+        let old = pool.grow_into(storage);
+        assert_eq!(old.get_capacity(), 0);
+        assert_eq!(pool[b], 2);
+        assert!(!pool.is_valid_handle(a));
+        assert!(pool.records.capacity() >= 16);
+
+        // Smaller storage is given back untouched.
+        let mut storage = Pool::new();
+        storage.reserve(1);
+        let returned = pool.grow_into(storage);
+        assert!(returned.records.capacity() < 16);
+        assert!(pool.records.capacity() >= 16);
+
+        // The freed record is reused.
+        assert_eq!(pool.spawn(3).index, a.index);
+    }
+
     #[test]
     fn test_atomic_handle() {
         let handle = AtomicHandle::new(123, 321);
@@ -0,0 +1,183 @@
+//! Wait-free single-producer single-consumer queue.
+//!
+//! # Overview
+//!
+//! The queue is a bounded ring buffer, it is used to pass commands from control threads to the render
+//! thread and to return freed resources back. Both ends are wait-free: [`Producer::push`] and
+//! [`Consumer::pop`] never block and never allocate, so they're safe to use on the render thread.
+
+use std::cell::UnsafeCell;
+use std::fmt::{Debug, Formatter};
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct Shared<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // Index of the next slot to read. Written only by the consumer.
+    head: AtomicUsize,
+    // Index of the next slot to write. Written only by the producer.
+    tail: AtomicUsize,
+}
+
+// The queue moves values between threads, each slot is accessed by one side at a time.
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn next(&self, index: usize) -> usize {
+        (index + 1) % self.slots.len()
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let mut head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        while head != tail {
+            // Safety: slots between head and tail are initialized and no one else has access to them.
+            unsafe { self.slots[head].get_mut().assume_init_drop() };
+            head = self.next(head);
+        }
+    }
+}
+
+/// Writing end of the queue.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Reading end of the queue.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Debug for Producer<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Producer")
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+impl<T> Debug for Consumer<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Consumer")
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+/// Creates new queue that can hold at most `capacity` values and returns its ends.
+pub fn spsc_queue<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    // One slot is always kept empty to distinguish full queue from empty one.
+    let slots = (0..capacity.max(1) + 1)
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        slots,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            shared: shared.clone(),
+        },
+        Consumer { shared },
+    )
+}
+
+impl<T> Producer<T> {
+    /// Pushes a value to the queue. Returns the value back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let shared = &*self.shared;
+        let tail = shared.tail.load(Ordering::Relaxed);
+        let next = shared.next(tail);
+        if next == shared.head.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        // Safety: the slot is not visible to the consumer until tail is advanced.
+        unsafe { (*shared.slots[tail].get()).write(value) };
+        shared.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns maximum amount of values the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len() - 1
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Pops a value from the queue, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let shared = &*self.shared;
+        let head = shared.head.load(Ordering::Relaxed);
+        if head == shared.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // Safety: the slot was initialized by the producer and it won't touch it until head is advanced.
+        let value = unsafe { (*shared.slots[head].get()).assume_init_read() };
+        shared.head.store(shared.next(head), Ordering::Release);
+        Some(value)
+    }
+
+    /// Returns maximum amount of values the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::queue::spsc_queue;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_push_pop() {
+        let (mut producer, mut consumer) = spsc_queue(2);
+        assert_eq!(producer.capacity(), 2);
+        assert_eq!(consumer.pop(), None);
+        assert_eq!(producer.push(1), Ok(()));
+        assert_eq!(producer.push(2), Ok(()));
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(producer.push(3), Ok(()));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_drops_remaining_values() {
+        let value = Arc::new(());
+        let (mut producer, consumer) = spsc_queue(4);
+        producer.push(value.clone()).unwrap();
+        producer.push(value.clone()).unwrap();
+        drop(producer);
+        drop(consumer);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_threads() {
+        let (mut producer, mut consumer) = spsc_queue(16);
+        let thread = std::thread::spawn(move || {
+            for i in 0..10000u32 {
+                let mut value = i;
+                while let Err(v) = producer.push(value) {
+                    value = v;
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < 10000 {
+            if let Some(value) = consumer.pop() {
+                assert_eq!(value, expected);
+                expected += 1;
+            }
+        }
+        thread.join().unwrap();
+    }
+}
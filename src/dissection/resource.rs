@@ -0,0 +1,490 @@
+//! Sound resources.
+//!
+//! # Overview
+//!
+//! [`ResourceManager`] owns every sound file the game uses. It maps file paths to [`SoundResource`]s,
+//! each of them holds a [`SharedBuffer`] once its file is loaded. Files are loaded on a background
+//! thread, so requesting a resource never blocks; requesting the same path again (even while it is still
+//! loading) returns the same resource. Load errors are stored per resource.
+//!
+//! The manager could also watch files on disk (by polling their modification times, see
+//! [`ResourceManager::set_watch_interval`]) and reload them when they change. Reloaded sample data is
+//! swapped into playing sources of every context passed to [`ResourceManager::add_context`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use audio::dissection::engine::SharedSoundContext;
+//! use audio::dissection::resource::{ResourceManager, ResourceState};
+//! use audio::dissection::source::SoundSource;
+//! use std::time::Duration;
+//!
+//! let context = SharedSoundContext::new();
+//! let manager = ResourceManager::new().unwrap();
+//! manager.add_context(context.clone());
+//! manager.set_watch_interval(Some(Duration::from_millis(500)));
+//!
+//! let footstep = manager.request("data/footstep.wav");
+//! match footstep.wait(Duration::from_secs(5)) {
+//!     ResourceState::Ok(buffer) => {
+//!         let mut source = SoundSource::default();
+//!         source.set_buffer(Some(buffer));
+//!         context.add_source(source);
+//!     }
+//!     ResourceState::LoadError(error) => println!("Unable to load footstep: {error}"),
+//!     ResourceState::Pending => println!("Footstep is still loading"),
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::dissection::buffer::{Buffer, SharedBuffer};
+use crate::dissection::engine::SharedSoundContext;
+
+/// State of a sound resource.
+#[derive(Debug, Clone)]
+pub enum ResourceState {
+    /// The file is being loaded.
+    Pending,
+    /// The file is loaded.
+    Ok(SharedBuffer),
+    /// The file could not be loaded.
+    LoadError(Arc<anyhow::Error>),
+}
+
+struct ResourceInner {
+    state: ResourceState,
+    // Error of the last reload. The resource keeps previous data if a reload fails.
+    reload_error: Option<Arc<anyhow::Error>>,
+    // Modification time of the file when it was loaded last time.
+    modified: Option<SystemTime>,
+}
+
+struct ResourceData {
+    path: PathBuf,
+    inner: Mutex<ResourceInner>,
+    loaded: Arc<Condvar>,
+}
+
+/// Shared handle of a sound file managed by [`ResourceManager`]. See module docs.
+#[derive(Clone)]
+pub struct SoundResource(Arc<ResourceData>);
+
+impl Debug for SoundResource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoundResource")
+            .field("path", &self.0.path)
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+impl PartialEq for SoundResource {
+    /// Resources are equal if they're the same resource of the same manager.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl SoundResource {
+    fn new(path: PathBuf) -> Self {
+        Self(Arc::new(ResourceData {
+            path,
+            inner: Mutex::new(ResourceInner {
+                state: ResourceState::Pending,
+                reload_error: None,
+                modified: None,
+            }),
+            loaded: Default::default(),
+        }))
+    }
+
+    /// Returns the path of the file of the resource.
+    pub fn path(&self) -> &Path {
+        &self.0.path
+    }
+
+    /// Returns current state of the resource.
+    pub fn state(&self) -> ResourceState {
+        self.0.inner.lock().unwrap().state.clone()
+    }
+
+    /// Returns sample data of the resource if it is loaded.
+    pub fn data(&self) -> Option<SharedBuffer> {
+        match self.state() {
+            ResourceState::Ok(buffer) => Some(buffer),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the file is still being loaded.
+    pub fn is_loading(&self) -> bool {
+        matches!(self.state(), ResourceState::Pending)
+    }
+
+    /// Returns the error of the last load or reload, if it failed. A resource keeps its previous data if a
+    /// reload fails.
+    pub fn error(&self) -> Option<Arc<anyhow::Error>> {
+        let inner = self.0.inner.lock().unwrap();
+        match &inner.state {
+            ResourceState::LoadError(error) => Some(error.clone()),
+            _ => inner.reload_error.clone(),
+        }
+    }
+
+    /// Waits until the resource is loaded (or failed to load), but no longer than the given timeout. Returns
+    /// `Pending` state on timeout.
+    pub fn wait(&self, timeout: Duration) -> ResourceState {
+        let inner = self.0.inner.lock().unwrap();
+        let (inner, _) = self
+            .0
+            .loaded
+            .wait_timeout_while(inner, timeout, |inner| {
+                matches!(inner.state, ResourceState::Pending)
+            })
+            .unwrap();
+        inner.state.clone()
+    }
+
+    // Loads (or reloads) the file and returns previous and new data if the reload succeeded. The resource is
+    // consumed, so the loader does not hold it when waiters are woken up.
+    fn load(self) -> Option<(SharedBuffer, SharedBuffer)> {
+        let modified = file_modified(&self.0.path);
+        let result = Buffer::load_from_file(&self.0.path);
+
+        let mut inner = self.0.inner.lock().unwrap();
+        inner.modified = modified;
+        let reloaded = match (result, &inner.state) {
+            (Ok(buffer), ResourceState::Ok(previous)) => {
+                let previous = previous.clone();
+                let buffer = SharedBuffer::new(buffer);
+                inner.state = ResourceState::Ok(buffer.clone());
+                inner.reload_error = None;
+                Some((previous, buffer))
+            }
+            (Ok(buffer), _) => {
+                inner.state = ResourceState::Ok(SharedBuffer::new(buffer));
+                None
+            }
+            (Err(error), ResourceState::Ok(_)) => {
+                inner.reload_error = Some(Arc::new(error));
+                None
+            }
+            (Err(error), _) => {
+                inner.state = ResourceState::LoadError(Arc::new(error));
+                None
+            }
+        };
+        drop(inner);
+
+        let loaded = self.0.loaded.clone();
+        drop(self);
+        loaded.notify_all();
+        reloaded
+    }
+
+    // Returns `true` if the file was modified since it was loaded.
+    fn is_modified(&self) -> bool {
+        let inner = self.0.inner.lock().unwrap();
+        if matches!(inner.state, ResourceState::Pending) {
+            return false;
+        }
+        let modified = inner.modified;
+        drop(inner);
+        match file_modified(&self.0.path) {
+            // A missing file isn't a change, the resource keeps its data until the file is back.
+            Some(current) => modified != Some(current),
+            None => false,
+        }
+    }
+
+    // Returns `true` if no one uses the resource except the manager. The caller holds the lock of the manager,
+    // so no one could get a new handle, and the data is checked under the lock of the resource, so no one
+    // could take it in the meantime.
+    fn is_unused(&self) -> bool {
+        let inner = self.0.inner.lock().unwrap();
+        Arc::strong_count(&self.0) == 1
+            && match &inner.state {
+                ResourceState::Ok(buffer) => buffer.use_count() == 1,
+                _ => true,
+            }
+    }
+}
+
+fn file_modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+enum LoaderMessage {
+    Load(SoundResource),
+    // Wakes the loader up to pick new watch interval.
+    Wake,
+}
+
+struct ManagerState {
+    resources: Mutex<HashMap<PathBuf, SoundResource>>,
+    contexts: Mutex<Vec<SharedSoundContext>>,
+    watch_interval: Mutex<Option<Duration>>,
+    loader: Mutex<Sender<LoaderMessage>>,
+}
+
+impl ManagerState {
+    fn send(&self, message: LoaderMessage) {
+        // The loader thread lives as long as the manager, so this can't fail.
+        let _ = self.loader.lock().unwrap().send(message);
+    }
+
+    fn load(&self, resource: SoundResource) {
+        if let Some((previous, buffer)) = resource.load() {
+            for context in self.contexts.lock().unwrap().iter() {
+                context.replace_buffer(previous.clone(), buffer.clone());
+            }
+        }
+    }
+
+    fn reload_modified(&self) {
+        let resources = self
+            .resources
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        for resource in resources {
+            if resource.is_modified() {
+                self.load(resource);
+            }
+        }
+    }
+}
+
+/// Path-keyed cache of sound resources with background loading and hot reload. It could be cloned and
+/// used from any amount of threads. See module docs.
+#[derive(Clone)]
+pub struct ResourceManager {
+    state: Arc<ManagerState>,
+}
+
+impl Debug for ResourceManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceManager")
+            .field("resources", &self.state.resources.lock().unwrap().len())
+            .field("watch_interval", &self.watch_interval())
+            .finish()
+    }
+}
+
+impl ResourceManager {
+    /// Creates new manager and starts its loader thread. The thread stops when the last clone of the manager
+    /// is dropped.
+    pub fn new() -> std::io::Result<Self> {
+        let (sender, receiver) = channel();
+        let state = Arc::new(ManagerState {
+            resources: Default::default(),
+            contexts: Default::default(),
+            watch_interval: Default::default(),
+            loader: Mutex::new(sender),
+        });
+
+        let weak_state: Weak<ManagerState> = Arc::downgrade(&state);
+        std::thread::Builder::new()
+            .name("SoundResourceLoader".to_string())
+            .spawn(move || {
+                let mut next_poll = Instant::now();
+                // Do not keep the manager alive while waiting.
+                while let Some(interval) = weak_state
+                    .upgrade()
+                    .map(|state| *state.watch_interval.lock().unwrap())
+                {
+                    let message = match interval {
+                        Some(interval) => {
+                            next_poll = next_poll.min(Instant::now() + interval);
+                            receiver
+                                .recv_timeout(next_poll.saturating_duration_since(Instant::now()))
+                        }
+                        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    };
+
+                    let Some(state) = weak_state.upgrade() else {
+                        break;
+                    };
+                    match message {
+                        Ok(LoaderMessage::Load(resource)) => state.load(resource),
+                        Ok(LoaderMessage::Wake) => (),
+                        Err(RecvTimeoutError::Timeout) => {
+                            state.reload_modified();
+                            next_poll = Instant::now() + interval.unwrap_or_default();
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            })?;
+
+        Ok(Self { state })
+    }
+
+    // Paths are canonicalized (if possible), so different paths to the same file share a resource.
+    fn key(path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    /// Returns a resource for the given file. The file is loaded on the background thread if it wasn't
+    /// requested before, otherwise existing resource is returned (even if it is still loading).
+    pub fn request<P: AsRef<Path>>(&self, path: P) -> SoundResource {
+        let key = Self::key(path.as_ref());
+        let mut resources = self.state.resources.lock().unwrap();
+        if let Some(resource) = resources.get(&key) {
+            return resource.clone();
+        }
+
+        let resource = SoundResource::new(path.as_ref().to_path_buf());
+        resources.insert(key, resource.clone());
+        drop(resources);
+
+        self.state.send(LoaderMessage::Load(resource.clone()));
+        resource
+    }
+
+    /// Reloads the resource on the background thread, regardless of modification time of its file.
+    pub fn reload(&self, resource: &SoundResource) {
+        self.state.send(LoaderMessage::Load(resource.clone()));
+    }
+
+    /// Returns every resource of the manager.
+    pub fn resources(&self) -> Vec<SoundResource> {
+        self.state
+            .resources
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Forgets resources that no one uses (no handles outside of the manager and no sources playing their
+    /// data), so their memory could be reclaimed. Returns amount of removed resources.
+    pub fn collect_unused(&self) -> usize {
+        let mut resources = self.state.resources.lock().unwrap();
+        let count = resources.len();
+        resources.retain(|_, resource| !resource.is_unused());
+        count - resources.len()
+    }
+
+    /// Adds a context that will receive reloaded data: every source of the context that plays old data of
+    /// a reloaded resource will play the new data instead.
+    pub fn add_context(&self, context: SharedSoundContext) {
+        self.state.contexts.lock().unwrap().push(context);
+    }
+
+    /// Sets how often the manager checks modification times of loaded files, `None` disables hot reload.
+    /// Hot reload is disabled by default.
+    pub fn set_watch_interval(&self, interval: Option<Duration>) {
+        *self.state.watch_interval.lock().unwrap() = interval;
+        self.state.send(LoaderMessage::Wake);
+    }
+
+    pub fn watch_interval(&self) -> Option<Duration> {
+        *self.state.watch_interval.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        buffer::Buffer,
+        engine::SharedSoundContext,
+        resource::{ResourceManager, ResourceState},
+        source::SoundSource,
+    };
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn write_wav(name: &str, value: f32) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        Buffer::new(vec![value; 100], true)
+            .with_sample_rate(22050)
+            .save_to_file(path.to_str().unwrap())
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_request_is_deduplicated() {
+        let path = write_wav("audio_test_resource_dedup.wav", 0.5);
+        let manager = ResourceManager::new().unwrap();
+        let first = manager.request(&path);
+        let second = manager.request(&path);
+        assert_eq!(first, second);
+
+        let ResourceState::Ok(buffer) = first.wait(TIMEOUT) else {
+            panic!("The resource must be loaded!");
+        };
+        assert_eq!(buffer.sample_rate(), 22050);
+        assert_eq!(buffer.samples[0], 0.5);
+        assert_eq!(second.data(), Some(buffer));
+
+        // Data that is still used keeps the resource alive.
+        let data = first.data();
+        drop((first, second));
+        assert_eq!(manager.collect_unused(), 0);
+        drop(data);
+        assert_eq!(manager.collect_unused(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_load_error() {
+        let manager = ResourceManager::new().unwrap();
+        let resource = manager.request("this/file/does/not/exist.wav");
+        assert!(matches!(
+            resource.wait(TIMEOUT),
+            ResourceState::LoadError(_)
+        ));
+        assert!(resource.error().is_some());
+    }
+
+    #[test]
+    fn test_hot_reload() {
+        let path = write_wav("audio_test_resource_reload.wav", 0.25);
+        let context = SharedSoundContext::new();
+        let manager = ResourceManager::new().unwrap();
+        manager.add_context(context.clone());
+
+        let resource = manager.request(&path);
+        resource.wait(TIMEOUT);
+        let mut source = SoundSource::default();
+        source.set_buffer(resource.data());
+        let source = context.add_source(source);
+
+        write_wav("audio_test_resource_reload.wav", 0.75);
+        // Make sure the modification time is different even on file systems with coarse timestamps.
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        manager.set_watch_interval(Some(Duration::from_millis(10)));
+
+        let deadline = std::time::Instant::now() + TIMEOUT;
+        loop {
+            let sample = context
+                .with_detached(move |context| context.source(source).buffer().unwrap().samples[0])
+                .unwrap();
+            if sample == 0.75 {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "No hot reload!");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(resource.data().unwrap().samples[0], 0.75);
+        let _ = std::fs::remove_file(path);
+    }
+}
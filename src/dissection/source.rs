@@ -1,9 +1,27 @@
 use glam::Vec3;
 
-use std::{fmt::Debug, time::Duration};
+use std::{collections::VecDeque, fmt::Debug, time::Duration};
 
-use super::buffer::Buffer;
-use crate::SAMPLE_RATE;
+use super::ambisonics::BFormatSample;
+use super::automation::Automation;
+use super::buffer::{Buffer, CueMarker, LoopRegion, SharedBuffer};
+use super::bus::{mix_sends, AudioBus, AudioBusGraph, AuxSend, SendTap};
+use super::engine::DistanceModel;
+use super::group::SoundGroup;
+use super::listener::Listener;
+use super::pool::Handle;
+use super::stretch::Stretcher;
+use crate::{lerp, SAMPLE_RATE};
+
+// Maximum amount of events a source could report in one block, the rest is dropped.
+const MAX_EVENTS_PER_BLOCK: usize = 32;
+
+// Length of the fade-out of a source stolen by its group, in frames.
+const STEAL_FADE: u64 = 441;
+
+// Cutoff of the air absorption low-pass filter when a source is one unit of distance beyond its
+// radius with unit air absorption factor. The cutoff is inversely proportional to that distance.
+const AIR_ABSORPTION_REFERENCE_CUTOFF: f32 = 20_000.0;
 
 /// Status (state) of sound source.
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
@@ -19,11 +37,47 @@ pub enum Status {
     Paused = 2,
 }
 
+/// Automatable parameter of a sound source, see [`SoundSource::automation_mut`].
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum SourceParameter {
+    /// See [`SoundSource::set_gain`].
+    Gain,
+    /// See [`SoundSource::set_pitch`].
+    Pitch,
+}
+
+/// Kind of a playback event, see [`SoundEvent`].
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum SoundEventKind {
+    /// Playback started from the beginning (or from the end, if played backwards). Resuming a paused source
+    /// is not reported.
+    Started,
+    /// Playback wrapped around the loop region or the whole buffer.
+    Looped,
+    /// Playback passed a cue marker of the buffer with the given identifier, see [`CueMarker`].
+    Marker(u32),
+    /// Playback reached the end of the buffer, or the source was stopped at a scheduled time (see
+    /// [`SoundSource::stop_at`] and [`SoundSource::fade_out_and_stop`]). Stopping a source directly is not
+    /// reported.
+    Finished,
+}
+
+/// An event of a sound source reported by the render thread, see
+/// [`crate::dissection::engine::SharedSoundContext::poll_event`].
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct SoundEvent {
+    pub source: Handle<SoundSource>,
+    pub kind: SoundEventKind,
+    /// Frame of the context clock at which the event happened, see
+    /// [`crate::dissection::engine::SoundContext::current_frame`].
+    pub time: u64,
+}
+
 /// See module info.
 #[derive(Clone)]
 pub struct SoundSource {
     pub name: String,
-    pub buffer: Option<Buffer>,
+    pub buffer: Option<SharedBuffer>,
     // Read position in the buffer in samples. Differs from `playback_pos` if buffer is streaming.
     // In case of streaming buffer its maximum value will be some fixed value which is
     // implementation defined. It can be less than zero, this happens when we are in the process
@@ -34,6 +88,42 @@ pub struct SoundSource {
     pitch: f64,
     pub gain: f32,
     pub looping: bool,
+    // Overrides the loop region of the buffer, see `set_loop_region`.
+    loop_region: Option<LoopRegion>,
+    loop_crossfade: usize,
+    released: bool,
+    // `true` if playback hasn't started since the source was rewound, backward playback of such source
+    // starts from the end of the buffer.
+    rewound: bool,
+    // Present if the source is in the time-stretch mode, see `set_time_stretch`.
+    stretcher: Option<Box<Stretcher>>,
+    tempo: f64,
+    // Events of the current block with their offsets in frames, they're collected by the context.
+    events: Vec<(SoundEventKind, usize)>,
+    priority: u8,
+    // Gain of the voice, zero if the context made the source virtual, see `SoundContext::set_max_voices`.
+    // The gain is interpolated from the value of the previous block, so voices are faded in and out.
+    voice_gain: f32,
+    last_voice_gain: f32,
+    group: Handle<SoundGroup>,
+    // State of the group copied by the context every block, see `set_group_state`.
+    group_gain: f32,
+    last_group_gain: f32,
+    group_pitch: f64,
+    group_paused: bool,
+    // Frame of the context clock at which the current playback started.
+    pub(crate) start_time: u64,
+    // Buffers played after the current one, see `enqueue`.
+    queue: VecDeque<SharedBuffer>,
+    queue_crossfade: usize,
+    // Set when playback reaches the end of the buffer and there's a queued buffer to continue with.
+    buffer_ended: bool,
+    // Buffer that is being faded out after `crossfade_to` or during a crossfade between queued buffers.
+    outgoing: Option<Outgoing>,
+    pending_crossfade: Option<(SharedBuffer, usize)>,
+    // Buffers the source is done with, the context sends them back to a control thread, so they're never
+    // freed on the render thread. The capacity is reserved up front, see `reserve_retired`.
+    retired: Vec<SharedBuffer>,
     pub spatial_blend: f32,
     // Important coefficient for runtime resampling. It is used to modify playback speed
     // of a source in order to match output device sampling rate. PCM data can be stored
@@ -45,7 +135,12 @@ pub struct SoundSource {
     // However such auto-resampling has poor quality, but it is fast.
     resampling_multiplier: f64,
     pub status: Status,
-    pub(crate) bus: String,
+    // Target audio bus. If the source is bound to a bus by name, this is the resolved handle.
+    bus: Handle<AudioBus>,
+    bus_name: Option<String>,
+    // Version of the bus graph the name was resolved with, see `AudioBusGraph::version`.
+    bus_version: Option<u64>,
+    sends: Vec<AuxSend>,
     pub play_once: bool,
     // Here we use Option because when source is just created it has no info about it
     // previous left and right channel gains. We can't set it to 1.0 for example
@@ -55,6 +150,8 @@ pub struct SoundSource {
     // will start interpolation of gain.
     pub(crate) last_left_gain: Option<f32>,
     pub(crate) last_right_gain: Option<f32>,
+    // Same as above, but for B-format encoding gains when rendering to an ambisonic bus.
+    pub(crate) last_ambisonic_gains: Option<BFormatSample>,
     pub(crate) frame_samples: Vec<(f32, f32)>,
     // This sample is used when doing linear interpolation between two blocks of streaming buffer.
     prev_buffer_sample: (f32, f32),
@@ -62,6 +159,68 @@ pub struct SoundSource {
     position: Vec3,
     max_distance: f32,
     rolloff_factor: f32,
+    // Forward direction of the source. Zero vector means that the source is omnidirectional.
+    direction: Vec3,
+    cone_inner_angle: f32,
+    cone_outer_angle: f32,
+    cone_outer_gain: f32,
+    air_absorption: f32,
+    // State of the air absorption low-pass filter (last output sample) and its coefficient
+    // used in the previous block, the latter is used to interpolate coefficient changes.
+    air_filter_state: (f32, f32),
+    last_air_coefficient: Option<f32>,
+    gain_automation: Automation,
+    pitch_automation: Automation,
+    // Per-frame values of automated parameters in the current block. Empty if the parameter does not change
+    // during the block.
+    gain_curve: Vec<f32>,
+    pitch_curve: Vec<f32>,
+    // Gain used in the previous block, it is interpolated when the gain is changed directly.
+    last_gain: Option<f32>,
+    // Frames of the context clock at which playback starts or stops, see `play_at` and `stop_at`.
+    scheduled_start: Option<u64>,
+    scheduled_stop: Option<u64>,
+    // Offsets of the scheduled start and stop within the current block.
+    start_offset: usize,
+    stop_offset: Option<usize>,
+    // Fade envelope, it is separate from the gain so it doesn't interfere with gain automation.
+    fade: Automation,
+    fade_gain: f32,
+    fade_curve: Vec<f32>,
+    // Fade requested by `fade_in` or `fade_out_and_stop`, it is scheduled when the clock time is known.
+    pending_fade: Option<PendingFade>,
+}
+
+// Buffer that is faded out by the equal-power crossfade, it is played forward with constant pitch.
+#[derive(Clone, Debug)]
+struct Outgoing {
+    buffer: SharedBuffer,
+    position: f64,
+    step: f64,
+    length: usize,
+    elapsed: usize,
+    // Frame of the current block the crossfade starts at.
+    first_frame: usize,
+}
+
+impl Outgoing {
+    fn next_frame(&mut self) -> (f32, f32) {
+        let length = self.buffer.channel_duration_in_samples() as f64;
+        let frame = if self.position >= 0.0 && self.position < length {
+            interpolate_frame(&self.buffer, self.position)
+        } else {
+            (0.0, 0.0)
+        };
+        self.position += self.step;
+        frame
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct PendingFade {
+    target: f32,
+    duration: u64,
+    stop: bool,
 }
 
 impl Debug for SoundSource {
@@ -74,19 +233,45 @@ impl Debug for SoundSource {
             .field("pitch", &self.pitch)
             .field("gain", &self.gain)
             .field("looping", &self.looping)
+            .field("loop_region", &self.loop_region)
+            .field("loop_crossfade", &self.loop_crossfade)
+            .field("released", &self.released)
+            .field("time_stretch", &self.stretcher.is_some())
+            .field("tempo", &self.tempo)
+            .field("priority", &self.priority)
+            .field("virtual", &self.is_virtual())
+            .field("group", &self.group)
+            .field("queue", &self.queue)
+            .field("queue_crossfade", &self.queue_crossfade)
             .field("spatial_blend", &self.spatial_blend)
             .field("resampling_multiplier", &self.resampling_multiplier)
             .field("status", &self.status)
             .field("bus", &self.bus)
+            .field("bus_name", &self.bus_name)
+            .field("sends", &self.sends)
             .field("play_once", &self.play_once)
             .field("last_left_gain", &self.last_left_gain)
             .field("last_right_gain", &self.last_right_gain)
-            .field("frame_samples", &format!("[..{} frame_samples]", &self.frame_samples.len()))
+            .field(
+                "frame_samples",
+                &format!("[..{} frame_samples]", &self.frame_samples.len()),
+            )
             .field("prev_buffer_sample", &self.prev_buffer_sample)
             .field("radius", &self.radius)
             .field("position", &self.position)
             .field("max_distance", &self.max_distance)
             .field("rolloff_factor", &self.rolloff_factor)
+            .field("direction", &self.direction)
+            .field("cone_inner_angle", &self.cone_inner_angle)
+            .field("cone_outer_angle", &self.cone_outer_angle)
+            .field("cone_outer_gain", &self.cone_outer_gain)
+            .field("air_absorption", &self.air_absorption)
+            .field("gain_automation", &self.gain_automation)
+            .field("pitch_automation", &self.pitch_automation)
+            .field("scheduled_start", &self.scheduled_start)
+            .field("scheduled_stop", &self.scheduled_stop)
+            .field("fade", &self.fade)
+            .field("fade_gain", &self.fade_gain)
             .finish()
     }
 }
@@ -104,18 +289,546 @@ impl SoundSource {
         self
     }
 
-    /// Sets sound pitch. Defines "tone" of sounds. Default value is 1.0
+    /// Sets new buffer to play and rewinds the source. The buffer could be shared with any amount of other
+    /// sources, see [`SharedBuffer`] docs.
+    pub fn set_buffer(&mut self, buffer: Option<SharedBuffer>) -> &mut Self {
+        self.buffer = buffer;
+        self.buf_read_pos = 0.0;
+        self.playback_pos = 0.0;
+        self.rewound = true;
+        self.reserve_retired();
+        self
+    }
+
+    /// Adds a buffer to the playback queue of the source. Queued buffers are played one after another right
+    /// after the current buffer ends, with no gap between them, see also [`Self::set_queue_crossfade`]. If the
+    /// source has no buffer, the queued buffer becomes the current one. A looping source repeats its current
+    /// buffer, so the queue advances only when it is released or stops looping.
+    ///
+    /// [`SoundEventKind::Started`] is reported for every queued buffer, while [`SoundEventKind::Finished`] is
+    /// reported only once the last buffer ends. Finished buffers of a source in a [`SharedSoundContext`] are sent
+    /// back and dropped on a control thread (see [`SharedSoundContext::collect_garbage`]).
+    ///
+    /// [`SharedSoundContext`]: crate::dissection::engine::SharedSoundContext
+    /// [`SharedSoundContext::collect_garbage`]: crate::dissection::engine::SharedSoundContext::collect_garbage
+    pub fn enqueue(&mut self, buffer: SharedBuffer) -> &mut Self {
+        if self.buffer.is_none() {
+            self.set_buffer(Some(buffer));
+        } else {
+            self.queue.push_back(buffer);
+            self.reserve_retired();
+        }
+        self
+    }
+
+    /// Returns buffers that are played after the current one.
+    pub fn queue(&self) -> &VecDeque<SharedBuffer> {
+        &self.queue
+    }
+
+    /// Removes every queued buffer, the current buffer keeps playing.
+    pub fn clear_queue(&mut self) -> &mut Self {
+        self.queue.clear();
+        self
+    }
+
+    /// Sets the length of the equal-power crossfade between queued buffers, in frames. The next buffer starts
+    /// this amount of frames before the end of the current one. Zero (default) plays buffers back-to-back.
+    /// Crossfades don't happen during backward playback.
+    pub fn set_queue_crossfade(&mut self, frames: usize) -> &mut Self {
+        self.queue_crossfade = frames;
+        self
+    }
+
+    /// Returns the length of the crossfade between queued buffers, in frames.
+    pub fn queue_crossfade(&self) -> usize {
+        self.queue_crossfade
+    }
+
+    /// Switches to the given buffer with an equal-power crossfade of the given length in frames, starting from
+    /// the next rendered block. The new buffer plays from its beginning, while the current one is faded out
+    /// from where it is. If the source isn't playing, this is the same as [`Self::set_buffer`].
+    pub fn crossfade_to(&mut self, buffer: SharedBuffer, frames: usize) -> &mut Self {
+        if self.status == Status::Playing && self.buffer.is_some() {
+            if let Some((replaced, _)) = self.pending_crossfade.replace((buffer, frames)) {
+                self.retire(replaced);
+            }
+            self.reserve_retired();
+        } else {
+            self.set_buffer(Some(buffer));
+        }
+        self
+    }
+
+    // Swaps the buffer keeping playback position where possible, used to hot reload sample data.
+    pub(crate) fn replace_buffer(&mut self, buffer: SharedBuffer) {
+        let last_sample = buffer.channel_duration_in_samples().saturating_sub(1) as f64;
+        self.buf_read_pos = self.buf_read_pos.min(last_sample);
+        self.playback_pos = self.playback_pos.min(last_sample);
+        self.buffer = Some(buffer);
+    }
+
+    /// Returns a reference to the buffer of the source.
+    pub fn buffer(&self) -> Option<&SharedBuffer> {
+        self.buffer.as_ref()
+    }
+
+    /// Sets the region of the buffer that is repeated while the source is looping, overriding the loop region
+    /// of the buffer (see [`Buffer::loop_region`]). Playback starts from the beginning of the buffer (intro),
+    /// then the region is repeated until [`Self::release`] is called, then the rest of the buffer (tail) is
+    /// played. A region that covers the whole buffer disables the loop region of the buffer. Without any
+    /// region, looping sources repeat the whole buffer.
+    pub fn set_loop_region(&mut self, loop_region: Option<LoopRegion>) -> &mut Self {
+        self.loop_region = loop_region;
+        self
+    }
+
+    /// Returns the loop region used by the source: either the one set by [`Self::set_loop_region`] or the one
+    /// of the buffer.
+    pub fn loop_region(&self) -> Option<LoopRegion> {
+        self.loop_region
+            .or_else(|| self.buffer.as_ref().and_then(|buffer| buffer.loop_region()))
+    }
+
+    /// Sets the length of the crossfade between the end of the loop region and the frames preceding its
+    /// start, in frames. Crossfading hides clicks at the loop point of material that doesn't loop perfectly.
+    /// The length is limited by the length of the region and by the amount of frames before it.
+    pub fn set_loop_crossfade(&mut self, frames: usize) -> &mut Self {
+        self.loop_crossfade = frames;
+        self
+    }
+
+    pub fn loop_crossfade(&self) -> usize {
+        self.loop_crossfade
+    }
+
+    /// Leaves the loop: the current pass of the loop region is finished, then the rest of the buffer is played
+    /// and the source stops. Stopping the source resets the release.
+    pub fn release(&mut self) -> &mut Self {
+        self.released = true;
+        self
+    }
+
+    /// Returns `true` if [`Self::release`] was called since the source was stopped last time.
+    pub fn is_released(&self) -> bool {
+        self.released
+    }
+
+    /// Sets sound pitch. Defines "tone" of sounds. Default value is 1.0. Negative pitch plays the buffer
+    /// backwards, loop regions are repeated in both directions. A rewound source (see [`Self::stop`]) played
+    /// backwards starts from the end of the buffer. Zero pitch holds the current frame, so pitch ramps
+    /// through zero (see [`Self::automation_mut`]) could be used for tape-stop and scratch effects.
     pub fn set_pitch(&mut self, pitch: f64) -> &mut Self {
-        self.pitch = pitch.abs();
+        self.pitch = pitch;
         self
     }
 
-    /// Stops sound source. Automatically rewinds streaming buffers.
-    pub fn stop(&mut self) -> anyhow::Result<()> {
-        self.status = Status::Stopped;
+    pub fn pitch(&self) -> f64 {
+        self.pitch
+    }
+
+    /// Enables or disables the time-stretch mode. In this mode pitch (see [`Self::set_pitch`]) doesn't change
+    /// playback speed and tempo (see [`Self::set_tempo`]) changes playback speed without changing pitch. See
+    /// [`crate::dissection::stretch`] docs. The mode costs more CPU time than regular playback. Time-stretched
+    /// sources always play forward and repeat the whole buffer when looping, loop regions are ignored.
+    ///
+    /// Enabling the mode allocates memory, so it is better done before the source is added to a context that
+    /// is attached to an engine.
+    pub fn set_time_stretch(&mut self, enabled: bool) -> &mut Self {
+        if enabled != self.stretcher.is_some() {
+            self.stretcher = enabled.then(|| Box::new(Stretcher::new()));
+        }
+        self
+    }
+
+    pub fn is_time_stretch(&self) -> bool {
+        self.stretcher.is_some()
+    }
+
+    /// Sets playback speed of the source in the time-stretch mode (see [`Self::set_time_stretch`]), 1.0 is
+    /// the original speed. Zero tempo freezes the sound at the current position. Default value is 1.0.
+    pub fn set_tempo(&mut self, tempo: f64) -> &mut Self {
+        self.tempo = tempo.max(0.0);
+        self
+    }
+
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    /// Sets the priority of the source, which is used when there are more playing sources than the context
+    /// could render, see [`crate::dissection::engine::SoundContext::set_max_voices`]. Sources with higher
+    /// priority are rendered first, sources with the same priority are ranked by loudness. Default is 128.
+    pub fn set_priority(&mut self, priority: u8) -> &mut Self {
+        self.priority = priority;
+        self
+    }
 
+    /// Returns the priority of the source.
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// Returns `true` if the source is playing, but isn't rendered because of the voice limit, see
+    /// [`crate::dissection::engine::SoundContext::set_max_voices`]. Playback of a virtual source still
+    /// advances and reports events.
+    pub fn is_virtual(&self) -> bool {
+        self.voice_gain == 0.0 && self.last_voice_gain == 0.0
+    }
+
+    // Makes the source a real or a virtual voice, the change is faded over the next block.
+    pub(crate) fn set_voice_active(&mut self, active: bool) {
+        self.voice_gain = if active { 1.0 } else { 0.0 };
+        // Playback hasn't started yet, so there's nothing to fade.
+        if self.rewound {
+            self.last_voice_gain = self.voice_gain;
+        }
+    }
+
+    // Returns `true` if the source is a real voice or is going to become one.
+    pub(crate) fn is_voice_active(&self) -> bool {
+        self.voice_gain > 0.0
+    }
+
+    // Estimates how loud the source is for the listener, it is used to pick sources worth rendering.
+    pub(crate) fn audibility(&self, listener: &Listener, distance_model: DistanceModel) -> f32 {
+        let distance_gain = lerp(
+            1.0,
+            self.calculate_distance_gain(listener, distance_model),
+            self.spatial_blend,
+        );
+        let cone_gain = lerp(1.0, self.calculate_cone_gain(listener), self.spatial_blend);
+        (self.gain * self.fade_gain * self.group_gain * distance_gain * cone_gain).abs()
+    }
+
+    /// Adds the source to a sound group, see [`crate::dissection::group`]. [`Handle::NONE`] (default) removes
+    /// the source from its group. A source is in one group at most.
+    pub fn set_group(&mut self, group: Handle<SoundGroup>) -> &mut Self {
+        self.group = group;
+        self
+    }
+
+    /// Returns the handle of the group of the source.
+    pub fn group(&self) -> Handle<SoundGroup> {
+        self.group
+    }
+
+    // Copies the state of the group of the source, the gain change is faded over the next block.
+    pub(crate) fn set_group_state(&mut self, gain: f32, pitch: f64, paused: bool) {
+        self.group_gain = gain;
+        self.group_pitch = pitch;
+        self.group_paused = paused;
+        // Playback hasn't started yet, so there's nothing to fade.
+        if self.rewound {
+            self.last_group_gain = gain;
+        }
+    }
+
+    // Returns `true` if the source is playing and its group isn't paused.
+    pub(crate) fn is_running(&self) -> bool {
+        self.status == Status::Playing && !self.group_paused
+    }
+
+    // Returns `true` if the source is fading out and will stop when the fade is over.
+    pub(crate) fn is_fading_out_to_stop(&self) -> bool {
+        self.pending_fade.is_some_and(|fade| fade.stop)
+            || (self.scheduled_stop.is_some() && self.fade.is_active())
+    }
+
+    // Stops the source because its group is full. A source that hasn't made any sound yet is stopped right
+    // away, otherwise it is quickly faded out.
+    pub(crate) fn steal(&mut self) {
+        if self.rewound {
+            self.scheduled_start = None;
+            self.finish();
+        } else {
+            self.fade_out_and_stop(STEAL_FADE);
+        }
+    }
+
+    /// Returns automation of the given parameter. See [`crate::dissection::automation`] docs.
+    pub fn automation(&self, parameter: SourceParameter) -> &Automation {
+        match parameter {
+            SourceParameter::Gain => &self.gain_automation,
+            SourceParameter::Pitch => &self.pitch_automation,
+        }
+    }
+
+    /// Returns automation of the given parameter, that could be used to schedule changes of the parameter.
+    /// Automated parameters are changed by the engine, so there's no need to set them directly. See
+    /// [`crate::dissection::automation`] docs.
+    pub fn automation_mut(&mut self, parameter: SourceParameter) -> &mut Automation {
+        match parameter {
+            SourceParameter::Gain => &mut self.gain_automation,
+            SourceParameter::Pitch => &mut self.pitch_automation,
+        }
+    }
+
+    // Evaluates automation of the parameters and scheduled start and stop for the block of `amount` frames
+    // starting from `time`.
+    pub(crate) fn update_automation(&mut self, time: u64, amount: usize) {
+        self.update_schedule(time, amount);
+        if self.rewound {
+            self.start_time = time + self.start_offset as u64;
+        }
+
+        self.gain_curve.clear();
+        if !self.gain_automation.is_idle(time, amount) {
+            self.gain_curve.resize(amount, 0.0);
+            self.gain_automation
+                .render(time, &mut self.gain, &mut self.gain_curve);
+        }
+
+        self.pitch_curve.clear();
+        if !self.pitch_automation.is_idle(time, amount) {
+            self.pitch_curve.resize(amount, 0.0);
+            let mut pitch = self.pitch as f32;
+            self.pitch_automation
+                .render(time, &mut pitch, &mut self.pitch_curve);
+            // The pitch itself is changed while rendering.
+        }
+    }
+
+    fn update_schedule(&mut self, time: u64, amount: usize) {
+        let end = time + amount as u64;
+
+        if let Some(fade) = self.pending_fade.take() {
+            // A fade-in of a source that is about to start begins together with playback.
+            let start = match self.scheduled_start {
+                Some(start) if self.status != Status::Playing => start.max(time),
+                _ => time,
+            };
+            self.fade.cancel_from(start);
+            self.fade.linear_ramp_to(fade.target, start, fade.duration);
+            if fade.stop {
+                self.scheduled_stop = Some(start + fade.duration);
+            }
+        }
+
+        self.start_offset = 0;
+        if let Some(start) = self.scheduled_start.filter(|start| *start < end) {
+            self.scheduled_start = None;
+            if self.status != Status::Playing {
+                self.status = Status::Playing;
+                self.start_offset = start.saturating_sub(time) as usize;
+            }
+        }
+
+        self.stop_offset = None;
+        if let Some(stop) = self.scheduled_stop.filter(|stop| *stop < end) {
+            self.scheduled_stop = None;
+            if self.status == Status::Playing {
+                self.stop_offset =
+                    Some((stop.saturating_sub(time) as usize).max(self.start_offset));
+            } else {
+                self.finish();
+            }
+        }
+
+        self.fade_curve.clear();
+        if !self.fade.is_idle(time, amount) {
+            self.fade_curve.resize(amount, 0.0);
+            self.fade
+                .render(time, &mut self.fade_gain, &mut self.fade_curve);
+        }
+    }
+
+    // Applies gain of the source to rendered samples. Automated gain is applied per frame, direct changes of
+    // the gain are interpolated over the block.
+    pub(crate) fn apply_gain(&mut self) {
+        let gain = self.gain;
+        let last_gain = self.last_gain.replace(gain).unwrap_or(gain);
+        if !self.gain_curve.is_empty() {
+            for ((left, right), gain) in self.frame_samples.iter_mut().zip(self.gain_curve.iter()) {
+                *left *= *gain;
+                *right *= *gain;
+            }
+        } else if last_gain != gain {
+            let step = (gain - last_gain) / self.frame_samples.len().max(1) as f32;
+            let mut current = last_gain;
+            for (left, right) in self.frame_samples.iter_mut() {
+                *left *= current;
+                *right *= current;
+                current += step;
+            }
+        } else if gain != 1.0 {
+            for (left, right) in self.frame_samples.iter_mut() {
+                *left *= gain;
+                *right *= gain;
+            }
+        }
+    }
+
+    /// Starts (or resumes) playback exactly at the given frame of the context clock, see
+    /// [`crate::dissection::engine::SoundContext::current_frame`]. Sources scheduled to the same frame start
+    /// in perfect sync. If the frame has already passed, playback starts at the next rendered block. Replaces
+    /// previously scheduled start.
+    pub fn play_at(&mut self, time: u64) -> &mut Self {
+        self.scheduled_start = Some(time);
+        self
+    }
+
+    /// Stops playback exactly at the given frame of the context clock and rewinds the source, just like
+    /// [`Self::stop`]. Replaces previously scheduled stop.
+    pub fn stop_at(&mut self, time: u64) -> &mut Self {
+        self.scheduled_stop = Some(time);
+        self
+    }
+
+    /// Returns the frame of the context clock at which playback is scheduled to start, if any.
+    pub fn scheduled_start(&self) -> Option<u64> {
+        self.scheduled_start
+    }
+
+    /// Returns the frame of the context clock at which playback is scheduled to stop, if any.
+    pub fn scheduled_stop(&self) -> Option<u64> {
+        self.scheduled_stop
+    }
+
+    /// Fades the source in over the given amount of frames. A source that isn't playing is started from
+    /// silence, either at the next rendered block or at the frame set by [`Self::play_at`], in the latter case
+    /// the fade begins together with playback. A playing source is faded from its current fade level, and a
+    /// scheduled stop is cancelled, so this reverts [`Self::fade_out_and_stop`]. Use
+    /// [`crate::dissection::automation::duration_to_frames`] to convert durations.
+    pub fn fade_in(&mut self, duration: u64) -> &mut Self {
+        self.scheduled_stop = None;
+        if self.status != Status::Playing {
+            self.fade.cancel();
+            self.fade_gain = 0.0;
+            if self.scheduled_start.is_none() {
+                self.status = Status::Playing;
+            }
+        }
+        self.pending_fade = Some(PendingFade {
+            target: 1.0,
+            duration,
+            stop: false,
+        });
+        self
+    }
+
+    /// Fades the source out over the given amount of frames starting from the next rendered block and stops
+    /// it when the fade is over. A source that isn't playing is stopped immediately.
+    pub fn fade_out_and_stop(&mut self, duration: u64) -> &mut Self {
+        if self.status == Status::Playing {
+            self.pending_fade = Some(PendingFade {
+                target: 0.0,
+                duration,
+                stop: true,
+            });
+        } else {
+            self.scheduled_start = None;
+            self.finish();
+        }
+        self
+    }
+
+    // Records an event at the current frame of the block.
+    fn push_event(&mut self, kind: SoundEventKind) {
+        let offset = self.frame_samples.len();
+        self.push_event_at(kind, offset);
+    }
+
+    fn push_event_at(&mut self, kind: SoundEventKind, offset: usize) {
+        // The capacity is reserved before rendering, so this never allocates.
+        if self.events.len() < self.events.capacity() {
+            self.events.push((kind, offset));
+        }
+    }
+
+    // Records markers of the buffer passed by playback since the position `from`, that was read at the frame
+    // `first_frame` of the block. `step` is the distance between consecutive frames.
+    fn push_markers(&mut self, buffer: &Buffer, from: f64, first_frame: usize, step: f64) {
+        let to = self.buf_read_pos;
+        let step = step.abs().max(f64::EPSILON);
+        let push = |source: &mut Self, marker: &CueMarker| {
+            let position = marker.position as f64;
+            let offset = first_frame + ((position - from).abs() / step).ceil() as usize;
+            source.push_event_at(SoundEventKind::Marker(marker.id), offset);
+        };
+        if to > from {
+            for marker in buffer.markers() {
+                if (from..to).contains(&(marker.position as f64)) {
+                    push(self, marker);
+                }
+            }
+        } else {
+            for marker in buffer.markers().iter().rev() {
+                let position = marker.position as f64;
+                if position <= from && position > to {
+                    push(self, marker);
+                }
+            }
+        }
+    }
+
+    // Returns events of the last rendered block with their offsets in frames.
+    pub(crate) fn events(&self) -> &[(SoundEventKind, usize)] {
+        &self.events
+    }
+
+    // Called when playback reaches the end of the buffer, playback continues with the next queued buffer if
+    // there's one.
+    fn end_buffer(&mut self) {
+        if self.queue.is_empty() {
+            self.end_playback();
+        } else {
+            self.buffer_ended = true;
+        }
+    }
+
+    // Stops the source at the end of playback and reports it.
+    fn end_playback(&mut self) {
+        self.push_event(SoundEventKind::Finished);
+        self.finish();
+    }
+
+    // Stops the source, rewinds it and resets the fade.
+    fn finish(&mut self) {
+        self.status = Status::Stopped;
         self.buf_read_pos = 0.0;
         self.playback_pos = 0.0;
+        self.scheduled_stop = None;
+        self.pending_fade = None;
+        self.fade.cancel();
+        self.fade_gain = 1.0;
+        self.released = false;
+        self.rewound = true;
+        if let Some(outgoing) = self.outgoing.take() {
+            self.retire(outgoing.buffer);
+        }
+        if let Some((buffer, _)) = self.pending_crossfade.take() {
+            self.retire(buffer);
+        }
+    }
+
+    // Keeps a buffer the source is done with until the context takes it. It never allocates, see
+    // `reserve_retired`.
+    fn retire(&mut self, buffer: SharedBuffer) {
+        self.retired.push(buffer);
+    }
+
+    // Reserves room to retire every buffer the source holds. The render thread only moves buffers the source
+    // already holds into `retired` and the context only takes them out, so `retired` never grows there, even
+    // if there's no room to send buffers back for many blocks.
+    pub(crate) fn reserve_retired(&mut self) {
+        let held = self.queue.len()
+            + usize::from(self.buffer.is_some())
+            + usize::from(self.outgoing.is_some())
+            + usize::from(self.pending_crossfade.is_some());
+        self.retired.reserve(held);
+    }
+
+    // Returns buffers the source is done with, see `retire`.
+    pub(crate) fn retired_buffers_mut(&mut self) -> &mut Vec<SharedBuffer> {
+        &mut self.retired
+    }
+
+    /// Stops sound source. Automatically rewinds streaming buffers. Scheduled start, stop and fades are
+    /// cancelled.
+    pub fn stop(&mut self) -> anyhow::Result<()> {
+        self.scheduled_start = None;
+        self.finish();
 
         Ok(())
     }
@@ -148,16 +861,175 @@ impl SoundSource {
         self
     }
 
-    /// Sets new name of the target audio bus. The name must be valid, otherwise the sound won't play!
-    /// Default is [`AudioBusGraph::PRIMARY_BUS`].
+    /// Returns position of source in world space.
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Sets forward direction of the source in world space. Direction is used together with sound
+    /// cone to make the source radiate mostly forward (speakers, characters, alarms, etc.). Zero
+    /// vector (default) makes the source omnidirectional. The vector does not need to be normalized.
+    pub fn set_direction(&mut self, direction: Vec3) -> &mut Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Returns forward direction of the source.
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    /// Sets full angle (in radians) of the inner cone around source direction. A listener inside the
+    /// inner cone hears the source with no cone attenuation. Default is `TAU` (no cone at all).
+    pub fn set_cone_inner_angle(&mut self, angle: f32) -> &mut Self {
+        self.cone_inner_angle = angle.clamp(0.0, std::f32::consts::TAU);
+        self
+    }
+
+    /// Returns full angle of the inner cone in radians.
+    pub fn cone_inner_angle(&self) -> f32 {
+        self.cone_inner_angle
+    }
+
+    /// Sets full angle (in radians) of the outer cone around source direction. A listener outside the
+    /// outer cone hears the source with [`Self::cone_outer_gain`], gain is linearly interpolated in
+    /// between the inner and the outer cones. Default is `TAU`.
+    pub fn set_cone_outer_angle(&mut self, angle: f32) -> &mut Self {
+        self.cone_outer_angle = angle.clamp(0.0, std::f32::consts::TAU);
+        self
+    }
+
+    /// Returns full angle of the outer cone in radians.
+    pub fn cone_outer_angle(&self) -> f32 {
+        self.cone_outer_angle
+    }
+
+    /// Sets gain that is applied to the source when a listener is outside of the outer cone.
+    pub fn set_cone_outer_gain(&mut self, gain: f32) -> &mut Self {
+        self.cone_outer_gain = gain.max(0.0);
+        self
+    }
+
+    /// Returns gain that is applied outside of the outer cone.
+    pub fn cone_outer_gain(&self) -> f32 {
+        self.cone_outer_gain
+    }
+
+    /// Sets air absorption factor. Air absorption muffles distant sounds by a low-pass filter which
+    /// cutoff frequency halves each time the distance beyond the source radius doubles. Larger values
+    /// make the cutoff drop faster, zero (default) disables air absorption.
+    pub fn set_air_absorption(&mut self, air_absorption: f32) -> &mut Self {
+        self.air_absorption = air_absorption.max(0.0);
+        self
+    }
+
+    /// Returns air absorption factor.
+    pub fn air_absorption(&self) -> f32 {
+        self.air_absorption
+    }
+
+    /// Binds the source to an audio bus by its name. The name is resolved into a handle at the next rendered
+    /// block and then only when the bus graph changes. The name must be valid, otherwise the sound won't play
+    /// (see [`crate::dissection::engine::SoundContext::misrouted_sources`]). Default is
+    /// [`AudioBusGraph::PRIMARY_BUS`].
     pub fn set_bus<S: AsRef<str>>(&mut self, bus: S) {
-        bus.as_ref().clone_into(&mut self.bus);
+        self.bus_name = Some(bus.as_ref().to_owned());
+        self.bus = Handle::NONE;
+        self.bus_version = None;
+    }
+
+    /// Binds the source to an audio bus by its handle. The handle must be valid, otherwise the sound won't
+    /// play.
+    pub fn set_bus_handle(&mut self, bus: Handle<AudioBus>) {
+        self.bus_name = None;
+        self.bus = bus;
+        self.bus_version = None;
+    }
+
+    /// Returns the name of the target audio bus, if the source is bound to a bus by name.
+    pub fn bus_name(&self) -> Option<&str> {
+        self.bus_name.as_deref()
+    }
+
+    /// Returns the handle of the target audio bus. If the source is bound to a bus by name, the handle is
+    /// resolved while rendering, so it could be [`Handle::NONE`] before the first rendered block.
+    pub fn bus_handle(&self) -> Handle<AudioBus> {
+        self.bus
+    }
+
+    // Returns the handle of the target bus, resolving its name only if the graph was changed since the last
+    // resolution.
+    pub(crate) fn resolve_bus(&mut self, graph: &AudioBusGraph) -> Handle<AudioBus> {
+        if let Some(name) = self.bus_name.as_ref() {
+            if self.bus_version != Some(graph.version()) {
+                self.bus = graph.find_bus_by_name(name);
+                self.bus_version = Some(graph.version());
+            }
+        }
+        self.bus
+    }
+
+    /// Adds an aux send to the source and returns its index. Sends to invalid buses are ignored. See
+    /// [`AuxSend`] docs for more info.
+    pub fn add_send(&mut self, send: AuxSend) -> usize {
+        self.sends.push(send);
+        self.sends.len() - 1
+    }
+
+    /// Removes an aux send at the given index.
+    pub fn remove_send(&mut self, index: usize) -> Option<AuxSend> {
+        (index < self.sends.len()).then(|| self.sends.remove(index))
+    }
+
+    /// Returns aux sends of the source.
+    pub fn sends(&self) -> &[AuxSend] {
+        &self.sends
+    }
+
+    /// Returns mutable reference to an aux send at the given index.
+    pub fn send_mut(&mut self, index: usize) -> Option<&mut AuxSend> {
+        self.sends.get_mut(index)
+    }
+
+    // Mixes rendered samples of the source before gain and spatialization into pre-fader sends.
+    pub(crate) fn mix_pre_fader_sends(&mut self, graph: &mut AudioBusGraph) {
+        mix_sends(
+            &mut self.sends,
+            SendTap::PreFader,
+            &self.frame_samples,
+            &[],
+            graph,
+        );
+    }
+
+    // Mixes the final signal of the source (the one that goes to its bus) into post-fader sends.
+    pub(crate) fn mix_post_fader_sends(
+        &mut self,
+        graph: &mut AudioBusGraph,
+        input: &[(f32, f32)],
+        ambisonic_input: &[BFormatSample],
+    ) {
+        mix_sends(
+            &mut self.sends,
+            SendTap::PostFader,
+            input,
+            ambisonic_input,
+            graph,
+        );
+    }
+
+    // Same as `resolve_bus`, but never caches anything.
+    pub(crate) fn target_bus(&self, graph: &AudioBusGraph) -> Handle<AudioBus> {
+        match self.bus_name.as_ref() {
+            Some(name) if self.bus_version != Some(graph.version()) => graph.find_bus_by_name(name),
+            _ => self.bus,
+        }
     }
 
     /// Returns playback duration.
     pub fn playback_time(&self) -> Duration {
-        if let Some(_buffer) = self.buffer.as_ref() {
-            return Duration::from_secs_f64(self.playback_pos / (SAMPLE_RATE as f64));
+        if let Some(buffer) = self.buffer.as_ref() {
+            return Duration::from_secs_f64(self.playback_pos / (buffer.sample_rate() as f64));
         }
 
         Duration::from_secs(0)
@@ -167,10 +1039,12 @@ impl SoundSource {
     pub fn set_playback_time(&mut self, time: Duration) {
         if let Some(buffer) = self.buffer.as_ref() {
             // Set absolute position first.
-            self.playback_pos = (time.as_secs_f64() * SAMPLE_RATE as f64)
-                .clamp(0.0, buffer.duration().as_secs_f64());
+            let last_sample = buffer.channel_duration_in_samples().saturating_sub(1);
+            self.playback_pos =
+                (time.as_secs_f64() * buffer.sample_rate() as f64).clamp(0.0, last_sample as f64);
             // Then adjust buffer read position.
             self.buf_read_pos = self.playback_pos;
+            self.rewound = false;
             assert!(
                 self.buf_read_pos * (buffer.channel_count() as f64) < buffer.samples.len() as f64
             );
@@ -178,41 +1052,487 @@ impl SoundSource {
     }
 
     pub(crate) fn render(&mut self, amount: usize) {
-        if self.frame_samples.capacity() < amount {
-            self.frame_samples = Vec::with_capacity(amount);
-        }
-
         self.frame_samples.clear();
+        self.frame_samples.reserve(amount);
+        self.events.clear();
+        self.events.reserve(MAX_EVENTS_PER_BLOCK);
+
+        // Scheduled start and stop could happen in the middle of the block.
+        let start = std::mem::take(&mut self.start_offset).min(amount);
+        let stop = self.stop_offset.take();
+        let end = stop.unwrap_or(amount).min(amount);
+        self.frame_samples.resize(start, (0.0, 0.0));
 
-        if let Some(mut buffer) = self.buffer.clone() {
-            if self.status == Status::Playing && !buffer.samples.is_empty() {
-                self.render_playing(&mut buffer, amount);
+        // Move the buffer out for a while, so the source could be borrowed mutably while reading it.
+        if let Some(mut buffer) = self.buffer.take() {
+            self.update_resampling_multiplier(&buffer);
+            if self.status == Status::Playing && !buffer.samples.is_empty() && start < end {
+                if let Some((next, frames)) = self.pending_crossfade.take() {
+                    self.begin_crossfade(&mut buffer, next, frames);
+                }
+                if self.rewound {
+                    self.push_event(SoundEventKind::Started);
+                }
+                // The crossfade into the next queued buffer starts when the rest of the current buffer is as
+                // long as the crossfade.
+                if let Some((offset, frames)) = self.queue_crossfade_start(&buffer, end - start) {
+                    self.render_range(&buffer, start, start + offset);
+                    if self.status == Status::Playing && !self.buffer_ended {
+                        let next = self.queue.pop_front().unwrap();
+                        self.begin_crossfade(&mut buffer, next, frames);
+                        self.push_event(SoundEventKind::Started);
+                    }
+                }
+                self.render_range(&buffer, self.frame_samples.len(), end);
+                // Queued buffers are played back-to-back.
+                while std::mem::take(&mut self.buffer_ended) {
+                    let finished = std::mem::replace(&mut buffer, self.queue.pop_front().unwrap());
+                    self.retire(finished);
+                    self.buf_read_pos = 0.0;
+                    self.playback_pos = 0.0;
+                    self.rewound = true;
+                    self.update_resampling_multiplier(&buffer);
+                    self.push_event(SoundEventKind::Started);
+                    if buffer.samples.is_empty() {
+                        self.end_buffer();
+                    } else {
+                        self.render_range(&buffer, self.frame_samples.len(), end);
+                    }
+                }
             }
+            self.buffer = Some(buffer);
+        }
+        if stop.is_some() && self.status == Status::Playing {
+            self.end_playback();
         }
         // Fill the remaining part of frame_samples.
         self.frame_samples.resize(amount, (0.0, 0.0));
+        self.mix_outgoing();
+        self.apply_context_gain();
+    }
+
+    fn update_resampling_multiplier(&mut self, buffer: &Buffer) {
+        // Pitch of the group changes the speed just like the resampling does.
+        self.resampling_multiplier =
+            buffer.sample_rate() as f64 / SAMPLE_RATE as f64 * self.group_pitch;
+    }
+
+    // Renders frames `from..to` of the block.
+    fn render_range(&mut self, buffer: &Buffer, from: usize, to: usize) {
+        if from >= to || self.status != Status::Playing || self.buffer_ended {
+            return;
+        }
+        if self.pitch_curve.len() >= to {
+            self.render_automated_pitch(buffer, from, to);
+        } else {
+            self.render_playing(buffer, to - from);
+        }
     }
 
-    fn render_playing(&mut self, buffer: &mut Buffer, amount: usize) {
+    // Returns the offset within the next `amount` frames at which the crossfade into the next queued buffer
+    // starts, and the length of the crossfade.
+    fn queue_crossfade_start(&self, buffer: &Buffer, amount: usize) -> Option<(usize, usize)> {
+        let speed = if self.stretcher.is_some() {
+            self.tempo
+        } else {
+            self.pitch
+        };
+        let step = speed * self.resampling_multiplier;
+        let buffer_len = buffer.channel_duration_in_samples();
+        if self.queue_crossfade == 0
+            || self.queue.is_empty()
+            || step <= 0.0
+            || (self.looping && !self.released)
+            || self.active_loop(buffer_len, false).is_some()
+        {
+            return None;
+        }
+        let remaining = ((buffer_len as f64 - self.buf_read_pos) / step).max(0.0);
+        let offset = (remaining - self.queue_crossfade as f64).max(0.0).ceil() as usize;
+        let frames = (self.queue_crossfade as f64).min(remaining).ceil() as usize;
+        (offset < amount && frames > 0).then_some((offset, frames))
+    }
+
+    // Starts to fade the current buffer out and the given one in, the new buffer is played from its beginning.
+    fn begin_crossfade(&mut self, buffer: &mut SharedBuffer, next: SharedBuffer, frames: usize) {
+        let speed = if self.stretcher.is_some() {
+            self.tempo
+        } else {
+            self.pitch
+        };
+        let previous = std::mem::replace(buffer, next);
+        // A crossfade in progress is replaced, its outgoing buffer is cut off.
+        if let Some(outgoing) = self.outgoing.take() {
+            self.retire(outgoing.buffer);
+        }
+        if frames > 0 {
+            self.outgoing = Some(Outgoing {
+                buffer: previous,
+                position: self.buf_read_pos,
+                step: speed * self.resampling_multiplier,
+                length: frames,
+                elapsed: 0,
+                first_frame: self.frame_samples.len(),
+            });
+        } else {
+            self.retire(previous);
+        }
+        self.buf_read_pos = 0.0;
+        self.playback_pos = 0.0;
+        self.rewound = true;
+        self.update_resampling_multiplier(buffer);
+    }
+
+    // Mixes the outgoing buffer of a crossfade into the rendered block using equal-power curves.
+    fn mix_outgoing(&mut self) {
+        let Some(mut outgoing) = self.outgoing.take() else {
+            return;
+        };
+        let first_frame = std::mem::take(&mut outgoing.first_frame).min(self.frame_samples.len());
+        for (left, right) in self.frame_samples[first_frame..].iter_mut() {
+            let angle =
+                outgoing.elapsed as f32 / outgoing.length as f32 * std::f32::consts::FRAC_PI_2;
+            let (fade_in, fade_out) = angle.sin_cos();
+            let (out_left, out_right) = outgoing.next_frame();
+            *left = *left * fade_in + out_left * fade_out;
+            *right = *right * fade_in + out_right * fade_out;
+            outgoing.elapsed += 1;
+            if outgoing.elapsed == outgoing.length {
+                self.retire(outgoing.buffer);
+                return;
+            }
+        }
+        self.outgoing = Some(outgoing);
+    }
+
+    // Applies the fade envelope (see `fade_in`), the gain of the group and the voice gain, which fades the
+    // voice in or out when it becomes real or virtual. They're applied before pre-fader sends take the signal,
+    // so sends fade together with the source. Changes of the group and voice gains are interpolated over the
+    // block.
+    fn apply_context_gain(&mut self) {
+        if !self.fade_curve.is_empty() {
+            for ((left, right), gain) in self.frame_samples.iter_mut().zip(self.fade_curve.iter()) {
+                *left *= *gain;
+                *right *= *gain;
+            }
+        } else if self.fade_gain != 1.0 {
+            for (left, right) in self.frame_samples.iter_mut() {
+                *left *= self.fade_gain;
+                *right *= self.fade_gain;
+            }
+        }
+
+        let gain = self.group_gain * self.voice_gain;
+        let last_gain = self.last_group_gain * self.last_voice_gain;
+        if gain != last_gain {
+            let step = (gain - last_gain) / self.frame_samples.len().max(1) as f32;
+            let mut current = last_gain;
+            for (left, right) in self.frame_samples.iter_mut() {
+                *left *= current;
+                *right *= current;
+                current += step;
+            }
+        } else if gain != 1.0 {
+            for (left, right) in self.frame_samples.iter_mut() {
+                *left *= gain;
+                *right *= gain;
+            }
+        }
+        self.last_group_gain = self.group_gain;
+        self.last_voice_gain = self.voice_gain;
+    }
+
+    fn render_playing(&mut self, buffer: &Buffer, amount: usize) {
+        if self.is_virtual() {
+            self.skip_playing(buffer, amount);
+            return;
+        }
+        if let Some(mut stretcher) = self.stretcher.take() {
+            self.render_stretched(buffer, amount, &mut stretcher);
+            self.stretcher = Some(stretcher);
+            return;
+        }
+        if self.pitch < 0.0 {
+            self.render_backwards(buffer, amount);
+            return;
+        }
+
+        self.rewound = false;
+        let buffer_len = buffer.channel_duration_in_samples();
         let mut count = 0;
         loop {
-            count += self.render_until_block_end(buffer, amount - count);
+            let active_loop = self.active_loop(buffer_len, false);
+            let remaining = amount - count;
+            let (from, first_frame) = (self.buf_read_pos, self.frame_samples.len());
+            let rendered = match active_loop {
+                Some((region, crossfade))
+                    if self.buf_read_pos >= (region.end - crossfade) as f64 =>
+                {
+                    self.render_loop_crossfade(buffer, remaining, region, crossfade)
+                }
+                Some((region, crossfade)) => {
+                    self.render_until_block_end(buffer, remaining, region.end - crossfade)
+                }
+                None => self.render_until_block_end(buffer, remaining, buffer_len),
+            };
+            count += rendered;
+            self.push_markers(
+                buffer,
+                from,
+                first_frame,
+                self.pitch * self.resampling_multiplier,
+            );
+
+            match active_loop {
+                // Resampling stops one frame before the end of the buffer, so the end of a region that ends
+                // with the buffer is detected by the amount of rendered frames.
+                Some((region, crossfade))
+                    if self.buf_read_pos >= region.end as f64
+                        || (crossfade == 0 && rendered < remaining) =>
+                {
+                    let overshoot = (self.buf_read_pos - region.end as f64).max(0.0);
+                    let position = region.start as f64 + overshoot % region.len() as f64;
+                    self.playback_pos -= self.buf_read_pos - position;
+                    self.buf_read_pos = position;
+                    self.push_event(SoundEventKind::Looped);
+                }
+                None if rendered < remaining => {
+                    if !self.looping || self.released {
+                        self.end_buffer();
+                        return;
+                    }
+                    self.buf_read_pos = 0.0;
+                    self.playback_pos = 0.0;
+                    self.push_event(SoundEventKind::Looped);
+                }
+                _ => (),
+            }
+
             if count == amount {
                 break;
             }
+        }
+    }
 
-            self.buf_read_pos = 0.0;
-            self.playback_pos = 0.0;
-            if !self.looping {
-                self.status = Status::Stopped;
-                return;
+    // Advances playback of a virtual voice as if `amount` frames were rendered, but writes silence. Loops,
+    // markers and the end of playback are handled just like in the normal playback.
+    fn skip_playing(&mut self, buffer: &Buffer, amount: usize) {
+        let stretched = self.stretcher.is_some();
+        let speed = if stretched { self.tempo } else { self.pitch };
+        let step = speed * self.resampling_multiplier;
+        let backwards = step < 0.0;
+        let buffer_len = buffer.channel_duration_in_samples();
+        if std::mem::take(&mut self.rewound) && backwards {
+            let last = buffer_len.saturating_sub(1) as f64;
+            self.buf_read_pos = last;
+            self.playback_pos = last;
+        }
+
+        let first_frame = self.frame_samples.len();
+        let mut skipped = 0;
+        while skipped < amount && step != 0.0 {
+            // Time stretching ignores loop regions.
+            let active_loop = if stretched {
+                None
+            } else {
+                self.active_loop(buffer_len, backwards)
+            };
+            let (start, end) =
+                active_loop.map_or((0, buffer_len), |(region, _)| (region.start, region.end));
+            // Amount of frames until playback passes the boundary of the region (or the buffer).
+            let frames = if backwards {
+                ((self.buf_read_pos - start as f64) / -step).floor() + 1.0
+            } else {
+                ((end as f64 - self.buf_read_pos) / step).ceil()
+            };
+            let frames = (frames.max(0.0) as usize).min(amount - skipped);
+            let from = self.buf_read_pos;
+            self.buf_read_pos += frames as f64 * step;
+            self.playback_pos += frames as f64 * step;
+            self.push_markers(buffer, from, first_frame + skipped, step);
+            skipped += frames;
+            self.frame_samples.resize(first_frame + skipped, (0.0, 0.0));
+
+            let passed = if backwards {
+                self.buf_read_pos < start as f64
+            } else {
+                self.buf_read_pos >= end as f64
+            };
+            if passed {
+                if active_loop.is_none() && (!self.looping || self.released) {
+                    self.end_buffer();
+                    return;
+                }
+                let position = start as f64
+                    + (self.buf_read_pos - start as f64).rem_euclid((end - start) as f64);
+                self.playback_pos += position - self.buf_read_pos;
+                self.buf_read_pos = position;
+                self.push_event(SoundEventKind::Looped);
+            }
+        }
+        self.frame_samples.resize(first_frame + amount, (0.0, 0.0));
+    }
+
+    // Renders in the time-stretch mode, positions are advanced by tempo and grains are resampled by pitch.
+    fn render_stretched(&mut self, buffer: &Buffer, amount: usize, stretcher: &mut Stretcher) {
+        let speed = self.tempo * self.resampling_multiplier;
+        let rate = self.pitch.abs() * self.resampling_multiplier;
+        let buffer_len = buffer.channel_duration_in_samples() as f64;
+        if std::mem::take(&mut self.rewound) {
+            stretcher.set_expected(f64::NAN);
+        }
+        stretcher.seek(self.buf_read_pos);
+
+        for _ in 0..amount {
+            let frame = stretcher.next_frame(buffer, self.looping && !self.released, speed, rate);
+            let (from, first_frame) = (self.buf_read_pos, self.frame_samples.len());
+            self.frame_samples.push(frame);
+            self.buf_read_pos += speed;
+            self.playback_pos += speed;
+            self.push_markers(buffer, from, first_frame, speed);
+            if self.buf_read_pos >= buffer_len {
+                if !self.looping || self.released {
+                    self.end_buffer();
+                    return;
+                }
+                self.buf_read_pos -= buffer_len;
+                self.playback_pos -= buffer_len;
+                stretcher.wrap(buffer_len);
+                self.push_event(SoundEventKind::Looped);
             }
         }
+        stretcher.set_expected(self.buf_read_pos);
+    }
+
+    // Returns the loop region and the length of the crossfade if playback hasn't passed the loop region yet and
+    // the region should be repeated. Playing backwards passes the region at its start.
+    fn active_loop(&self, buffer_len: usize, backwards: bool) -> Option<(LoopRegion, usize)> {
+        let region = self.loop_region()?;
+        let passed = if backwards {
+            self.buf_read_pos < region.start as f64
+        } else {
+            self.buf_read_pos >= region.end as f64
+        };
+        if !self.looping || self.released || region.len() < 2 || region.end > buffer_len || passed {
+            return None;
+        }
+        // The crossfade uses frames outside of the region: before its start when playing forward and after
+        // its end when playing backwards.
+        let outside = if backwards {
+            buffer_len - region.end
+        } else {
+            region.start
+        };
+        let crossfade = self.loop_crossfade.min(outside).min(region.len() / 2);
+        Some((region, crossfade))
     }
 
-    // Renders until the end of the block or until amount samples is written and returns
+    // Renders with negative pitch. It is the mirror of the forward rendering, but it is done frame by frame
+    // with interpolation, since backward playback is rare.
+    fn render_backwards(&mut self, buffer: &Buffer, amount: usize) {
+        let step = self.pitch * self.resampling_multiplier;
+        let buffer_len = buffer.channel_duration_in_samples();
+        let last = buffer_len.saturating_sub(1) as f64;
+        if std::mem::take(&mut self.rewound) {
+            self.buf_read_pos = last;
+            self.playback_pos = last;
+        }
+
+        let active_loop = self.active_loop(buffer_len, true);
+        for _ in 0..amount {
+            let position = self.buf_read_pos;
+            let frame = match active_loop {
+                Some((region, crossfade)) if position < (region.start + crossfade) as f64 => {
+                    // The start of the region fades into the frames following its end.
+                    let t =
+                        (((region.start + crossfade) as f64 - position) / crossfade as f64) as f32;
+                    let (out_left, out_right) = interpolate_frame(buffer, position);
+                    let (in_left, in_right) =
+                        interpolate_frame(buffer, position + region.len() as f64);
+                    (lerp(out_left, in_left, t), lerp(out_right, in_right, t))
+                }
+                _ => interpolate_frame(buffer, position),
+            };
+            let first_frame = self.frame_samples.len();
+            self.frame_samples.push(frame);
+            self.buf_read_pos += step;
+            self.playback_pos += step;
+            self.push_markers(buffer, position, first_frame, step);
+
+            match active_loop {
+                Some((region, _)) if self.buf_read_pos < region.start as f64 => {
+                    let overshoot = region.start as f64 - self.buf_read_pos;
+                    let position = region.end as f64 - overshoot % region.len() as f64;
+                    self.playback_pos += position - self.buf_read_pos;
+                    self.buf_read_pos = position;
+                    self.push_event(SoundEventKind::Looped);
+                }
+                None if self.buf_read_pos < 0.0 => {
+                    if !self.looping || self.released {
+                        self.end_buffer();
+                        return;
+                    }
+                    let position = buffer_len as f64 - (-self.buf_read_pos) % buffer_len as f64;
+                    self.playback_pos += position - self.buf_read_pos;
+                    self.buf_read_pos = position;
+                    self.push_event(SoundEventKind::Looped);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // Renders the crossfade at the end of the loop region: frames before the end of the region are mixed with
+    // the frames preceding its start, so playback smoothly continues from the start of the region. Returns the
+    // number of written samples.
+    fn render_loop_crossfade(
+        &mut self,
+        buffer: &Buffer,
+        amount: usize,
+        region: LoopRegion,
+        crossfade: usize,
+    ) -> usize {
+        let step = self.pitch * self.resampling_multiplier;
+        let crossfade_start = (region.end - crossfade) as f64;
+        let mut rendered = 0;
+        while rendered < amount && self.buf_read_pos < region.end as f64 {
+            let t = ((self.buf_read_pos - crossfade_start) / crossfade as f64) as f32;
+            let (out_left, out_right) = interpolate_frame(buffer, self.buf_read_pos);
+            let (in_left, in_right) =
+                interpolate_frame(buffer, self.buf_read_pos - region.len() as f64);
+            self.frame_samples
+                .push((lerp(out_left, in_left, t), lerp(out_right, in_right, t)));
+            self.buf_read_pos += step;
+            self.playback_pos += step;
+            rendered += 1;
+        }
+        rendered
+    }
+
+    // Renders frames `from..to` of the block in parts with constant pitch, so pitch changes are
+    // sample-accurate.
+    fn render_automated_pitch(&mut self, buffer: &Buffer, from: usize, to: usize) {
+        let mut start = from;
+        while start < to && self.status == Status::Playing && !self.buffer_ended {
+            let pitch = self.pitch_curve[start];
+            let end = self.pitch_curve[start..to]
+                .iter()
+                .position(|other| *other != pitch)
+                .map_or(to, |length| start + length);
+            self.pitch = pitch as f64;
+            self.render_playing(buffer, end - start);
+            start = end;
+        }
+    }
+
+    // Renders until the end of the block (or the `limit` frame) or until amount samples is written and returns
     // the number of written samples.
-    fn render_until_block_end(&mut self, buffer: &mut Buffer, mut amount: usize) -> usize {
+    fn render_until_block_end(
+        &mut self,
+        buffer: &Buffer,
+        mut amount: usize,
+        limit: usize,
+    ) -> usize {
         let step = self.pitch * self.resampling_multiplier;
         if step == 1.0 {
             if self.buf_read_pos < 0.0 {
@@ -223,8 +1543,7 @@ impl SoundSource {
             }
             // Fast-path for common case when there is no resampling and no pitch change.
             let from = self.buf_read_pos as usize;
-            let buffer_len = buffer.samples.len() / buffer.channel_count();
-            let rendered = (buffer_len - from).min(amount);
+            let rendered = limit.saturating_sub(from).min(amount);
             if buffer.channel_count() == 2 {
                 for i in from..from + rendered {
                     self.frame_samples
@@ -240,16 +1559,17 @@ impl SoundSource {
             self.playback_pos += rendered as f64;
             rendered
         } else {
-            self.render_until_block_end_resample(buffer, amount, step)
+            self.render_until_block_end_resample(buffer, amount, step, limit)
         }
     }
 
     // Does linear resampling while rendering until the end of the block.
     fn render_until_block_end_resample(
         &mut self,
-        buffer: &mut Buffer,
+        buffer: &Buffer,
         amount: usize,
         step: f64,
+        limit: usize,
     ) -> usize {
         let mut rendered = 0;
 
@@ -279,8 +1599,14 @@ impl SoundSource {
         let start_buffer_rel_pos = buffer_rel_pos;
         let rel_step = step as f32;
         // We skip one last element because the hot loop resampling between current and next
-        // element. Last elements are appended after the hot loop.
-        let buffer_last = buffer.samples.len() / buffer.channel_count() - 1;
+        // element. Last elements are appended after the hot loop. If the limit is inside the buffer, the frame at
+        // the limit could still be used for resampling.
+        let buffer_len = buffer.samples.len() / buffer.channel_count();
+        let buffer_last = if limit < buffer_len {
+            limit
+        } else {
+            buffer_len - 1
+        };
         if buffer.channel_count() == 2 {
             while rendered < amount {
                 let (idx, w) = {
@@ -323,6 +1649,125 @@ impl SoundSource {
     pub(crate) fn frame_samples(&self) -> &[(f32, f32)] {
         &self.frame_samples
     }
+
+    // Clamped distance between the source and the listener.
+    fn distance_to(&self, listener: &Listener) -> f32 {
+        self.position
+            .distance(listener.position())
+            .max(self.radius)
+            .min(self.max_distance)
+    }
+
+    pub(crate) fn calculate_panning(&self, listener: &Listener) -> f32 {
+        (self.position - listener.position())
+            .try_normalize()
+            // Fallback to look axis will give zero panning which will result in even
+            // gain in each channels (as if there was no panning at all).
+            .unwrap_or_else(|| listener.look_axis())
+            .dot(listener.ear_axis())
+    }
+
+    pub(crate) fn calculate_distance_gain(
+        &self,
+        listener: &Listener,
+        distance_model: DistanceModel,
+    ) -> f32 {
+        // Zero radius would give 0/0 in the inverse and exponent models.
+        let radius = self.radius.max(f32::EPSILON);
+        let distance = self.distance_to(listener).max(radius);
+        match distance_model {
+            DistanceModel::None => 1.0,
+            DistanceModel::InverseDistance => {
+                radius / (radius + self.rolloff_factor * (distance - radius))
+            }
+            DistanceModel::LinearDistance => {
+                if self.max_distance <= radius {
+                    // There is no range to decay over.
+                    1.0
+                } else {
+                    (1.0 - (distance - radius) / (self.max_distance - radius)).max(0.0)
+                }
+            }
+            DistanceModel::ExponentDistance => (distance / radius).powf(-self.rolloff_factor),
+        }
+    }
+
+    pub(crate) fn calculate_cone_gain(&self, listener: &Listener) -> f32 {
+        let (Some(direction), Some(to_listener)) = (
+            self.direction.try_normalize(),
+            (listener.position() - self.position).try_normalize(),
+        ) else {
+            // Omnidirectional source or the listener is exactly at the source position.
+            return 1.0;
+        };
+
+        // Full angle of a cone around the direction that has the listener on its surface.
+        let angle = 2.0 * direction.dot(to_listener).clamp(-1.0, 1.0).acos();
+        let outer_angle = self.cone_outer_angle.max(self.cone_inner_angle);
+        if angle <= self.cone_inner_angle {
+            1.0
+        } else if angle >= outer_angle {
+            self.cone_outer_gain
+        } else {
+            let t = (angle - self.cone_inner_angle) / (outer_angle - self.cone_inner_angle);
+            lerp(1.0, self.cone_outer_gain, t)
+        }
+    }
+
+    // Returns coefficient of the one-pole air absorption filter, zero means no filtering.
+    pub(crate) fn calculate_air_absorption_coefficient(&self, listener: &Listener) -> f32 {
+        let excess_distance = self.distance_to(listener) - self.radius;
+        if self.air_absorption <= 0.0 || excess_distance <= 0.0 {
+            return 0.0;
+        }
+        let cutoff = AIR_ABSORPTION_REFERENCE_CUTOFF / (self.air_absorption * excess_distance);
+        (-std::f32::consts::TAU * cutoff / SAMPLE_RATE as f32).exp()
+    }
+
+    // Applies air absorption low-pass filter to the rendered frame samples. The coefficient is
+    // interpolated from the value of the previous block to remove zipper noise.
+    pub(crate) fn apply_air_absorption(&mut self, coefficient: f32) {
+        let last_coefficient = *self.last_air_coefficient.get_or_insert(coefficient);
+        self.last_air_coefficient = Some(coefficient);
+
+        if last_coefficient == 0.0 && coefficient == 0.0 {
+            // Keep filter state in sync, so the filter won't click when it kicks in.
+            if let Some(&last) = self.frame_samples.last() {
+                self.air_filter_state = last;
+            }
+            return;
+        }
+
+        let step = 1.0 / self.frame_samples.len().max(1) as f32;
+        let mut t = 0.0;
+        let (mut state_left, mut state_right) = self.air_filter_state;
+        for (left, right) in self.frame_samples.iter_mut() {
+            let a = lerp(last_coefficient, coefficient, t);
+            state_left = *left * (1.0 - a) + state_left * a;
+            state_right = *right * (1.0 - a) + state_right * a;
+            *left = state_left;
+            *right = state_right;
+            t += step;
+        }
+        self.air_filter_state = (state_left, state_right);
+    }
+}
+
+// Reads a frame of the buffer at the fractional position using linear interpolation.
+fn interpolate_frame(buffer: &Buffer, position: f64) -> (f32, f32) {
+    let last = buffer.channel_duration_in_samples().saturating_sub(1);
+    let index = (position.max(0.0) as usize).min(last);
+    let next = (index + 1).min(last);
+    let w = (position - index as f64).clamp(0.0, 1.0) as f32;
+    let frame = |i: usize| {
+        if buffer.channel_count() == 2 {
+            (buffer.samples[i * 2], buffer.samples[i * 2 + 1])
+        } else {
+            (buffer.samples[i], buffer.samples[i])
+        }
+    };
+    let (current, next) = (frame(index), frame(next));
+    (lerp(current.0, next.0, w), lerp(current.1, next.1, w))
 }
 
 impl Default for SoundSource {
@@ -336,18 +1781,356 @@ impl Default for SoundSource {
             gain: 1.0,
             spatial_blend: 1.0,
             looping: false,
+            loop_region: None,
+            loop_crossfade: 0,
+            released: false,
+            rewound: true,
+            stretcher: None,
+            tempo: 1.0,
+            events: Default::default(),
+            priority: 128,
+            voice_gain: 1.0,
+            last_voice_gain: 1.0,
+            group: Handle::NONE,
+            group_gain: 1.0,
+            last_group_gain: 1.0,
+            group_pitch: 1.0,
+            group_paused: false,
+            start_time: 0,
+            queue: Default::default(),
+            queue_crossfade: 0,
+            buffer_ended: false,
+            outgoing: None,
+            pending_crossfade: None,
+            retired: Default::default(),
             resampling_multiplier: 1.0,
             status: Status::Stopped,
-            bus: "Master".to_string(),
+            bus: Handle::NONE,
+            bus_name: Some(AudioBusGraph::PRIMARY_BUS.to_string()),
+            bus_version: None,
+            sends: Default::default(),
             play_once: false,
             last_left_gain: None,
             last_right_gain: None,
+            last_ambisonic_gains: None,
             frame_samples: Default::default(),
             prev_buffer_sample: (0.0, 0.0),
             radius: 1.0,
             position: Vec3::new(0.0, 0.0, 0.0),
             max_distance: f32::MAX,
             rolloff_factor: 1.0,
+            direction: Vec3::ZERO,
+            cone_inner_angle: std::f32::consts::TAU,
+            cone_outer_angle: std::f32::consts::TAU,
+            cone_outer_gain: 0.0,
+            air_absorption: 0.0,
+            air_filter_state: (0.0, 0.0),
+            last_air_coefficient: None,
+            gain_automation: Default::default(),
+            pitch_automation: Default::default(),
+            gain_curve: Default::default(),
+            pitch_curve: Default::default(),
+            last_gain: None,
+            scheduled_start: None,
+            scheduled_stop: None,
+            start_offset: 0,
+            stop_offset: None,
+            fade: Default::default(),
+            fade_gain: 1.0,
+            fade_curve: Default::default(),
+            pending_fade: None,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::dissection::{
+        buffer::{Buffer, LoopRegion},
+        engine::DistanceModel,
+        listener::Listener,
+        source::{SoundEventKind, SoundSource, Status},
+    };
+    use crate::SAMPLE_RATE;
+    use glam::Vec3;
+    use std::time::Duration;
+
+    fn render_left(source: &mut SoundSource, amount: usize) -> Vec<f32> {
+        source.render(amount);
+        source
+            .frame_samples()
+            .iter()
+            .map(|(left, _)| *left)
+            .collect()
+    }
+
+    #[test]
+    fn test_loop_region_and_release() {
+        let samples = (0..16).map(|i| i as f32).collect();
+        let mut source = SoundSource::default();
+        source
+            .set_buffer(Some(Buffer::new(samples, true).into()))
+            .set_loop_region(Some(LoopRegion::new(4, 8)));
+        source.looping = true;
+        source.status = Status::Playing;
+
+        let expected = [0, 1, 2, 3, 4, 5, 6, 7, 4, 5, 6, 7, 4, 5, 6, 7].map(|i| i as f32);
+        assert_eq!(render_left(&mut source, 16), expected);
+        assert_eq!(render_left(&mut source, 4), [4.0, 5.0, 6.0, 7.0]);
+
+        // The tail is played after release.
+        source.release();
+        let mut expected = (4..16).map(|i| i as f32).collect::<Vec<_>>();
+        expected.resize(16, 0.0);
+        assert_eq!(render_left(&mut source, 16), expected);
+        assert_eq!(source.status, Status::Stopped);
+        assert!(!source.is_released());
+    }
+
+    #[test]
+    fn test_loop_crossfade() {
+        let samples = (0..16).map(|i| i as f32).collect();
+        let mut source = SoundSource::default();
+        source
+            .set_buffer(Some(Buffer::new(samples, true).into()))
+            .set_loop_region(Some(LoopRegion::new(8, 12)))
+            .set_loop_crossfade(2);
+        source.looping = true;
+        source.status = Status::Playing;
+
+        // The end of the region fades into the frames preceding its start.
+        let expected = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 9, 8, 9, 10, 9].map(|i| i as f32);
+        assert_eq!(render_left(&mut source, 16), expected);
+    }
+
+    #[test]
+    fn test_backward_playback() {
+        let samples = (0..8).map(|i| i as f32).collect();
+        let mut source = SoundSource::default();
+        source
+            .set_buffer(Some(Buffer::new(samples, true).into()))
+            .set_pitch(-1.0);
+        source.status = Status::Playing;
+
+        let expected = [7, 6, 5, 4, 3, 2, 1, 0, 0, 0].map(|i| i as f32);
+        assert_eq!(render_left(&mut source, 10), expected);
+        assert_eq!(source.status, Status::Stopped);
+
+        // Direction changes keep the position.
+        source.status = Status::Playing;
+        assert_eq!(render_left(&mut source, 3), [7.0, 6.0, 5.0]);
+        source.set_pitch(0.5);
+        assert_eq!(render_left(&mut source, 3), [4.0, 4.5, 5.0]);
+        source.set_pitch(-2.0);
+        assert_eq!(render_left(&mut source, 2), [5.5, 3.5]);
+        assert_eq!(
+            source.playback_time(),
+            Duration::from_secs_f64(1.5 / SAMPLE_RATE as f64)
+        );
+    }
+
+    #[test]
+    fn test_backward_loop_region() {
+        let samples = (0..8).map(|i| i as f32).collect();
+        let mut source = SoundSource::default();
+        source
+            .set_buffer(Some(Buffer::new(samples, true).into()))
+            .set_loop_region(Some(LoopRegion::new(2, 6)))
+            .set_pitch(-1.0);
+        source.looping = true;
+        source.status = Status::Playing;
+
+        let expected = [7, 6, 5, 4, 3, 2, 5, 4, 3, 2, 5, 4].map(|i| i as f32);
+        assert_eq!(render_left(&mut source, 12), expected);
+        source.release();
+        assert_eq!(render_left(&mut source, 6), [3.0, 2.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(source.status, Status::Stopped);
+
+        // The start of the region fades into the frames following its end.
+        source.set_loop_crossfade(1);
+        source.status = Status::Playing;
+        let expected = [7, 6, 5, 4, 3, 6, 5, 4, 3, 6].map(|i| i as f32);
+        assert_eq!(render_left(&mut source, 10), expected);
+    }
+
+    #[test]
+    fn test_gapless_queue() {
+        let mut source = SoundSource::default();
+        source
+            .enqueue(Buffer::new(vec![0.25; 10], true).into())
+            .enqueue(Buffer::new(vec![0.5; 10], true).into());
+        source.status = Status::Playing;
+
+        let mut expected = vec![0.25; 10];
+        expected.resize(16, 0.5);
+        assert_eq!(render_left(&mut source, 16), expected);
+        assert_eq!(
+            source.events(),
+            [(SoundEventKind::Started, 0), (SoundEventKind::Started, 10)]
+        );
+        assert!(source.queue().is_empty());
+
+        assert_eq!(
+            render_left(&mut source, 8),
+            [0.5, 0.5, 0.5, 0.5, 0.0, 0.0, 0.0, 0.0]
+        );
+        assert_eq!(source.events(), [(SoundEventKind::Finished, 4)]);
+        assert_eq!(source.status, Status::Stopped);
+    }
+
+    #[test]
+    fn test_queue_crossfade() {
+        let mut source = SoundSource::default();
+        source
+            .set_queue_crossfade(8)
+            .enqueue(Buffer::new(vec![1.0; 20], true).into())
+            .enqueue(Buffer::new(vec![0.0; 40], true).into());
+        source.status = Status::Playing;
+
+        let output = render_left(&mut source, 32);
+        assert_eq!(output[..12], [1.0; 12]);
+        for (i, sample) in output[12..20].iter().enumerate() {
+            let expected = (i as f32 / 8.0 * std::f32::consts::FRAC_PI_2).cos();
+            assert!((sample - expected).abs() < 1.0e-6);
+        }
+        assert_eq!(output[20..], [0.0; 12]);
+        assert_eq!(source.events()[1], (SoundEventKind::Started, 12));
+        // The next buffer started 20 frames ago.
+        assert_eq!(
+            source.playback_time(),
+            Duration::from_secs_f64(20.0 / SAMPLE_RATE as f64)
+        );
+    }
+
+    #[test]
+    fn test_crossfade_to() {
+        let mut source = SoundSource::default();
+        source.set_buffer(Some(Buffer::new(vec![1.0; 64], true).into()));
+        source.looping = true;
+        source.status = Status::Playing;
+        render_left(&mut source, 8);
+
+        let next = Buffer::new(vec![0.0; 64], true).into();
+        source.crossfade_to(next, 4);
+        let output = render_left(&mut source, 8);
+        for (i, sample) in output.iter().enumerate() {
+            let expected = if i < 4 {
+                (i as f32 / 4.0 * std::f32::consts::FRAC_PI_2).cos()
+            } else {
+                0.0
+            };
+            assert!((sample - expected).abs() < 1.0e-6);
+        }
+        assert_eq!(source.buffer().unwrap().samples[0], 0.0);
+    }
+
+    #[test]
+    fn test_cone_gain() {
+        let mut source = SoundSource::default();
+        source
+            .set_direction(Vec3::new(0.0, 0.0, 1.0))
+            .set_cone_inner_angle(90.0f32.to_radians())
+            .set_cone_outer_angle(180.0f32.to_radians())
+            .set_cone_outer_gain(0.25);
+
+        let mut listener = Listener::new();
+
+        // In front of the source - inside the inner cone.
+        listener.set_position(Vec3::new(0.0, 0.0, 10.0));
+        assert_eq!(source.calculate_cone_gain(&listener), 1.0);
+
+        // Behind the source - outside the outer cone.
+        listener.set_position(Vec3::new(0.0, 0.0, -10.0));
+        assert_eq!(source.calculate_cone_gain(&listener), 0.25);
+
+        // 67.5 degrees off-axis - halfway between the inner and the outer cones.
+        let angle = 67.5f32.to_radians();
+        listener.set_position(Vec3::new(angle.sin(), 0.0, angle.cos()) * 10.0);
+        assert!((source.calculate_cone_gain(&listener) - 0.625).abs() < 1.0e-4);
+
+        // Omnidirectional source.
+        source.set_direction(Vec3::ZERO);
+        assert_eq!(source.calculate_cone_gain(&listener), 1.0);
+    }
+
+    #[test]
+    fn test_distance_gain_and_panning() {
+        let mut source = SoundSource::default();
+        source.set_position(Vec3::new(4.0, 0.0, 0.0));
+
+        let listener = Listener::new();
+        assert_eq!(
+            source.calculate_distance_gain(&listener, DistanceModel::InverseDistance),
+            0.25
+        );
+        assert_eq!(
+            source.calculate_distance_gain(&listener, DistanceModel::None),
+            1.0
+        );
+        // The source is exactly to the right of the listener.
+        assert_eq!(source.calculate_panning(&listener), 1.0);
+    }
+
+    #[test]
+    fn test_distance_gain_with_radius() {
+        let mut source = SoundSource::default();
+        source.set_radius(2.0).set_max_distance(10.0);
+        let listener = Listener::new();
+
+        let gain_at = |source: &mut SoundSource, x: f32, model: DistanceModel| {
+            source.set_position(Vec3::new(x, 0.0, 0.0));
+            source.calculate_distance_gain(&listener, model)
+        };
+
+        // Linear model decays from full volume at the radius to silence at the max distance.
+        let linear = DistanceModel::LinearDistance;
+        assert_eq!(gain_at(&mut source, 1.0, linear), 1.0);
+        assert_eq!(gain_at(&mut source, 6.0, linear), 0.5);
+        assert_eq!(gain_at(&mut source, 10.0, linear), 0.0);
+        assert_eq!(gain_at(&mut source, 20.0, linear), 0.0);
+        let inverse = DistanceModel::InverseDistance;
+        assert_eq!(gain_at(&mut source, 4.0, inverse), 0.5);
+        let exponent = DistanceModel::ExponentDistance;
+        assert_eq!(gain_at(&mut source, 8.0, exponent), 0.25);
+
+        // Degenerate settings must not produce NaN or infinite gain.
+        source.set_radius(0.0);
+        for x in [0.0, 5.0] {
+            for model in [inverse, linear, exponent] {
+                let gain = gain_at(&mut source, x, model);
+                assert!((0.0..=1.0).contains(&gain), "{model:?} at {x}: {gain}");
+            }
+        }
+        source.set_radius(10.0);
+        assert_eq!(gain_at(&mut source, 5.0, linear), 1.0);
+    }
+
+    #[test]
+    fn test_air_absorption() {
+        let mut source = SoundSource::default();
+        let listener = Listener::new();
+
+        // Disabled by default.
+        source.set_position(Vec3::new(0.0, 0.0, 100.0));
+        assert_eq!(source.calculate_air_absorption_coefficient(&listener), 0.0);
+
+        source.set_air_absorption(1.0);
+        let far = source.calculate_air_absorption_coefficient(&listener);
+        source.set_position(Vec3::new(0.0, 0.0, 10.0));
+        let near = source.calculate_air_absorption_coefficient(&listener);
+        assert!(far > near && near > 0.0);
+
+        // Alternating signal (Nyquist frequency) must be attenuated, DC must pass through.
+        source.frame_samples = (0..64)
+            .map(|i| if i % 2 == 0 { (1.0, 1.0) } else { (-1.0, -1.0) })
+            .collect();
+        source.apply_air_absorption(far);
+        let (left, _) = source.frame_samples()[63];
+        assert!(left.abs() < 0.5);
+
+        source.frame_samples = vec![(1.0, 1.0); 4096];
+        source.apply_air_absorption(near);
+        let (left, _) = source.frame_samples()[4095];
+        assert!((left - 1.0).abs() < 1.0e-3);
+    }
+}
@@ -72,60 +72,60 @@ fn mess_test() {
 fn sound_engine_test() {
     let engine = SharedSoundEngine::new().unwrap();
     let context = SharedSoundContext::new();
-    engine.lock().context = context.clone();
 
     let sine_wave_buffer = sin_buffer(false);
 
-    {
+    // The context isn't attached to the engine yet, so it could be configured directly.
+    context.with_detached(|context| {
         let mut effects_bus = AudioBus::new("Effects".to_string());
         let effect = Effect::Attenuate(Attenuate::new(0.25));
         effects_bus.add_effect(effect);
-        let mut context = context.lock();
         let bus_graph = context.bus_graph_mut();
         let master_bus = bus_graph.primary_bus_handle();
-        bus_graph.add_bus(effects_bus, master_bus);
-    }
+        bus_graph.add_bus(effects_bus, master_bus).unwrap();
+    });
 
     // Create generic source (without spatial effects) using that buffer.
     let mut source = SoundSource::default();
-    source.buffer = Some(sine_wave_buffer);
+    source.set_buffer(Some(sine_wave_buffer.into()));
     source.looping = true;
     source.status = source::Status::Playing;
     source.set_bus("Effects");
     //dbg!(&source);
-    let source_handle = context.lock().add_source(source);
-
+    let source_handle = context.add_source(source);
+
+    engine.set_context(context.clone()).unwrap();
+
+    let timeout = std::time::Duration::from_secs(1);
+    if let Some((full_render_duration, bus_graph, paused)) = context
+        .query(|state| {
+            (
+                state.full_render_duration(),
+                format!("{:#?}", state.bus_graph_ref()),
+                state.paused,
+            )
+        })
+        .wait(timeout)
     {
-        let sound_state = context.lock();
-        println!(
-            "full_render_duration {:?}",
-            sound_state.full_render_duration()
-        );
-        println!("bus_graph {:#?}", sound_state.bus_graph_ref());
-        println!("is_paused {:?}", sound_state.paused);
+        println!("full_render_duration {:?}", full_render_duration);
+        println!("bus_graph {}", bus_graph);
+        println!("is_paused {:?}", paused);
     }
 
+    for pitch in [0.5, 1.5, 0.25] {
+        std::thread::sleep(std::time::Duration::from_secs(3));
+        context.edit_source(source_handle, move |source| {
+            source.set_pitch(pitch);
+        });
+        let source = context
+            .query(move |state| format!("{:?}", state.source(source_handle)))
+            .wait(timeout);
+        println!("source  {}", source.unwrap_or_default());
+    }
     std::thread::sleep(std::time::Duration::from_secs(3));
-
-    context.lock().source_mut(source_handle).set_pitch(0.5);
-    println!(
-        "source  {:?}",
-        context.lock().sources().try_borrow(source_handle).unwrap()
-    );
-    std::thread::sleep(std::time::Duration::from_secs(3));
-    context.lock().source_mut(source_handle).set_pitch(1.5);
-    println!(
-        "source  {:?}",
-        context.lock().sources().try_borrow(source_handle).unwrap()
-    );
-    std::thread::sleep(std::time::Duration::from_secs(3));
-    context.lock().source_mut(source_handle).set_pitch(0.25);
-    println!(
-        "source  {:?}",
-        context.lock().sources().try_borrow(source_handle).unwrap()
-    );
-    std::thread::sleep(std::time::Duration::from_secs(3));
-    context.lock().source_mut(source_handle).set_pitch(0.75);
+    context.edit_source(source_handle, |source| {
+        source.set_pitch(0.75);
+    });
     std::thread::sleep(std::time::Duration::from_secs(3));
 }
 
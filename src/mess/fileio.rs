@@ -1,7 +1,7 @@
 use std::io::{Read, Write};
+use std::path::Path;
 
-use crate::dissection::buffer::Buffer;
-use crate::SAMPLE_RATE;
+use crate::dissection::buffer::{Buffer, CueMarker, LoopRegion};
 
 pub fn make_wav_header(num_channels: u16, sample_rate: u32, num_frames: u32) -> [u8; 44] {
     let bits_per_sample = 32u16;
@@ -34,7 +34,183 @@ pub fn make_wav_header(num_channels: u16, sample_rate: u32, num_frames: u32) ->
     header
 }
 
+/// Returns the largest amount of frames that a header made by [`make_wav_header`] can describe, sizes of the
+/// RIFF and data chunks are 32-bit.
+pub fn max_wav_frames(num_channels: u16) -> u32 {
+    (u32::MAX - 36) / (num_channels as u32 * 4)
+}
+
+// Sample formats of WAV files.
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Debug, Clone, Copy)]
+struct WavFormat {
+    format: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+}
+
+impl WavFormat {
+    fn parse(chunk: &[u8]) -> anyhow::Result<Self> {
+        if chunk.len() < 16 {
+            anyhow::bail!("The fmt chunk is too short");
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+        let mut format = u16_at(0);
+        if format == WAVE_FORMAT_EXTENSIBLE && chunk.len() >= 26 {
+            // The actual format is stored in the first two bytes of the sub-format GUID.
+            format = u16_at(24);
+        }
+        Ok(Self {
+            format,
+            channels: u16_at(2),
+            sample_rate: u32::from_le_bytes(chunk[4..8].try_into().unwrap()),
+            bits_per_sample: u16_at(14),
+        })
+    }
+
+    fn decode(&self, data: &[u8]) -> anyhow::Result<Vec<f32>> {
+        let samples = match (self.format, self.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => data.iter().map(|&s| (s as f32 - 128.0) / 128.0).collect(),
+            (WAVE_FORMAT_PCM, 16) => data
+                .chunks_exact(2)
+                .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0)
+                .collect(),
+            (WAVE_FORMAT_PCM, 24) => data
+                .chunks_exact(3)
+                .map(|s| (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8) as f32 / 8388608.0)
+                .collect(),
+            (WAVE_FORMAT_PCM, 32) => data
+                .chunks_exact(4)
+                .map(|s| i32::from_le_bytes(s.try_into().unwrap()) as f32 / 2147483648.0)
+                .collect(),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => data
+                .chunks_exact(4)
+                .map(|s| f32::from_le_bytes(s.try_into().unwrap()))
+                .collect(),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => data
+                .chunks_exact(8)
+                .map(|s| f64::from_le_bytes(s.try_into().unwrap()) as f32)
+                .collect(),
+            (format, bits) => {
+                anyhow::bail!("Unsupported WAV sample format {format} with {bits} bits per sample")
+            }
+        };
+        Ok(samples)
+    }
+}
+
+// Reads the first loop of a `smpl` chunk. The end of a loop is inclusive in the chunk.
+fn parse_sampler_loop(chunk: &[u8]) -> Option<LoopRegion> {
+    let u32_at = |i: usize| u32::from_le_bytes(chunk[i..i + 4].try_into().unwrap());
+    if chunk.len() < 36 + 24 || u32_at(28) == 0 {
+        return None;
+    }
+    let start = u32_at(36 + 8) as usize;
+    let end = u32_at(36 + 12) as usize;
+    Some(LoopRegion::new(start, end.saturating_add(1)))
+}
+
+// Reads cue points of a `cue ` chunk, the position of a point in the data chunk is its sample offset.
+fn parse_cue_points(chunk: &[u8]) -> Vec<CueMarker> {
+    let u32_at = |i: usize| u32::from_le_bytes(chunk[i..i + 4].try_into().unwrap());
+    if chunk.len() < 4 {
+        return Vec::new();
+    }
+    let count = (u32_at(0) as usize).min((chunk.len() - 4) / 24);
+    (0..count)
+        .map(|i| {
+            let point = 4 + i * 24;
+            CueMarker::new(u32_at(point), u32_at(point + 20) as usize)
+        })
+        .collect()
+}
+
+// Chunks of a WAV file the crate cares about.
+struct WavChunks<'a> {
+    format: WavFormat,
+    data: &'a [u8],
+    loop_region: Option<LoopRegion>,
+    markers: Vec<CueMarker>,
+}
+
+impl<'a> WavChunks<'a> {
+    fn parse(bytes: &'a [u8]) -> anyhow::Result<Self> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            anyhow::bail!("Not a RIFF WAVE file");
+        }
+
+        let mut format = None;
+        let mut data = None;
+        let mut loop_region = None;
+        let mut markers = Vec::new();
+        let mut position = 12;
+        while position + 8 <= bytes.len() {
+            let id = &bytes[position..position + 4];
+            let size = u32::from_le_bytes(bytes[position + 4..position + 8].try_into().unwrap());
+            let start = position + 8;
+            // Some writers put wrong size into the last chunk, so it is clamped.
+            let end = start.saturating_add(size as usize).min(bytes.len());
+            let chunk = &bytes[start..end];
+            match id {
+                b"fmt " => format = Some(WavFormat::parse(chunk)?),
+                b"data" => data = Some(chunk),
+                b"smpl" => loop_region = parse_sampler_loop(chunk),
+                b"cue " => markers = parse_cue_points(chunk),
+                _ => (),
+            }
+            // Chunks are aligned to two bytes.
+            position = end + (end - start) % 2;
+        }
+
+        Ok(Self {
+            format: format.ok_or_else(|| anyhow::anyhow!("The fmt chunk is missing"))?,
+            data: data.ok_or_else(|| anyhow::anyhow!("The data chunk is missing"))?,
+            loop_region,
+            markers,
+        })
+    }
+}
+
 impl Buffer {
+    /// Reads a WAV file. 8, 16, 24 and 32-bit integer and 32, 64-bit float mono or stereo files are supported.
+    /// The sample rate of the file is preserved, see [`Buffer::sample_rate`]. The first loop of the `smpl`
+    /// chunk, if any, becomes the loop region of the buffer, see [`Buffer::loop_region`]. Cue points of the
+    /// `cue ` chunk become markers of the buffer, see [`Buffer::markers`].
+    pub fn read_wav<R: Read>(mut reader: R) -> anyhow::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut chunks = WavChunks::parse(&bytes)?;
+        let format = chunks.format;
+        if !(1..=2).contains(&format.channels) {
+            anyhow::bail!("Unsupported channel count {}", format.channels);
+        }
+        if format.sample_rate == 0 {
+            anyhow::bail!("Invalid sample rate");
+        }
+        let mut samples = format.decode(chunks.data)?;
+        // Drop incomplete frame at the end, if any.
+        samples.truncate(samples.len() / format.channels as usize * format.channels as usize);
+        let frames = samples.len() / format.channels as usize;
+        let loop_region = chunks
+            .loop_region
+            .filter(|region| !region.is_empty() && region.end <= frames);
+        chunks.markers.retain(|marker| marker.position < frames);
+        Ok(Buffer::new(samples, format.channels == 1)
+            .with_sample_rate(format.sample_rate)
+            .with_loop_region(loop_region)
+            .with_markers(chunks.markers))
+    }
+
+    /// Loads a WAV file from the given path, see [`Buffer::read_wav`].
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Self::read_wav(std::io::BufReader::new(file))
+    }
+
     pub fn save_to_file(&self, path: &str) -> std::io::Result<()> {
         match std::path::Path::new(path).extension() {
             Some(ext) if ext == "wav" => self.save_wav(path),
@@ -50,7 +226,7 @@ impl Buffer {
         let mut file = std::fs::File::create(path)?;
         let header = crate::mess::fileio::make_wav_header(
             self.channel_count() as u16,
-            SAMPLE_RATE,
+            self.sample_rate(),
             self.channel_duration_in_samples() as u32,
         );
         file.write_all(&header)?;